@@ -0,0 +1,191 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChecksumKind {
+    Xor,
+    Sum8,
+    Crc8,
+    Crc16Modbus,
+    Crc16Ccitt,
+    Crc16Xmodem,
+    Crc32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteOrder {
+    BigEndian,
+    LittleEndian,
+}
+
+impl ChecksumKind {
+    pub fn all() -> [ChecksumKind; 7] {
+        [
+            ChecksumKind::Xor,
+            ChecksumKind::Sum8,
+            ChecksumKind::Crc8,
+            ChecksumKind::Crc16Modbus,
+            ChecksumKind::Crc16Ccitt,
+            ChecksumKind::Crc16Xmodem,
+            ChecksumKind::Crc32,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChecksumKind::Xor => "XOR",
+            ChecksumKind::Sum8 => "SUM8",
+            ChecksumKind::Crc8 => "CRC-8",
+            ChecksumKind::Crc16Modbus => "CRC-16/MODBUS",
+            ChecksumKind::Crc16Ccitt => "CRC-16/CCITT-FALSE",
+            ChecksumKind::Crc16Xmodem => "CRC-16/XMODEM",
+            ChecksumKind::Crc32 => "CRC-32",
+        }
+    }
+
+    // the byte order the checksum is usually sent in
+    pub fn default_byte_order(&self) -> ByteOrder {
+        match self {
+            ChecksumKind::Crc16Modbus => ByteOrder::LittleEndian,
+            _ => ByteOrder::BigEndian,
+        }
+    }
+
+    pub fn compute(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ChecksumKind::Xor => vec![xor8(data)],
+            ChecksumKind::Sum8 => vec![sum8(data)],
+            ChecksumKind::Crc8 => vec![crc8(data)],
+            ChecksumKind::Crc16Modbus => crc16_modbus(data).to_be_bytes().to_vec(),
+            ChecksumKind::Crc16Ccitt => crc16_ccitt(data).to_be_bytes().to_vec(),
+            ChecksumKind::Crc16Xmodem => crc16_xmodem(data).to_be_bytes().to_vec(),
+            ChecksumKind::Crc32 => crc32(data).to_be_bytes().to_vec(),
+        }
+    }
+
+    pub fn compute_with_order(&self, data: &[u8], order: ByteOrder) -> Vec<u8> {
+        let mut bytes = self.compute(data);
+        if order == ByteOrder::LittleEndian {
+            bytes.reverse();
+        }
+        bytes
+    }
+}
+
+pub fn xor8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |x, b| x ^ b)
+}
+
+pub fn sum8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+// CRC-8/SMBUS: poly 0x07, init 0x00
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0;
+    for b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            if crc & 0x80 != 0 {
+                crc = (crc << 1) ^ 0x07;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+// MSB-first CRC-16 with poly 0x1021
+fn crc16_1021(data: &[u8], init: u16) -> u16 {
+    let mut crc = init;
+    for b in data {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+pub fn crc16_xmodem(data: &[u8]) -> u16 {
+    crc16_1021(data, 0)
+}
+
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    crc16_1021(data, 0xffff)
+}
+
+// reflected poly 0xa001 (0x8005), init 0xffff
+pub fn crc16_modbus(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    for b in data {
+        crc ^= *b as u16;
+        for _ in 0..8 {
+            if crc & 1 != 0 {
+                crc = (crc >> 1) ^ 0xa001;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    crc
+}
+
+// CRC-16/X-25, the HDLC frame check sequence: reflected poly 0x8408 (0x1021), init 0xffff, inverted
+pub fn crc16_x25(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    for b in data {
+        crc ^= *b as u16;
+        for _ in 0..8 {
+            if crc & 1 != 0 {
+                crc = (crc >> 1) ^ 0x8408;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    !crc
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffff_ffff;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            if crc & 1 != 0 {
+                crc = (crc >> 1) ^ 0xedb8_8320;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the catalogued check values, the CRC of "123456789"
+    const CHECK: &[u8] = b"123456789";
+
+    #[test]
+    fn check_values() {
+        assert_eq!(crc8(CHECK), 0xf4);
+        assert_eq!(crc16_xmodem(CHECK), 0x31c3);
+        assert_eq!(crc16_ccitt(CHECK), 0x29b1);
+        assert_eq!(crc16_modbus(CHECK), 0x4b37);
+        assert_eq!(crc16_x25(CHECK), 0x906e);
+        assert_eq!(crc32(CHECK), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn byte_order() {
+        assert_eq!(ChecksumKind::Crc16Modbus.compute(CHECK), vec![0x4b, 0x37]);
+        assert_eq!(ChecksumKind::Crc16Modbus.compute_with_order(CHECK, ByteOrder::LittleEndian), vec![0x37, 0x4b]);
+        assert_eq!(ChecksumKind::Xor.compute(&[0x01, 0x02, 0x04]), vec![0x07]);
+        assert_eq!(ChecksumKind::Sum8.compute(&[0xff, 0x02]), vec![0x01]);
+    }
+}
@@ -0,0 +1,93 @@
+use std::{fs, io};
+use std::path::PathBuf;
+
+//
+// Settings are stored as plain text files in the user config directory,
+// one record per line, fields separated by tabs.
+//
+
+pub fn config_path(file_name: &str) -> PathBuf {
+    glib::user_config_dir().join("serial-tool").join(file_name)
+}
+
+pub fn load_records(file_name: &str) -> Vec<Vec<String>> {
+    match fs::read_to_string(config_path(file_name)) {
+        Ok(text) => parse_records(&text),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn save_records(file_name: &str, records: &[Vec<String>]) -> io::Result<()> {
+    let path = config_path(file_name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format_records(records))
+}
+
+pub fn parse_records(text: &str) -> Vec<Vec<String>> {
+    text.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.split('\t').map(unescape).collect())
+        .collect()
+}
+
+// `<left> => <right>` as used by the trigger and highlight editors, split at the first arrow;
+// the spaces around it may be left out
+pub fn split_arrow(line: &str) -> Option<(&str, &str)> {
+    line.split_once("=>").map(|(left, right)| (left.trim(), right.trim_start()))
+}
+
+pub fn format_records(records: &[Vec<String>]) -> String {
+    let mut text = String::new();
+    for record in records {
+        let fields: Vec<String> = record.iter().map(|f| escape(f)).collect();
+        text.push_str(&fields.join("\t"));
+        text.push('\n');
+    }
+    text
+}
+
+fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut s = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            s.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => s.push('\t'),
+            Some('n') => s.push('\n'),
+            Some(other) => s.push(other),
+            None => s.push('\\'),
+        }
+    }
+    s
+}
+
+//
+// Single values, stored as `key<TAB>value` records in settings.txt
+//
+
+const SETTINGS_FILE: &str = "settings.txt";
+
+pub fn load_setting(key: &str) -> Option<String> {
+    load_records(SETTINGS_FILE)
+        .into_iter()
+        .find(|record| record.len() >= 2 && record[0] == key)
+        .map(|record| record[1].clone())
+}
+
+pub fn save_setting(key: &str, value: &str) -> io::Result<()> {
+    let mut records = load_records(SETTINGS_FILE);
+    match records.iter_mut().find(|record| record.len() >= 2 && record[0] == key) {
+        Some(record) => record[1] = value.to_string(),
+        None => records.push(vec![key.to_string(), value.to_string()]),
+    }
+    save_records(SETTINGS_FILE, &records)
+}
@@ -0,0 +1,107 @@
+use super::{payload_field, DecodedFrame, ProtocolDecoder};
+
+//
+// COBS: each frame is encoded without zero bytes and ends with a zero
+//
+
+#[derive(Debug, Default)]
+struct Cobs {
+    encoded: Vec<u8>,
+}
+
+pub fn new() -> Box<dyn ProtocolDecoder> {
+    Box::new(Cobs::default())
+}
+
+fn decode(encoded: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let code = encoded[i] as usize;
+        if i + code > encoded.len() {
+            return Err(format!("Code {:02X} at offset {} runs past the end of the frame", code, i));
+        }
+        decoded.extend_from_slice(&encoded[i + 1..i + code]);
+        i += code;
+        // a block shorter than 254 bytes stands for a zero, except at the end
+        if code < 0xff && i < encoded.len() {
+            decoded.push(0);
+        }
+    }
+    Ok(decoded)
+}
+
+impl ProtocolDecoder for Cobs {
+    fn feed(&mut self, data: &[u8]) -> Vec<DecodedFrame> {
+        let mut frames = Vec::new();
+        for b in data {
+            if *b != 0 {
+                self.encoded.push(*b);
+                continue;
+            }
+            if self.encoded.is_empty() {
+                continue;
+            }
+            let frame = match decode(&self.encoded) {
+                Ok(payload) => {
+                    let mut frame = DecodedFrame::new(format!("COBS, {} bytes", payload.len()));
+                    frame.field("Length", payload.len().to_string());
+                    payload_field(&mut frame, &payload);
+                    frame
+                }
+                Err(e) => {
+                    let mut frame = DecodedFrame::new(format!("COBS, {} bytes encoded", self.encoded.len()));
+                    payload_field(&mut frame, &self.encoded);
+                    frame.error(e);
+                    frame
+                }
+            };
+            self.encoded.clear();
+            frames.push(frame);
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(payload: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![0];
+        let mut code_index = 0;
+        for b in payload {
+            if *b != 0 {
+                encoded.push(*b);
+            }
+            if *b == 0 || encoded.len() - code_index == 0xff {
+                encoded[code_index] = (encoded.len() - code_index) as u8;
+                code_index = encoded.len();
+                encoded.push(0);
+            }
+        }
+        encoded[code_index] = (encoded.len() - code_index) as u8;
+        encoded.push(0);
+        encoded
+    }
+
+    #[test]
+    fn known_vectors() {
+        assert_eq!(decode(&[0x01, 0x01]).unwrap(), vec![0x00]);
+        assert_eq!(decode(&[0x03, 0x11, 0x22, 0x02, 0x33]).unwrap(), vec![0x11, 0x22, 0x00, 0x33]);
+        assert!(decode(&[0x05, 0x01]).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        // a block longer than 254 bytes is split without a zero
+        let payloads: [Vec<u8>; 3] = [vec![0, 0], (1..=255).collect(), vec![1, 2, 0, 3]];
+        for payload in payloads.iter() {
+            let encoded = encode(payload);
+            assert_eq!(decode(&encoded[..encoded.len() - 1]).as_ref(), Ok(payload));
+            let frames = new().feed(&encoded);
+            assert_eq!(frames.len(), 1);
+            assert_eq!(frames[0].fields[0].1, payload.len().to_string());
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use std::io;
+
+use crate::checksum::{ByteOrder, ChecksumKind};
+use crate::config;
+use crate::escape;
+use super::{payload_field, DecodedFrame, ProtocolDecoder};
+
+//
+// Frames described by the user, one element per line in the order they are sent:
+//   sync <hex>                      constant bytes a frame starts (or continues) with
+//   length <u8|u16be|u16le> [rest]  payload length, or with `rest` the bytes after the length field
+//   field <name> <type>             u8, i8, u16be, i16le, u32be, ... or a byte count for raw bytes
+//   payload [bytes]                 the length field's bytes, or a fixed number of bytes
+//   checksum <kind> [be|le]         over the bytes after the leading sync, e.g. CRC-16/MODBUS le
+//   end <hex>                       constant bytes a frame ends with
+//
+
+const FRAME_FORMAT_FILE: &str = "frame_format.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct IntType {
+    size: usize,
+    signed: bool,
+    order: ByteOrder,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FieldType {
+    Int(IntType),
+    Bytes(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Element {
+    Sync(Vec<u8>),
+    // the length counts the bytes after the length field instead of the payload
+    Length { int: IntType, rest: bool },
+    Field { name: String, field_type: FieldType },
+    Payload(Option<usize>),
+    Checksum { kind: ChecksumKind, order: ByteOrder },
+    End(Vec<u8>),
+}
+
+impl Element {
+    // size of the element, None for a payload sized by the length field
+    fn size(&self) -> Option<usize> {
+        match self {
+            Element::Sync(bytes) | Element::End(bytes) => Some(bytes.len()),
+            Element::Length { int, .. } => Some(int.size),
+            Element::Field { field_type: FieldType::Int(int), .. } => Some(int.size),
+            Element::Field { field_type: FieldType::Bytes(n), .. } => Some(*n),
+            Element::Payload(size) => *size,
+            Element::Checksum { kind, .. } => Some(kind.compute(&[]).len()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameFormat {
+    elements: Vec<Element>,
+}
+
+fn parse_int_type(s: &str) -> Option<IntType> {
+    let (signed, rest) = match s.as_bytes().first()? {
+        b'u' => (false, &s[1..]),
+        b'i' => (true, &s[1..]),
+        _ => return None,
+    };
+    let (bits, order) = if let Some(bits) = rest.strip_suffix("be") {
+        (bits, ByteOrder::BigEndian)
+    } else if let Some(bits) = rest.strip_suffix("le") {
+        (bits, ByteOrder::LittleEndian)
+    } else if rest == "8" {
+        (rest, ByteOrder::BigEndian)
+    } else {
+        return None;
+    };
+    match bits {
+        "8" | "16" | "32" => Some(IntType { size: bits.parse::<usize>().ok()? / 8, signed, order }),
+        _ => None,
+    }
+}
+
+fn parse_byte_order(s: Option<&str>, default: ByteOrder) -> Option<ByteOrder> {
+    match s {
+        None => Some(default),
+        Some("be") => Some(ByteOrder::BigEndian),
+        Some("le") => Some(ByteOrder::LittleEndian),
+        Some(_) => None,
+    }
+}
+
+pub fn parse_frame_format(text: &str) -> Result<FrameFormat, String> {
+    let mut format = FrameFormat::default();
+    for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let element = match fields[..] {
+            ["sync", ..] | ["end", ..] => {
+                let bytes = escape::parse_hex(&fields[1..].join(" ")).map_err(|e| format!("{}: {}", e, line))?;
+                if bytes.is_empty() {
+                    return Err(format!("Expected hex bytes: {}", line));
+                }
+                if fields[0] == "sync" { Element::Sync(bytes) } else { Element::End(bytes) }
+            }
+            ["length", int] | ["length", int, "rest"] => match parse_int_type(int) {
+                Some(int) if !int.signed && int.size <= 2 => Element::Length { int, rest: fields.len() == 3 },
+                _ => return Err(format!("Expected length u8, u16be or u16le: {}", line)),
+            },
+            ["field", name, field_type] => {
+                let field_type = match (parse_int_type(field_type), field_type.parse::<usize>()) {
+                    (Some(int), _) => FieldType::Int(int),
+                    (None, Ok(n)) if n > 0 => FieldType::Bytes(n),
+                    _ => return Err(format!("Unknown field type `{}`: {}", field_type, line)),
+                };
+                Element::Field { name: name.to_string(), field_type }
+            }
+            ["payload"] => Element::Payload(None),
+            ["payload", size] => match size.parse::<usize>() {
+                Ok(size) if size > 0 => Element::Payload(Some(size)),
+                _ => return Err(format!("Expected a payload size: {}", line)),
+            },
+            ["checksum", kind] | ["checksum", kind, _] => {
+                let kind = ChecksumKind::all()
+                    .iter()
+                    .find(|k| k.name().eq_ignore_ascii_case(kind))
+                    .copied()
+                    .ok_or_else(|| format!("Unknown checksum `{}`: {}", kind, line))?;
+                let order = parse_byte_order(fields.get(2).copied(), kind.default_byte_order())
+                    .ok_or_else(|| format!("Expected be or le: {}", line))?;
+                Element::Checksum { kind, order }
+            }
+            _ => return Err(format!("Unknown element: {}", line)),
+        };
+        format.elements.push(element);
+    }
+
+    let count = |f: fn(&Element) -> bool| format.elements.iter().filter(|e| f(e)).count();
+    if format.elements.is_empty() {
+        return Err(String::from("The frame format is empty"));
+    }
+    if count(|e| matches!(e, Element::Length { .. })) > 1 || count(|e| matches!(e, Element::Checksum { .. })) > 1 {
+        return Err(String::from("A frame has at most one length and one checksum"));
+    }
+    if count(|e| matches!(e, Element::Payload(None))) > 1 {
+        return Err(String::from("Only one payload can be sized by the length field"));
+    }
+    if let Some(i) = format.elements.iter().position(|e| matches!(e, Element::Payload(None))) {
+        if !format.elements[..i].iter().any(|e| matches!(e, Element::Length { .. })) {
+            return Err(String::from("A payload without a size needs a length field before it"));
+        }
+    }
+    Ok(format)
+}
+
+fn format_int_type(int: &IntType) -> String {
+    let order = match (int.size, int.order) {
+        (1, _) => "",
+        (_, ByteOrder::BigEndian) => "be",
+        (_, ByteOrder::LittleEndian) => "le",
+    };
+    format!("{}{}{}", if int.signed { "i" } else { "u" }, int.size * 8, order)
+}
+
+pub fn format_frame_format(format: &FrameFormat) -> String {
+    format.elements.iter()
+        .map(|element| match element {
+            Element::Sync(bytes) => format!("sync {}\n", escape::format_hex(bytes)),
+            Element::Length { int, rest } => format!("length {}{}\n", format_int_type(int), if *rest { " rest" } else { "" }),
+            Element::Field { name, field_type: FieldType::Int(int) } => format!("field {} {}\n", name, format_int_type(int)),
+            Element::Field { name, field_type: FieldType::Bytes(n) } => format!("field {} {}\n", name, n),
+            Element::Payload(None) => String::from("payload\n"),
+            Element::Payload(Some(size)) => format!("payload {}\n", size),
+            Element::Checksum { kind, order } => {
+                format!("checksum {} {}\n", kind.name(), if *order == ByteOrder::LittleEndian { "le" } else { "be" })
+            }
+            Element::End(bytes) => format!("end {}\n", escape::format_hex(bytes)),
+        })
+        .collect()
+}
+
+pub fn load_frame_format() -> Option<FrameFormat> {
+    let lines: Vec<String> = config::load_records(FRAME_FORMAT_FILE).into_iter().map(|record| record.join(" ")).collect();
+    parse_frame_format(&lines.join("\n")).ok()
+}
+
+pub fn save_frame_format(format: &FrameFormat) -> io::Result<()> {
+    let records: Vec<Vec<String>> = format_frame_format(format).lines().map(|line| vec![line.to_string()]).collect();
+    config::save_records(FRAME_FORMAT_FILE, &records)
+}
+
+#[derive(Debug)]
+struct Custom {
+    format: Option<FrameFormat>,
+    buf: Vec<u8>,
+    skipped: usize,
+    // the missing format is reported once
+    reported: bool,
+}
+
+pub fn new() -> Box<dyn ProtocolDecoder> {
+    Box::new(Custom { format: load_frame_format(), buf: Vec::new(), skipped: 0, reported: false })
+}
+
+fn read_int(int: &IntType, bytes: &[u8]) -> i64 {
+    let mut value = 0u64;
+    let mut be = bytes.to_vec();
+    if int.order == ByteOrder::LittleEndian {
+        be.reverse();
+    }
+    for b in be {
+        value = (value << 8) | b as u64;
+    }
+    let bits = int.size * 8;
+    if int.signed && value >> (bits - 1) != 0 {
+        value as i64 - (1i64 << bits)
+    } else {
+        value as i64
+    }
+}
+
+enum Framing {
+    // the frame and its length
+    Frame(DecodedFrame, usize),
+    // more bytes are needed
+    Incomplete,
+    // no frame starts at the beginning of the buffer
+    NoFrame,
+}
+
+fn next_frame(format: &FrameFormat, buf: &[u8]) -> Framing {
+    let mut frame = DecodedFrame::new(String::new());
+    let mut pos = 0;
+    let mut payload_len = None;
+    // the checksum does not cover a leading sync
+    let checksum_start = match format.elements.first() {
+        Some(Element::Sync(bytes)) => bytes.len(),
+        _ => 0,
+    };
+
+    for (i, element) in format.elements.iter().enumerate() {
+        let size = match element.size().or(payload_len) {
+            Some(size) => size,
+            None => return Framing::NoFrame,
+        };
+        let available = &buf[pos..buf.len().min(pos + size)];
+        // a sync that does not match is the end of the search, even before all of it arrived
+        if let Element::Sync(sync) = element {
+            if !sync.starts_with(available) {
+                return Framing::NoFrame;
+            }
+        }
+        if available.len() < size {
+            return Framing::Incomplete;
+        }
+        let bytes = available;
+
+        match element {
+            Element::Sync(_) => frame.field("Sync", escape::format_hex(bytes)),
+            Element::Length { int, rest } => {
+                let value = read_int(int, bytes) as usize;
+                frame.field("Length", value.to_string());
+                payload_len = if *rest {
+                    // the sizes of everything after the length field except the payload
+                    let fixed: usize = format.elements[i + 1..].iter().filter_map(|e| e.size()).sum();
+                    match value.checked_sub(fixed) {
+                        Some(len) => Some(len),
+                        None => return Framing::NoFrame,
+                    }
+                } else {
+                    Some(value)
+                };
+            }
+            Element::Field { name, field_type: FieldType::Int(int) } => frame.field(name, read_int(int, bytes).to_string()),
+            Element::Field { name, field_type: FieldType::Bytes(_) } => frame.field(name, escape::format_hex(bytes)),
+            Element::Payload(_) => payload_field(&mut frame, bytes),
+            Element::Checksum { kind, order } => {
+                frame.field("Checksum", escape::format_hex(bytes));
+                let calculated = kind.compute_with_order(&buf[checksum_start.min(pos)..pos], *order);
+                if bytes != calculated.as_slice() {
+                    frame.error(format!("Bad checksum, calculated {}", escape::format_hex(&calculated)));
+                }
+            }
+            Element::End(end) => {
+                frame.field("End", escape::format_hex(bytes));
+                if bytes != end.as_slice() {
+                    frame.error(format!("Expected end {}", escape::format_hex(end)));
+                }
+            }
+        }
+        pos += size;
+    }
+    frame.summary = format!("Frame, {} bytes", pos);
+    Framing::Frame(frame, pos)
+}
+
+impl ProtocolDecoder for Custom {
+    fn feed(&mut self, data: &[u8]) -> Vec<DecodedFrame> {
+        let format = match &self.format {
+            Some(format) => format,
+            None if self.reported => return Vec::new(),
+            None => {
+                self.reported = true;
+                let mut frame = DecodedFrame::new(String::from("No frame format"));
+                frame.error(String::from("Describe the frame in Options > Frame Format..."));
+                return vec![frame];
+            }
+        };
+        self.buf.extend_from_slice(data);
+        let mut frames = Vec::new();
+        while !self.buf.is_empty() {
+            match next_frame(format, &self.buf) {
+                Framing::Frame(frame, len) => {
+                    if self.skipped > 0 {
+                        let mut skipped = DecodedFrame::new(format!("{} bytes skipped", self.skipped));
+                        skipped.error(String::from("Not a valid frame"));
+                        frames.push(skipped);
+                        self.skipped = 0;
+                    }
+                    frames.push(frame);
+                    self.buf.drain(..len);
+                }
+                Framing::Incomplete => break,
+                // try again from the next byte
+                Framing::NoFrame => {
+                    self.buf.remove(0);
+                    self.skipped += 1;
+                }
+            }
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::{crc16_modbus, xor8};
+
+    fn decoder(text: &str) -> Custom {
+        Custom { format: Some(parse_frame_format(text).unwrap()), buf: Vec::new(), skipped: 0, reported: false }
+    }
+
+    fn field<'a>(frame: &'a DecodedFrame, name: &str) -> Option<&'a str> {
+        frame.fields.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse_frame_format("").is_err());
+        assert!(parse_frame_format("payload").is_err());
+        assert!(parse_frame_format("length i16be\npayload").is_err());
+        assert!(parse_frame_format("field x u24be").is_err());
+        assert!(parse_frame_format("checksum foo").is_err());
+        assert!(parse_frame_format("length u8\nlength u8\npayload").is_err());
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let text = "sync AA 55\nlength u16le rest\nfield cmd u8\nfield temp i16be\nfield id 3\npayload\nchecksum CRC-16/MODBUS le\nend 0D\n";
+        assert_eq!(format_frame_format(&parse_frame_format(text).unwrap()), text);
+    }
+
+    #[test]
+    fn sync_length_checksum_end() {
+        let mut decoder = decoder("sync AA 55\nlength u8\nfield cmd u8\nfield temp i16le\npayload\nchecksum crc-16/modbus\nend 0d");
+        let mut body = vec![2, 0x10, 0xfe, 0xff, b'h', b'i'];
+        body.extend_from_slice(&crc16_modbus(&body).to_le_bytes());
+        body.push(0x0d);
+        let mut data = vec![0x00, 0xaa, 0x00, 0xaa, 0x55];
+        data.extend_from_slice(&body);
+
+        let mut frames = decoder.feed(&data[..7]);
+        assert!(frames.is_empty());
+        frames.extend(decoder.feed(&data[7..]));
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].summary, "3 bytes skipped");
+        let frame = &frames[1];
+        assert!(frame.errors.is_empty(), "{:?}", frame.errors);
+        assert_eq!(field(frame, "cmd"), Some("16"));
+        assert_eq!(field(frame, "temp"), Some("-2"));
+        assert_eq!(field(frame, "Text"), Some("hi"));
+    }
+
+    #[test]
+    fn rest_length() {
+        // the length counts the payload and the checksum after it
+        let mut decoder = decoder("field id u8\nlength u16be rest\npayload\nchecksum xor");
+        let data = [0x01, 0x00, 0x03, 0x09, 0x08];
+        let mut frame = data.to_vec();
+        frame.push(xor8(&data));
+        let frames = decoder.feed(&frame);
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].errors.is_empty());
+        assert_eq!(field(&frames[0], "Payload"), Some("09 08"));
+        assert_eq!(frames[0].summary, "Frame, 6 bytes");
+
+        frame[5] ^= 0xff;
+        let frames = decoder.feed(&frame);
+        assert!(frames[0].errors[0].starts_with("Bad checksum"));
+    }
+
+    #[test]
+    fn missing_format_is_reported_once() {
+        let mut decoder = Custom { format: None, buf: Vec::new(), skipped: 0, reported: false };
+        assert_eq!(decoder.feed(&[1]).len(), 1);
+        assert!(decoder.feed(&[2]).is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::checksum::crc16_x25;
+use super::{payload_field, DecodedFrame, ProtocolDecoder};
+
+//
+// Asynchronous HDLC framing (RFC 1662): flag 0x7E, control escape 0x7D (next byte XOR 0x20)
+//   frame: address, control, information, FCS-16 (CRC-16/X-25, low byte first)
+//
+const FLAG: u8 = 0x7e;
+const CONTROL_ESCAPE: u8 = 0x7d;
+
+#[derive(Debug, Default)]
+struct Hdlc {
+    frame: Vec<u8>,
+    escaped: bool,
+}
+
+pub fn new() -> Box<dyn ProtocolDecoder> {
+    Box::new(Hdlc::default())
+}
+
+fn control_name(control: u8) -> String {
+    if control & 0x01 == 0 {
+        format!("I-frame, N(S)={} N(R)={}", (control >> 1) & 0x07, control >> 5)
+    } else if control & 0x03 == 0x01 {
+        let kind = match (control >> 2) & 0x03 {
+            0 => "RR",
+            1 => "REJ",
+            2 => "RNR",
+            _ => "SREJ",
+        };
+        format!("S-frame {}, N(R)={}", kind, control >> 5)
+    } else {
+        let kind = match control & 0xef {
+            0x03 => "UI",
+            0x2f => "SABM",
+            0x43 => "DISC",
+            0x63 => "UA",
+            0x0f => "DM",
+            0x87 => "FRMR",
+            _ => "U",
+        };
+        format!("U-frame {}", kind)
+    }
+}
+
+fn decode(bytes: &[u8]) -> DecodedFrame {
+    if bytes.len() < 4 {
+        let mut frame = DecodedFrame::new(format!("HDLC, {} bytes", bytes.len()));
+        payload_field(&mut frame, bytes);
+        frame.error(String::from("Frame too short"));
+        return frame;
+    }
+    let (body, fcs) = bytes.split_at(bytes.len() - 2);
+    let mut frame = DecodedFrame::new(format!("HDLC {}, {} bytes of information", control_name(body[1]), body.len() - 2));
+    frame.field("Address", format!("{:02X}", body[0]));
+    frame.field("Control", format!("{:02X} ({})", body[1], control_name(body[1])));
+    payload_field(&mut frame, &body[2..]);
+    let received = u16::from_le_bytes([fcs[0], fcs[1]]);
+    let calculated = crc16_x25(body);
+    frame.field("FCS", format!("{:04X}", received));
+    if received != calculated {
+        frame.error(format!("Bad FCS, calculated {:04X}", calculated));
+    }
+    frame
+}
+
+impl ProtocolDecoder for Hdlc {
+    fn feed(&mut self, data: &[u8]) -> Vec<DecodedFrame> {
+        let mut frames = Vec::new();
+        for b in data {
+            match *b {
+                // back to back frames may share a flag
+                FLAG => {
+                    if !self.frame.is_empty() {
+                        frames.push(decode(&self.frame));
+                        self.frame.clear();
+                    }
+                    self.escaped = false;
+                }
+                CONTROL_ESCAPE => self.escaped = true,
+                other if self.escaped => {
+                    self.escaped = false;
+                    self.frame.push(other ^ 0x20);
+                }
+                other => self.frame.push(other),
+            }
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(body: &[u8]) -> Vec<u8> {
+        let mut unescaped = body.to_vec();
+        unescaped.extend_from_slice(&crc16_x25(body).to_le_bytes());
+        let mut encoded = vec![FLAG];
+        for b in unescaped {
+            if b == FLAG || b == CONTROL_ESCAPE || b < 0x20 {
+                encoded.extend_from_slice(&[CONTROL_ESCAPE, b ^ 0x20]);
+            } else {
+                encoded.push(b);
+            }
+        }
+        encoded.push(FLAG);
+        encoded
+    }
+
+    #[test]
+    fn unescape_and_fcs() {
+        let encoded = encode(&[0xff, 0x03, 0xc0, 0x21, FLAG, CONTROL_ESCAPE, 0x01]);
+        let frames = new().feed(&encoded);
+        assert_eq!(frames.len(), 1);
+        let frame = &frames[0];
+        assert!(frame.errors.is_empty(), "{:?}", frame.errors);
+        assert_eq!(frame.fields[1].1, "03 (U-frame UI)");
+        assert_eq!(frame.fields[2].1, "C0 21 7E 7D 01");
+    }
+
+    #[test]
+    fn bad_fcs() {
+        let mut encoded = encode(&[0x01, 0x00, 0x41]);
+        let last = encoded.len() - 2;
+        encoded[last] ^= 0x01;
+        let frames = new().feed(&encoded);
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].errors[0].starts_with("Bad FCS"));
+        assert_eq!(control_name(0x00), "I-frame, N(S)=0 N(R)=0");
+        assert_eq!(control_name(0x21), "S-frame RR, N(R)=1");
+    }
+
+    #[test]
+    fn shared_flags() {
+        let mut encoded = encode(&[0x01, 0x03]);
+        encoded.extend_from_slice(&encode(&[0x02, 0x03])[1..]);
+        assert_eq!(new().feed(&encoded).len(), 2);
+    }
+}
@@ -0,0 +1,70 @@
+use super::{payload_field, DecodedFrame, ProtocolDecoder};
+
+//
+// A length field followed by that many bytes of payload
+//
+
+#[derive(Debug)]
+struct LengthPrefixed {
+    // bytes in the length field, 1 or 2
+    width: usize,
+    big_endian: bool,
+    buf: Vec<u8>,
+}
+
+pub fn new_u8() -> Box<dyn ProtocolDecoder> {
+    Box::new(LengthPrefixed { width: 1, big_endian: true, buf: Vec::new() })
+}
+
+pub fn new_u16_be() -> Box<dyn ProtocolDecoder> {
+    Box::new(LengthPrefixed { width: 2, big_endian: true, buf: Vec::new() })
+}
+
+pub fn new_u16_le() -> Box<dyn ProtocolDecoder> {
+    Box::new(LengthPrefixed { width: 2, big_endian: false, buf: Vec::new() })
+}
+
+impl LengthPrefixed {
+    fn payload_len(&self) -> Option<usize> {
+        match (self.width, self.big_endian) {
+            (1, _) => self.buf.first().map(|n| *n as usize),
+            (_, true) if self.buf.len() >= 2 => Some(u16::from_be_bytes([self.buf[0], self.buf[1]]) as usize),
+            (_, false) if self.buf.len() >= 2 => Some(u16::from_le_bytes([self.buf[0], self.buf[1]]) as usize),
+            _ => None,
+        }
+    }
+}
+
+impl ProtocolDecoder for LengthPrefixed {
+    fn feed(&mut self, data: &[u8]) -> Vec<DecodedFrame> {
+        self.buf.extend_from_slice(data);
+        let mut frames = Vec::new();
+        while let Some(len) = self.payload_len() {
+            if self.buf.len() < self.width + len {
+                break;
+            }
+            let payload: Vec<u8> = self.buf.drain(..self.width + len).skip(self.width).collect();
+            let mut frame = DecodedFrame::new(format!("Length {}", len));
+            frame.field("Length", len.to_string());
+            payload_field(&mut frame, &payload);
+            frames.push(frame);
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_orders() {
+        assert_eq!(new_u8().feed(&[2, b'h', b'i', 0])[..].len(), 2);
+        let frames = new_u16_be().feed(&[0, 2, 0xaa, 0xbb]);
+        assert_eq!(frames[0].fields[1].1, "AA BB");
+        let mut decoder = new_u16_le();
+        assert!(decoder.feed(&[3, 0, b'a']).is_empty());
+        let frames = decoder.feed(b"bc");
+        assert_eq!(frames[0].fields[2].1, "abc");
+    }
+}
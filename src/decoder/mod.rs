@@ -0,0 +1,108 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::escape;
+
+mod cobs;
+pub mod custom;
+mod hdlc;
+mod length_prefixed;
+mod modbus;
+mod nmea;
+mod slip;
+
+//
+// A protocol decoder turns the received byte stream into frames. It sees
+// every byte the port receives, in addition to the line based output.
+//
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecodedFrame {
+    pub summary: String,
+    // (name, value) in the order they appear in the frame
+    pub fields: Vec<(String, String)>,
+    pub errors: Vec<String>,
+}
+
+impl DecodedFrame {
+    pub fn new(summary: String) -> Self {
+        DecodedFrame { summary, ..Default::default() }
+    }
+
+    pub fn field(&mut self, name: &str, value: String) {
+        self.fields.push((name.to_string(), value));
+    }
+
+    pub fn error(&mut self, error: String) {
+        self.errors.push(error);
+    }
+}
+
+pub trait ProtocolDecoder: Send + fmt::Debug {
+    // the frames completed by `data`, partial frames are kept for the next call
+    fn feed(&mut self, data: &[u8]) -> Vec<DecodedFrame>;
+
+    // a binary decoder frames the received data in place of the line output
+    fn is_binary(&self) -> bool {
+        true
+    }
+}
+
+pub type NewDecoder = fn() -> Box<dyn ProtocolDecoder>;
+
+// (name, constructor) of the built-in decoders
+pub const DECODERS: [(&str, NewDecoder); 9] = [
+    ("SLIP", slip::new),
+    ("COBS", cobs::new),
+    ("HDLC", hdlc::new),
+    ("Modbus RTU", modbus::new),
+    ("NMEA 0183", nmea::new),
+    ("Length-Prefixed (u8)", length_prefixed::new_u8),
+    ("Length-Prefixed (u16 BE)", length_prefixed::new_u16_be),
+    ("Length-Prefixed (u16 LE)", length_prefixed::new_u16_le),
+    ("Custom Frame", custom::new),
+];
+
+pub fn create_decoder(name: &str) -> Option<Box<dyn ProtocolDecoder>> {
+    DECODERS.iter().find(|(n, _)| *n == name).map(|(_, new)| new())
+}
+
+//
+// The decoder selected for the port, fed by the read loop;
+// frames go straight to the UI.
+//
+#[derive(Debug)]
+pub struct DecoderSession {
+    decoder: Box<dyn ProtocolDecoder>,
+    frame_tx: glib::Sender<Vec<DecodedFrame>>,
+}
+
+pub type SharedDecoder = Arc<Mutex<Option<DecoderSession>>>;
+
+impl DecoderSession {
+    pub fn new(decoder: Box<dyn ProtocolDecoder>, frame_tx: glib::Sender<Vec<DecodedFrame>>) -> Self {
+        DecoderSession { decoder, frame_tx }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.decoder.is_binary()
+    }
+
+    // the number of frames completed, and of those with errors
+    pub fn feed(&mut self, data: &[u8]) -> (usize, usize) {
+        let frames = self.decoder.feed(data);
+        let counts = (frames.len(), frames.iter().filter(|f| !f.errors.is_empty()).count());
+        if !frames.is_empty() {
+            let _ = self.frame_tx.send(frames);
+        }
+        counts
+    }
+}
+
+// payload as hex, plus the printable text if there is any
+fn payload_field(frame: &mut DecodedFrame, payload: &[u8]) {
+    frame.field("Payload", escape::format_hex(payload));
+    if !payload.is_empty() && payload.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        frame.field("Text", String::from_utf8_lossy(payload).to_string());
+    }
+}
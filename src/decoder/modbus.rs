@@ -0,0 +1,178 @@
+use crate::escape;
+use crate::modbus::{check_crc, exception_name, FunctionCode};
+use crate::modbus::slave::parse_request;
+use super::{DecodedFrame, ProtocolDecoder};
+
+//
+// Passive Modbus RTU decoding, for listening in on a bus. There is no
+// timing information, so frames are found by their length and CRC.
+//
+
+// RTU frames are at most 256 bytes, after that we are out of sync
+const MAX_FRAME: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Request,
+    Response,
+    Exception,
+}
+
+#[derive(Debug, Default)]
+struct Modbus {
+    buf: Vec<u8>,
+    skipped: usize,
+}
+
+pub fn new() -> Box<dyn ProtocolDecoder> {
+    Box::new(Modbus::default())
+}
+
+fn read_u16(frame: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([frame[offset], frame[offset + 1]])
+}
+
+// the lengths the frame at the start of `buf` could have, None until enough of it arrived to tell
+fn candidates(buf: &[u8]) -> Vec<(Kind, Option<usize>)> {
+    if buf[1] & 0x80 != 0 {
+        return vec![(Kind::Exception, Some(5))];
+    }
+    match FunctionCode::from_code(buf[1]) {
+        Some(FunctionCode::WriteMultipleCoils) | Some(FunctionCode::WriteMultipleRegisters) => vec![
+            (Kind::Request, buf.get(6).map(|n| 9 + *n as usize)),
+            (Kind::Response, Some(8)),
+        ],
+        // a single write is echoed back as is
+        Some(FunctionCode::WriteSingleCoil) | Some(FunctionCode::WriteSingleRegister) => vec![(Kind::Request, Some(8))],
+        Some(_) => vec![
+            (Kind::Request, Some(8)),
+            (Kind::Response, buf.get(2).map(|n| 5 + *n as usize)),
+        ],
+        None => Vec::new(),
+    }
+}
+
+fn decode(kind: Kind, frame: &[u8]) -> DecodedFrame {
+    let slave_id = frame[0];
+    let function = FunctionCode::from_code(frame[1] & 0x7f);
+    let function_name = function.map_or_else(|| format!("{:02X}", frame[1] & 0x7f), |f| f.name().to_string());
+    let mut decoded = match kind {
+        Kind::Exception => DecodedFrame::new(format!("Slave {} exception, {}: {}", slave_id, function_name, exception_name(frame[2]))),
+        Kind::Request if !matches!(function, Some(f) if f.is_write()) => DecodedFrame::new(format!("Slave {} request, {}", slave_id, function_name)),
+        Kind::Request => DecodedFrame::new(format!("Slave {}, {}", slave_id, function_name)),
+        Kind::Response => DecodedFrame::new(format!("Slave {} response, {}", slave_id, function_name)),
+    };
+    decoded.field("Slave ID", slave_id.to_string());
+    decoded.field("Function", function_name);
+
+    match (kind, function) {
+        (Kind::Exception, _) => decoded.field("Exception", format!("{:02X} {}", frame[2], exception_name(frame[2]))),
+        (Kind::Request, _) => match parse_request(frame) {
+            Ok(request) => {
+                decoded.field("Address", request.address.to_string());
+                if request.function.is_write() {
+                    let values: Vec<String> = request.values.iter().map(|v| v.to_string()).collect();
+                    decoded.field("Values", values.join(", "));
+                } else {
+                    decoded.field("Count", request.count.to_string());
+                }
+            }
+            Err(e) => decoded.error(e.to_string()),
+        },
+        (Kind::Response, Some(f)) if f.is_write() => {
+            decoded.field("Address", read_u16(frame, 2).to_string());
+            decoded.field("Quantity", read_u16(frame, 4).to_string());
+        }
+        (Kind::Response, Some(f)) => {
+            let data = &frame[3..frame.len() - 2];
+            decoded.field("Byte Count", frame[2].to_string());
+            if f.is_bits() {
+                let bits: Vec<String> = data.iter().map(|b| format!("{:08b}", b.reverse_bits())).collect();
+                decoded.field("Bits", bits.join(" "));
+            } else {
+                let registers: Vec<String> = data.chunks(2)
+                    .map(|c| if c.len() == 2 { read_u16(c, 0).to_string() } else { format!("{:02X}", c[0]) })
+                    .collect();
+                decoded.field("Registers", registers.join(", "));
+            }
+        }
+        (Kind::Response, None) => {}
+    }
+    decoded.field("CRC", escape::format_hex(&frame[frame.len() - 2..]));
+    decoded
+}
+
+impl ProtocolDecoder for Modbus {
+    fn feed(&mut self, data: &[u8]) -> Vec<DecodedFrame> {
+        self.buf.extend_from_slice(data);
+        let mut frames = Vec::new();
+        while self.buf.len() >= 4 {
+            let candidates = candidates(&self.buf);
+            let found = candidates.iter()
+                .filter_map(|(kind, len)| len.map(|len| (*kind, len)))
+                .find(|(_, len)| self.buf.len() >= *len && check_crc(&self.buf[..*len]));
+            if let Some((kind, len)) = found {
+                if self.skipped > 0 {
+                    let mut skipped = DecodedFrame::new(format!("{} bytes skipped", self.skipped));
+                    skipped.error(String::from("Not a valid frame"));
+                    frames.push(skipped);
+                    self.skipped = 0;
+                }
+                frames.push(decode(kind, &self.buf[..len]));
+                self.buf.drain(..len);
+                continue;
+            }
+
+            // wait while any of the candidates may still complete
+            let waiting = candidates.iter().any(|(_, len)| !matches!(len, Some(len) if self.buf.len() >= *len));
+            if waiting && self.buf.len() < MAX_FRAME {
+                break;
+            }
+            // not the start of a frame, try again from the next byte
+            self.buf.remove(0);
+            self.skipped += 1;
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modbus::Request;
+
+    #[test]
+    fn resync_by_crc() {
+        let request = [0x01, 0x03, 0x00, 0x00, 0x00, 0x0a, 0xc5, 0xcd];
+        let mut data = vec![0x55, 0xaa, 0x01];
+        data.extend_from_slice(&request);
+        let mut decoder = new();
+        let mut frames = decoder.feed(&data[..6]);
+        frames.extend(decoder.feed(&data[6..]));
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].summary, "3 bytes skipped");
+        assert_eq!(frames[1].summary, "Slave 1 request, 03 Read Holding Registers");
+        assert_eq!(frames[1].fields[3], (String::from("Count"), String::from("10")));
+    }
+
+    #[test]
+    fn request_response_and_exception() {
+        let request = Request::new(1, FunctionCode::ReadHoldingRegisters, 0, 2, Vec::new()).unwrap();
+        let mut data = request.to_frame();
+        let response = [0x01, 0x03, 0x04, 0x00, 0x0a, 0x12, 0x34];
+        data.extend_from_slice(&response);
+        data.extend_from_slice(&crate::checksum::crc16_modbus(&response).to_le_bytes());
+        let exception = [0x01, 0x83, 0x02];
+        data.extend_from_slice(&exception);
+        data.extend_from_slice(&crate::checksum::crc16_modbus(&exception).to_le_bytes());
+
+        let frames = new().feed(&data);
+        let summaries: Vec<&str> = frames.iter().map(|f| f.summary.as_str()).collect();
+        assert_eq!(summaries, vec![
+            "Slave 1 request, 03 Read Holding Registers",
+            "Slave 1 response, 03 Read Holding Registers",
+            "Slave 1 exception, 03 Read Holding Registers: Illegal Data Address",
+        ]);
+        assert_eq!(frames[1].fields[3], (String::from("Registers"), String::from("10, 4660")));
+    }
+}
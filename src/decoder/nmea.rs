@@ -0,0 +1,73 @@
+use crate::nmea::{parse_sentence, NmeaError};
+use super::{DecodedFrame, ProtocolDecoder};
+
+// sentences are at most 82 characters, anything longer is not NMEA
+const MAX_LINE: usize = 256;
+
+#[derive(Debug, Default)]
+struct Nmea {
+    line: Vec<u8>,
+}
+
+pub fn new() -> Box<dyn ProtocolDecoder> {
+    Box::new(Nmea::default())
+}
+
+fn decode(line: &str) -> Option<DecodedFrame> {
+    match parse_sentence(line) {
+        Ok(sentence) => {
+            let mut frame = DecodedFrame::new(format!("{}{}, {} fields", sentence.talker, sentence.kind, sentence.fields.len()));
+            frame.field("Talker", sentence.talker.clone());
+            frame.field("Type", sentence.kind.clone());
+            for (i, value) in sentence.fields.iter().enumerate() {
+                frame.field(&(i + 1).to_string(), value.clone());
+            }
+            Some(frame)
+        }
+        Err(NmeaError::NotNmea) => None,
+        Err(e) => {
+            let mut frame = DecodedFrame::new(line.to_string());
+            frame.error(e.to_string());
+            Some(frame)
+        }
+    }
+}
+
+impl ProtocolDecoder for Nmea {
+    fn feed(&mut self, data: &[u8]) -> Vec<DecodedFrame> {
+        let mut frames = Vec::new();
+        for b in data {
+            if *b != b'\n' {
+                if self.line.len() < MAX_LINE {
+                    self.line.push(*b);
+                }
+                continue;
+            }
+            let line = String::from_utf8_lossy(&self.line).to_string();
+            self.line.clear();
+            frames.extend(decode(&line));
+        }
+        frames
+    }
+
+    // sentences are text, the lines still go to the output
+    fn is_binary(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let mut decoder = new();
+        assert!(decoder.feed(b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,").is_empty());
+        let frames = decoder.feed(b"0.9,545.4,M,46.9,M,,*47\r\nplain text\n$GPGGA,1*00\r\n");
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].summary, "GPGGA, 14 fields");
+        assert!(frames[0].errors.is_empty());
+        assert_eq!(frames[1].errors, vec![String::from("Bad checksum: *00, calculated *4B")]);
+    }
+}
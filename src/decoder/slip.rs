@@ -0,0 +1,94 @@
+use super::{payload_field, DecodedFrame, ProtocolDecoder};
+
+//
+// SLIP (RFC 1055): frames end with END, END and ESC in the payload are escaped
+//
+const END: u8 = 0xc0;
+const ESC: u8 = 0xdb;
+const ESC_END: u8 = 0xdc;
+const ESC_ESC: u8 = 0xdd;
+
+#[derive(Debug, Default)]
+struct Slip {
+    payload: Vec<u8>,
+    escaped: bool,
+    errors: Vec<String>,
+}
+
+pub fn new() -> Box<dyn ProtocolDecoder> {
+    Box::new(Slip::default())
+}
+
+impl ProtocolDecoder for Slip {
+    fn feed(&mut self, data: &[u8]) -> Vec<DecodedFrame> {
+        let mut frames = Vec::new();
+        for b in data {
+            if self.escaped {
+                self.escaped = false;
+                match *b {
+                    ESC_END => self.payload.push(END),
+                    ESC_ESC => self.payload.push(ESC),
+                    other => {
+                        self.errors.push(format!("Invalid escape {:02X}", other));
+                        self.payload.push(other);
+                    }
+                }
+                continue;
+            }
+            match *b {
+                // an END before the first byte only flushes line noise
+                END if self.payload.is_empty() && self.errors.is_empty() => {}
+                END => {
+                    let mut frame = DecodedFrame::new(format!("SLIP, {} bytes", self.payload.len()));
+                    frame.field("Length", self.payload.len().to_string());
+                    payload_field(&mut frame, &self.payload);
+                    frame.errors = std::mem::take(&mut self.errors);
+                    self.payload.clear();
+                    frames.push(frame);
+                }
+                ESC => self.escaped = true,
+                other => self.payload.push(other),
+            }
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(payload: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![END];
+        for b in payload {
+            match *b {
+                END => encoded.extend_from_slice(&[ESC, ESC_END]),
+                ESC => encoded.extend_from_slice(&[ESC, ESC_ESC]),
+                other => encoded.push(other),
+            }
+        }
+        encoded.push(END);
+        encoded
+    }
+
+    #[test]
+    fn round_trip() {
+        let payload = [0x01, END, 0x02, ESC, 0x03];
+        let encoded = encode(&payload);
+        let mut decoder = new();
+        // split inside the escape sequence
+        let mut frames = decoder.feed(&encoded[..3]);
+        assert!(frames.is_empty());
+        frames.extend(decoder.feed(&encoded[3..]));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].fields[1], (String::from("Payload"), String::from("01 C0 02 DB 03")));
+        assert!(frames[0].errors.is_empty());
+    }
+
+    #[test]
+    fn invalid_escape() {
+        let frames = new().feed(&[END, 0x01, ESC, 0x02, END]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].errors, vec![String::from("Invalid escape 02")]);
+    }
+}
@@ -0,0 +1,143 @@
+//
+// C-style escape sequences: \r \n \t \0 \a \b \e \f \v \\ \" \' \xHH \u{HHHH}
+//
+
+pub fn unescape(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        let escaped = match chars.next() {
+            Some(e) => e,
+            None => return Err(String::from("Trailing backslash")),
+        };
+        match escaped {
+            'r' => bytes.push(b'\r'),
+            'n' => bytes.push(b'\n'),
+            't' => bytes.push(b'\t'),
+            '0' => bytes.push(0x00),
+            'a' => bytes.push(0x07),
+            'b' => bytes.push(0x08),
+            'e' => bytes.push(0x1b),
+            'f' => bytes.push(0x0c),
+            'v' => bytes.push(0x0b),
+            '\\' | '"' | '\'' => bytes.push(escaped as u8),
+            'x' => {
+                let hex: String = (0..2).filter_map(|_| chars.next()).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(b) if hex.len() == 2 => bytes.push(b),
+                    _ => return Err(format!("Invalid escape: \\x{}", hex)),
+                }
+            }
+            'u' => {
+                if chars.next() != Some('{') {
+                    return Err(String::from("Invalid escape: \\u must be followed by {...}"));
+                }
+                let mut hex = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(h) => hex.push(h),
+                        None => return Err(format!("Invalid escape: \\u{{{}", hex)),
+                    }
+                }
+                let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                match c {
+                    Some(c) => {
+                        let mut buf = [0u8; 4];
+                        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    }
+                    None => return Err(format!("Invalid escape: \\u{{{}}}", hex)),
+                }
+            }
+            _ => return Err(format!("Invalid escape: \\{}", escaped)),
+        }
+    }
+    Ok(bytes)
+}
+
+// control characters that can be sent on their own: (name, byte, Ctrl+Shift shortcut)
+pub const CONTROL_CHARACTERS: [(&str, u8, Option<char>); 10] = [
+    ("Ctrl+A (SOH)", 0x01, Some('a')),
+    ("Ctrl+C (ETX)", 0x03, Some('c')),
+    ("Ctrl+D (EOT)", 0x04, Some('d')),
+    ("Ctrl+X (CAN)", 0x18, Some('x')),
+    ("Ctrl+Z (SUB)", 0x1a, Some('z')),
+    ("Ctrl+] (GS)", 0x1d, None),
+    ("Ctrl+\\ (FS)", 0x1c, None),
+    ("ESC", 0x1b, None),
+    ("NUL", 0x00, None),
+    ("DEL", 0x7f, None),
+];
+
+// caret notation (^C) for control characters other than line breaks and tabs
+pub fn caret_notation(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\r' | '\n' | '\t' => s.push(c),
+            '\x00'..='\x1f' => {
+                s.push('^');
+                s.push((c as u8 + 0x40) as char);
+            }
+            '\x7f' => s.push_str("^?"),
+            _ => s.push(c),
+        }
+    }
+    s
+}
+
+// hex payload such as `01 03 00 0A`, `01030A` or `0x01,0x03`
+pub fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    let mut digits = String::new();
+    for token in s.split(|c: char| c.is_whitespace() || c == ',' || c == ':') {
+        let token = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(token);
+        if token.len() % 2 != 0 {
+            digits.push('0');
+        }
+        digits.push_str(token);
+    }
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid hex: {}", s));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|e| e.to_string()))
+        .collect()
+}
+
+// `01 03 00 0A`
+pub fn format_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_sequences() {
+        assert_eq!(unescape(r"AT\r\n").unwrap(), b"AT\r\n");
+        assert_eq!(unescape(r"\x01\x7F\\\0").unwrap(), vec![0x01, 0x7f, b'\\', 0x00]);
+        assert_eq!(unescape(r"\u{e9}").unwrap(), "é".as_bytes());
+        assert!(unescape(r"\q").is_err());
+        assert!(unescape(r"\x4").is_err());
+        assert!(unescape("abc\\").is_err());
+    }
+
+    #[test]
+    fn hex() {
+        let bytes = vec![0x01, 0x03, 0x00, 0x0a];
+        assert_eq!(parse_hex("01 03 00 0A").unwrap(), bytes);
+        assert_eq!(parse_hex("0103000a").unwrap(), bytes);
+        assert_eq!(parse_hex("0x01,0x03,0x00,0x0A").unwrap(), bytes);
+        assert_eq!(parse_hex("1 3 0 a").unwrap(), bytes);
+        assert!(parse_hex("hello").is_err());
+        assert_eq!(format_hex(&bytes), "01 03 00 0A");
+    }
+}
@@ -0,0 +1,265 @@
+use std::path::Path;
+
+use crate::port::DataDirection;
+use crate::transcript;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Text,
+    Html,
+    Csv,
+}
+
+impl ExportFormat {
+    // chosen by the file extension, plain text for anything unknown
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "html" | "htm" => ExportFormat::Html,
+            "csv" => ExportFormat::Csv,
+            _ => ExportFormat::Text,
+        }
+    }
+}
+
+// a run of output text sharing the same highlight style
+#[derive(Debug, Clone, Default)]
+pub struct StyledSpan {
+    pub text: String,
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+const ESC: char = '\x1b';
+
+// the standard and bright ANSI colors, as xterm shows them
+const ANSI_COLORS: [&str; 16] = [
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+];
+
+//
+// Splits `text` at the escape sequences a device may send:
+//   CSI (`ESC [ <parameters> <final byte>`) yields Err with its parameters if it is
+//   SGR (final byte `m`), other CSI and two byte `ESC <char>` sequences are dropped
+//
+fn split_escapes(text: &str) -> Vec<Result<&str, &str>> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(ESC) {
+        if start > 0 {
+            parts.push(Ok(&rest[..start]));
+        }
+        let sequence = &rest[start + 1..];
+        rest = match sequence.strip_prefix('[') {
+            Some(csi) => match csi.find(|c| ('\x40'..='\x7e').contains(&c)) {
+                Some(end) => {
+                    if csi[end..].starts_with('m') {
+                        parts.push(Err(&csi[..end]));
+                    }
+                    &csi[end + 1..]
+                }
+                // unterminated, the rest of the text belongs to it
+                None => "",
+            },
+            None => {
+                let mut chars = sequence.chars();
+                chars.next();
+                chars.as_str()
+            }
+        };
+    }
+    if !rest.is_empty() {
+        parts.push(Ok(rest));
+    }
+    parts
+}
+
+// `text` without ANSI escape sequences
+pub fn strip_ansi(text: &str) -> String {
+    split_escapes(text).into_iter().filter_map(Result::ok).collect()
+}
+
+fn ansi_256_color(n: u16) -> Option<String> {
+    match n {
+        0..=15 => Some(String::from(ANSI_COLORS[n as usize])),
+        16..=231 => {
+            let level = |v: u16| if v == 0 { 0 } else { 55 + 40 * v };
+            let n = n - 16;
+            Some(format!("#{:02x}{:02x}{:02x}", level(n / 36), level(n / 6 % 6), level(n % 6)))
+        }
+        232..=255 => {
+            let gray = 8 + 10 * (n - 232);
+            Some(format!("#{:02x}{:02x}{:02x}", gray, gray, gray))
+        }
+        _ => None,
+    }
+}
+
+// the color of a `38;...` or `48;...` parameter list, consuming its arguments
+fn extended_color(params: &mut impl Iterator<Item = u16>) -> Option<String> {
+    match params.next()? {
+        5 => ansi_256_color(params.next()?),
+        2 => {
+            let (r, g, b) = (params.next()?, params.next()?, params.next()?);
+            Some(format!("#{:02x}{:02x}{:02x}", r.min(255), g.min(255), b.min(255)))
+        }
+        _ => None,
+    }
+}
+
+// the text attributes set by SGR sequences so far
+#[derive(Debug, Clone, Default, PartialEq)]
+struct AnsiStyle {
+    foreground: Option<String>,
+    background: Option<String>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl AnsiStyle {
+    fn apply(&mut self, sgr: &str) {
+        // an empty parameter counts as 0, so `ESC[m` resets
+        let mut params = sgr.split(';').map(|p| p.parse::<u16>().unwrap_or(0));
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = AnsiStyle::default(),
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => self.foreground = Some(String::from(ANSI_COLORS[param as usize - 30])),
+                90..=97 => self.foreground = Some(String::from(ANSI_COLORS[param as usize - 90 + 8])),
+                40..=47 => self.background = Some(String::from(ANSI_COLORS[param as usize - 40])),
+                100..=107 => self.background = Some(String::from(ANSI_COLORS[param as usize - 100 + 8])),
+                38 => self.foreground = extended_color(&mut params),
+                48 => self.background = extended_color(&mut params),
+                39 => self.foreground = None,
+                49 => self.background = None,
+                _ => {}
+            }
+        }
+    }
+}
+
+// `spans` split further where SGR sequences change the style, the sequences themselves removed;
+// colors sent by the device win over the highlight rules
+fn apply_ansi(spans: &[StyledSpan]) -> Vec<StyledSpan> {
+    let mut styled = Vec::new();
+    let mut ansi = AnsiStyle::default();
+    for span in spans {
+        for part in split_escapes(&span.text) {
+            match part {
+                Ok(text) => styled.push(StyledSpan {
+                    text: text.to_string(),
+                    foreground: ansi.foreground.clone().or_else(|| span.foreground.clone()),
+                    background: ansi.background.clone().or_else(|| span.background.clone()),
+                    bold: span.bold || ansi.bold,
+                    italic: span.italic || ansi.italic,
+                    underline: span.underline || ansi.underline,
+                }),
+                Err(sgr) => ansi.apply(sgr),
+            }
+        }
+    }
+    styled
+}
+
+pub fn to_html(spans: &[StyledSpan]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Serial Tool Output</title>\n</head>\n<body>\n<pre>"
+    );
+    for span in apply_ansi(spans).iter() {
+        let mut styles = Vec::new();
+        if let Some(color) = &span.foreground {
+            styles.push(format!("color: {}", color));
+        }
+        if let Some(color) = &span.background {
+            styles.push(format!("background-color: {}", color));
+        }
+        if span.bold {
+            styles.push(String::from("font-weight: bold"));
+        }
+        if span.italic {
+            styles.push(String::from("font-style: italic"));
+        }
+        if span.underline {
+            styles.push(String::from("text-decoration: underline"));
+        }
+
+        if styles.is_empty() {
+            html.push_str(&escape_html(&span.text));
+        } else {
+            html.push_str(&format!("<span style=\"{}\">{}</span>", escape_html(&styles.join("; ")), escape_html(&span.text)));
+        }
+    }
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn to_csv(text: &str) -> String {
+    let mut csv = String::from("timestamp,direction,data\n");
+    for line in text.lines() {
+        let (timestamp, direction, data) = transcript::parse_line(line);
+        let direction = match direction.unwrap_or(DataDirection::Rx) {
+            DataDirection::Rx => "RX",
+            DataDirection::Tx => "TX",
+        };
+        let data = strip_ansi(data);
+        let fields = [timestamp.unwrap_or(""), direction, &data];
+        let fields: Vec<String> = fields.iter().map(|f| escape_csv(f)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+pub fn escape_csv(field: &str) -> String {
+    let field = field.trim_end_matches('\r');
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip() {
+        assert_eq!(strip_ansi("\x1b[1;31mERROR\x1b[0m done\x1b[2K\x1b7"), "ERROR done");
+        assert_eq!(strip_ansi("cut \x1b[3"), "cut ");
+    }
+
+    #[test]
+    fn sgr_to_spans() {
+        let span = StyledSpan { text: String::from("a\x1b[1;32mb\x1b[38;5;196mc\x1b[mdx"), ..Default::default() };
+        let highlighted = StyledSpan { text: String::from("x"), foreground: Some(String::from("blue")), ..Default::default() };
+        let spans = apply_ansi(&[span, highlighted]);
+        let texts: Vec<&str> = spans.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, vec!["a", "b", "c", "dx", "x"]);
+        assert_eq!((spans[1].foreground.as_deref(), spans[1].bold), (Some("#00cd00"), true));
+        assert_eq!((spans[2].foreground.as_deref(), spans[2].bold), (Some("#ff0000"), true));
+        assert_eq!((spans[3].foreground.as_deref(), spans[3].bold), (None, false));
+        assert_eq!(spans[4].foreground.as_deref(), Some("blue"));
+        assert!(!to_html(&spans).contains(ESC));
+    }
+}
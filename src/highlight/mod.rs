@@ -0,0 +1,128 @@
+use std::io;
+
+use regex::Regex;
+
+use crate::config;
+
+const HIGHLIGHT_RULES_FILE: &str = "highlight_rules.txt";
+
+#[derive(Debug, Clone)]
+pub struct HighlightRule {
+    pub regex: Regex,
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    // style only the matched text instead of the whole line
+    pub match_only: bool,
+}
+
+impl HighlightRule {
+    pub fn new(pattern: &str, style: &str) -> Result<Self, String> {
+        let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+        let mut rule = HighlightRule {
+            regex,
+            foreground: None,
+            background: None,
+            bold: false,
+            italic: false,
+            underline: false,
+            match_only: false,
+        };
+
+        for token in style.split_whitespace() {
+            if let Some(color) = token.strip_prefix("bg=") {
+                rule.background = Some(color.to_string());
+                continue;
+            }
+            match token {
+                "bold" => rule.bold = true,
+                "italic" => rule.italic = true,
+                "underline" => rule.underline = true,
+                "match" => rule.match_only = true,
+                _ if rule.foreground.is_none() => rule.foreground = Some(token.to_string()),
+                _ => return Err(format!("Unknown style: {}", token)),
+            }
+        }
+        Ok(rule)
+    }
+
+    pub fn style(&self) -> String {
+        let mut tokens = Vec::new();
+        if let Some(color) = &self.foreground {
+            tokens.push(color.clone());
+        }
+        if let Some(color) = &self.background {
+            tokens.push(format!("bg={}", color));
+        }
+        for (flag, name) in [(self.bold, "bold"), (self.italic, "italic"), (self.underline, "underline"), (self.match_only, "match")] {
+            if flag {
+                tokens.push(name.to_string());
+            }
+        }
+        tokens.join(" ")
+    }
+}
+
+pub fn load_rules() -> Vec<HighlightRule> {
+    config::load_records(HIGHLIGHT_RULES_FILE)
+        .into_iter()
+        .filter(|record| record.len() >= 2)
+        .filter_map(|record| HighlightRule::new(&record[0], &record[1]).ok())
+        .collect()
+}
+
+pub fn save_rules(rules: &[HighlightRule]) -> io::Result<()> {
+    let records: Vec<Vec<String>> = rules.iter()
+        .map(|r| vec![r.regex.as_str().to_string(), r.style()])
+        .collect();
+    config::save_records(HIGHLIGHT_RULES_FILE, &records)
+}
+
+pub fn parse_rules(text: &str) -> Result<Vec<HighlightRule>, String> {
+    //
+    // one rule per line: <regex> => <color> [bg=<color>] [bold] [italic] [underline] [match]
+    //
+    let mut rules = Vec::new();
+    for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let (pattern, style) = match config::split_arrow(line) {
+            Some(split) => split,
+            None => return Err(format!("Missing `=>`: {}", line)),
+        };
+        let rule = HighlightRule::new(pattern, style).map_err(|e| format!("{}\n\n{}", e, line))?;
+        rules.push(rule);
+    }
+    Ok(rules)
+}
+
+pub fn format_rules(rules: &[HighlightRule]) -> String {
+    rules.iter()
+        .map(|r| format!("{} => {}\n", r.regex.as_str(), r.style()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format() {
+        let text = "# comment\nERROR => red bold\n^\\[(\\d+)\\] => #808080 bg=yellow italic underline match\n";
+        let rules = parse_rules(text).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].foreground.as_deref(), Some("red"));
+        assert!(rules[0].bold && !rules[0].match_only);
+        assert_eq!(rules[1].regex.as_str(), "^\\[(\\d+)\\]");
+        assert_eq!(rules[1].background.as_deref(), Some("yellow"));
+        assert!(rules[1].italic && rules[1].underline && rules[1].match_only);
+        assert_eq!(format_rules(&rules), "ERROR => red bold\n^\\[(\\d+)\\] => #808080 bg=yellow italic underline match\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_rules("ERROR red").unwrap_err().starts_with("Missing `=>`"));
+        assert!(parse_rules("ERROR => red blue").unwrap_err().starts_with("Unknown style: blue"));
+        assert!(parse_rules("( => red").is_err());
+    }
+}
@@ -1,10 +1,28 @@
 #![windows_subsystem = "windows"]
 
+pub mod checksum;
+pub mod config;
+pub mod decoder;
+pub mod escape;
+pub mod export;
+pub mod highlight;
 pub mod main_window;
 pub mod my_tools;
+pub mod modbus;
 pub mod model;
+pub mod nmea;
+pub mod plot;
 pub mod port;
+pub mod script;
+pub mod scrollback;
+pub mod search;
+pub mod sequence;
+pub mod stats;
+pub mod transcript;
+pub mod trigger;
 pub mod usb;
+pub mod xmodem;
+pub mod zmodem;
 
 use main_window::MainWindow;
 use gtk::prelude::*;
@@ -13,6 +31,13 @@ use gtk::gdk;
 
 #[tokio::main]
 async fn main() {
+    // headless mode for running test scripts, e.g. from CI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--script") {
+        attach_parent_console();
+        std::process::exit(script::run_headless(&args));
+    }
+
     let app = gtk::Application::builder()
         .application_id("site.riddleling.app.serial-tool")
         .build();
@@ -25,6 +50,25 @@ async fn main() {
     app.run();
 }
 
+// a GUI subsystem program starts without a console on Windows, so the headless
+// output would go nowhere; borrow the console of the shell that started it
+#[cfg(windows)]
+fn attach_parent_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // fails when started without a console (e.g. redirected by CI), the handles are usable then
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_parent_console() {}
+
 fn build_ui(app: &gtk::Application) {
     let win = MainWindow::new(app);
     win.set_title("Serial Tool");
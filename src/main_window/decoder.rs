@@ -0,0 +1,103 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use crate::decoder::{self, custom, DecodedFrame, DecoderSession};
+use crate::model;
+use crate::my_tools::*;
+
+use super::imp::MainWindow;
+
+// decoded frames kept in the list, the oldest are removed first
+const MAX_DECODED_FRAMES: i32 = 5000;
+
+impl MainWindow {
+    pub(super) fn on_decoder_changed(&self) {
+        let decoded_scrolled_window = self.decoded_scrolled_window.get().unwrap();
+        let name = self.decoder_combo_box.get().unwrap().active_text();
+        let decoder = match name.as_deref().and_then(decoder::create_decoder) {
+            Some(decoder) => decoder,
+            None => {
+                self.decoder.lock().unwrap().take();
+                decoded_scrolled_window.hide();
+                return;
+            }
+        };
+
+        // the previous session's channel closes when its sender is dropped
+        let obj = MainWindow::instance(self);
+        let (frame_tx, frame_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        frame_rx.attach(
+            None,
+            clone!(@weak obj => @default-return glib::Continue(false),
+                move |frames| {
+                    let priv_ = MainWindow::from_instance(&obj);
+                    priv_.on_decoded_frames(frames);
+                    glib::Continue(true)
+                }
+            )
+        );
+        self.decoder.lock().unwrap().replace(DecoderSession::new(decoder, frame_tx));
+        self.decoded_model.get().unwrap().clear();
+        self.decoded_view.get().unwrap().show();
+        decoded_scrolled_window.show();
+    }
+
+    fn on_decoded_frames(&self, frames: Vec<DecodedFrame>) {
+        let decoded_model = self.decoded_model.get().unwrap();
+        let timestamp = current_timestamp_string();
+        let mut last = None;
+        for frame in frames {
+            let summary = if frame.errors.is_empty() { frame.summary } else { format!("⚠ {}", frame.summary) };
+            let parent = model::add_decoded_frame_item(decoded_model, None, &timestamp, &summary);
+            for (name, value) in frame.fields.iter() {
+                model::add_decoded_frame_item(decoded_model, Some(&parent), "", &format!("{}: {}", name, value));
+            }
+            for error in frame.errors.iter() {
+                model::add_decoded_frame_item(decoded_model, Some(&parent), "", &format!("⚠ {}", error));
+            }
+            last = Some(parent);
+        }
+        while decoded_model.iter_n_children(None) > MAX_DECODED_FRAMES {
+            match decoded_model.iter_first() {
+                Some(first) => decoded_model.remove(&first),
+                None => break,
+            };
+        }
+        let is_auto_scroll = self.auto_scroll_check_button.get().unwrap().is_active();
+        if let Some(path) = last.filter(|_| is_auto_scroll).and_then(|iter| decoded_model.path(&iter)) {
+            self.decoded_view.get().unwrap().scroll_to_cell(Some(&path), None::<&gtk::TreeViewColumn>, false, 0.0, 0.0);
+        }
+    }
+
+    pub(super) fn on_frame_format_activate(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let text = custom::load_frame_format().map(|format| custom::format_frame_format(&format)).unwrap_or_default();
+            let hint = "One element per line, in the order they are sent: sync <hex>, length <u8|u16be|u16le> [rest],\n\
+                        field <name> <u8|i16le|u32be|...|bytes>, payload [bytes], checksum <kind> [be|le], end <hex>\n\
+                        e.g. sync AA 55, length u8, field cmd u8, payload, checksum CRC-16/MODBUS le";
+            let text = match show_text_edit_dialog(&obj, "Frame Format", hint, &text).await {
+                Some(text) => text,
+                None => return,
+            };
+            let format = match custom::parse_frame_format(&text) {
+                Ok(format) => format,
+                Err(e) => {
+                    show_alert_dialog(&obj, e).await;
+                    return;
+                }
+            };
+            if let Err(e) = custom::save_frame_format(&format) {
+                show_alert_dialog(&obj, format!("Failed to save the frame format: {}", e)).await;
+                return;
+            }
+            // the custom decoder reads the format when it is created
+            if priv_.decoder_combo_box.get().unwrap().active_text().as_deref() == Some("Custom Frame") {
+                priv_.on_decoder_changed();
+            }
+        }));
+    }
+}
@@ -0,0 +1,67 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use crate::export::{self, ExportFormat, StyledSpan};
+use crate::my_tools::*;
+
+use super::imp::MainWindow;
+
+impl MainWindow {
+    pub(super) fn on_save_output_activate(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let title = "Save Output As (.txt, .html or .csv)";
+            let files = show_file_chooser_dialog(&obj, title, gtk::FileChooserAction::Save, false).await;
+            let path = match files.first() {
+                Some(path) => path,
+                None => return,
+            };
+            let buffer = match priv_.read_text_view.get().unwrap().buffer() {
+                Some(buffer) => buffer,
+                None => return,
+            };
+            let (start, end) = buffer.bounds();
+            let text = buffer.text(&start, &end, true).map(|t| t.to_string()).unwrap_or_default();
+            let contents = match ExportFormat::from_path(path) {
+                ExportFormat::Text => export::strip_ansi(&text),
+                ExportFormat::Html => export::to_html(&priv_.output_spans(&buffer)),
+                ExportFormat::Csv => export::to_csv(&text),
+            };
+            if let Err(e) = std::fs::write(path, contents) {
+                show_alert_dialog(&obj, format!("Failed to save output: {}", e)).await;
+            }
+        }));
+    }
+
+    // the output split into runs of equal highlight style
+    fn output_spans(&self, buffer: &gtk::TextBuffer) -> Vec<StyledSpan> {
+        let rules = self.highlight_rules.borrow();
+        let tags = self.highlight_tags.borrow();
+        let mut spans = Vec::new();
+        let mut iter = buffer.start_iter();
+        while !iter.is_end() {
+            let mut next = iter.clone();
+            next.forward_to_tag_toggle(None::<&gtk::TextTag>);
+            let text = buffer.text(&iter, &next, true).map(|t| t.to_string()).unwrap_or_default();
+            let mut span = StyledSpan { text, ..Default::default() };
+            // later rules take priority, the same as their tags in the view
+            for (rule, _) in rules.iter().zip(tags.iter()).filter(|(_, tag)| iter.has_tag(*tag)) {
+                if rule.foreground.is_some() {
+                    span.foreground = rule.foreground.clone();
+                }
+                if rule.background.is_some() {
+                    span.background = rule.background.clone();
+                }
+                span.bold |= rule.bold;
+                span.italic |= rule.italic;
+                span.underline |= rule.underline;
+            }
+            spans.push(span);
+            iter = next;
+        }
+        spans
+    }
+}
@@ -0,0 +1,157 @@
+use glib::clone;
+use gtk::glib;
+use gtk::pango;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use crate::highlight::{self, HighlightRule};
+use crate::my_tools::*;
+use crate::search;
+
+use super::imp::MainWindow;
+
+impl MainWindow {
+    pub(super) fn set_highlight_rules(&self, rules: Vec<HighlightRule>) {
+        let text_view = self.read_text_view.get().unwrap();
+        let buffer = match text_view.buffer() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        let tag_table = match buffer.tag_table() {
+            Some(tag_table) => tag_table,
+            None => return,
+        };
+
+        for tag in self.highlight_tags.borrow_mut().drain(..) {
+            tag_table.remove(&tag);
+        }
+
+        let mut tags = Vec::new();
+        for rule in &rules {
+            let tag = gtk::TextTag::new(None);
+            if let Some(color) = &rule.foreground {
+                tag.set_foreground(Some(color));
+            }
+            if let Some(color) = &rule.background {
+                tag.set_background(Some(color));
+            }
+            if rule.bold {
+                tag.set_weight(700);  // PANGO_WEIGHT_BOLD
+            }
+            if rule.italic {
+                tag.set_style(pango::Style::Italic);
+            }
+            if rule.underline {
+                tag.set_underline(pango::Underline::Single);
+            }
+            tag_table.add(&tag);
+            tags.push(tag);
+        }
+
+        // keep search highlighting on top of the rules
+        for name in ["search_match", "search_current"] {
+            if let Some(tag) = tag_table.lookup(name) {
+                tag.set_priority(tag_table.size() - 1);
+            }
+        }
+
+        self.highlight_tags.replace(tags);
+        self.highlight_rules.replace(rules);
+        self.apply_highlight_rules(&buffer, 0);
+    }
+
+    pub(super) fn apply_highlight_rules(&self, buffer: &gtk::TextBuffer, start_offset: i32) {
+        let rules = self.highlight_rules.borrow();
+        if rules.is_empty() {
+            return;
+        }
+        let tags = self.highlight_tags.borrow();
+
+        let first_line = buffer.iter_at_offset(start_offset).line();
+        for line in first_line..buffer.line_count() {
+            let line_start = buffer.iter_at_line(line);
+            let mut line_end = line_start.clone();
+            if !line_end.ends_line() {
+                line_end.forward_to_line_end();
+            }
+            let line_text = match buffer.text(&line_start, &line_end, true) {
+                Some(text) => text.to_string(),
+                None => continue,
+            };
+
+            for (rule, tag) in rules.iter().zip(tags.iter()) {
+                if !rule.match_only {
+                    if rule.regex.is_match(&line_text) {
+                        buffer.apply_tag(tag, &line_start, &line_end);
+                    }
+                    continue;
+                }
+                for (match_start, match_end) in search::find_matches(&rule.regex, &line_text) {
+                    let offset = line_start.offset();
+                    buffer.apply_tag(tag, &buffer.iter_at_offset(offset + match_start), &buffer.iter_at_offset(offset + match_end));
+                }
+            }
+        }
+    }
+
+    pub(super) fn on_edit_highlight_rules_activate(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let text = highlight::format_rules(&priv_.highlight_rules.borrow());
+            let hint = "One rule per line: <regex> => <color> [bg=<color>] [bold] [italic] [underline] [match]\n\
+                        e.g. ERROR => red bold, ^\\[BLE\\] => blue, `match` styles only the matched text";
+            let text = match show_text_edit_dialog(&obj, "Highlight Rules", hint, &text).await {
+                Some(text) => text,
+                None => return,
+            };
+            match highlight::parse_rules(&text) {
+                Ok(rules) => priv_.save_highlight_rules(rules).await,
+                Err(e) => show_alert_dialog(&obj, e).await,
+            }
+        }));
+    }
+
+    async fn save_highlight_rules(&self, rules: Vec<HighlightRule>) {
+        if let Err(e) = highlight::save_rules(&rules) {
+            let obj = MainWindow::instance(self);
+            show_alert_dialog(&obj, format!("Failed to save highlight rules: {}", e)).await;
+        }
+        self.set_highlight_rules(rules);
+    }
+
+    pub(super) fn on_import_highlight_rules_activate(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let files = show_file_chooser_dialog(&obj, "Import Highlight Rules", gtk::FileChooserAction::Open, false).await;
+            let path = match files.first() {
+                Some(path) => path,
+                None => return,
+            };
+            let result = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| highlight::parse_rules(&text));
+            match result {
+                Ok(rules) => priv_.save_highlight_rules(rules).await,
+                Err(e) => show_alert_dialog(&obj, format!("Failed to import highlight rules: {}", e)).await,
+            }
+        }));
+    }
+
+    pub(super) fn on_export_highlight_rules_activate(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let files = show_file_chooser_dialog(&obj, "Export Highlight Rules", gtk::FileChooserAction::Save, false).await;
+            let path = match files.first() {
+                Some(path) => path,
+                None => return,
+            };
+            let text = highlight::format_rules(&priv_.highlight_rules.borrow());
+            if let Err(e) = std::fs::write(path, text) {
+                show_alert_dialog(&obj, format!("Failed to export highlight rules: {}", e)).await;
+            }
+        }));
+    }
+}
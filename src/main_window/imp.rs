@@ -1,22 +1,71 @@
 use glib::clone;
+use gtk::gdk;
+use gtk::gdk::keys::constants as keys;
 use gtk::glib;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 
 use std::cell::{Cell, RefCell};
-use std::sync::{Arc, Mutex};
+use std::fs::File;
+use std::io;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 use once_cell::unsync::OnceCell;
-use futures::channel::mpsc::unbounded;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
 
 use tokio_serial::available_ports;
 use serialport::SerialPortType::*;
 use regex::Regex;
 
+use crate::checksum::{ByteOrder, ChecksumKind};
+use crate::decoder::{self, SharedDecoder};
+use crate::escape;
+use crate::highlight::{self, HighlightRule};
+use crate::modbus::slave::{self, SlaveMap};
 use crate::model;
-use crate::port::open_port_async;
+use crate::nmea::NmeaState;
+use crate::plot::{LineFormat, PlotData};
+use crate::port::{open_port_async, DataDirection, Output, PortCommand, SharedPrompt, READ_CHANNEL_BOUND};
 use crate::my_tools::*;
+use crate::scrollback::{self, ScrollbackLimit};
+use crate::search::FilterMode;
+use crate::sequence::{self, Sequence};
+use crate::stats::SharedStats;
+use crate::transcript::{self, EchoFilter};
+use crate::trigger::{self, Trigger};
 use crate::usb::hotplug_runloop_startup;
+use crate::xmodem::Protocol;
+
+use super::modbus::{ModbusPanel, ModbusSlavePanel};
+use super::nmea::NmeaPanel;
+use super::plot::PlotPanel;
+use super::script::ScriptPanel;
+use super::stats::StatsPanel;
+
+// the first item of the checksum combo box is "No Checksum"
+fn selected_checksum_kind(combo: &gtk::ComboBoxText) -> Option<ChecksumKind> {
+    match combo.active() {
+        Some(i) if i > 0 => ChecksumKind::all().get(i as usize - 1).copied(),
+        _ => None,
+    }
+}
+
+// one text column per title, bound to the model column of the same index
+pub(super) fn append_text_columns(tree_view: &gtk::TreeView, titles: &[&str]) -> Vec<gtk::CellRendererText> {
+    titles.iter()
+        .enumerate()
+        .map(|(i, title)| {
+            let renderer = gtk::CellRendererText::new();
+            let column = gtk::TreeViewColumn::new();
+            column.set_title(title);
+            column.pack_start(&renderer, true);
+            column.add_attribute(&renderer, "text", i as i32);
+            tree_view.append_column(&column);
+            renderer
+        })
+        .collect()
+}
 
 enum PortState {
     Opening,
@@ -26,28 +75,110 @@ enum PortState {
 
 #[derive(Debug, Default)]
 pub struct MainWindow {
-    port_model: OnceCell<gtk::ListStore>,
-    port_combo_box: OnceCell<gtk::ComboBoxText>,
-    port_refresh_button: OnceCell<gtk::Button>,
-    selected_port_name: RefCell<String>,
-
-    write_entry: OnceCell<gtk::Entry>,
-    write_button: OnceCell<gtk::Button>,
-    write_button_handler_id: RefCell<Option<glib::SignalHandlerId>>,
-
-    read_text_view: OnceCell<gtk::TextView>,
-    scrolled_window: OnceCell<gtk::ScrolledWindow>,
-
-    timestamp_check_button: OnceCell<gtk::CheckButton>,
-    auto_scroll_check_button: OnceCell<gtk::CheckButton>,
-
-    baud_rate_combo_box: OnceCell<gtk::ComboBoxText>,
-    open_close_button: OnceCell<gtk::Button>,
-
-    port_close_flag: Arc<Mutex<bool>>,
-    is_port_opened: Cell<bool>,
-
-    usb_detect_pause_flag: Arc<Mutex<bool>>,  // use only when hotplug is not supported
+    pub(super) port_model: OnceCell<gtk::ListStore>,
+    pub(super) port_combo_box: OnceCell<gtk::ComboBoxText>,
+    pub(super) port_refresh_button: OnceCell<gtk::Button>,
+    pub(super) selected_port_name: RefCell<String>,
+
+    pub(super) dtr_toggle_button: OnceCell<gtk::ToggleButton>,
+    pub(super) rts_toggle_button: OnceCell<gtk::ToggleButton>,
+    pub(super) break_button: OnceCell<gtk::Button>,
+    pub(super) break_duration_spin_button: OnceCell<gtk::SpinButton>,
+    pub(super) sequence_menu: OnceCell<gtk::Menu>,
+    pub(super) user_sequences: RefCell<Vec<Sequence>>,
+    pub(super) modem_status_labels: OnceCell<Vec<(String, gtk::Label)>>,
+
+    pub(super) write_entry: OnceCell<gtk::Entry>,
+    pub(super) write_button: OnceCell<gtk::Button>,
+    pub(super) write_button_handler_id: RefCell<Option<glib::SignalHandlerId>>,
+    pub(super) write_tx: RefCell<Option<UnboundedSender<PortCommand>>>,
+
+    pub(super) control_menu_button: OnceCell<gtk::MenuButton>,
+    pub(super) escapes_check_button: OnceCell<gtk::CheckButton>,
+    pub(super) hex_check_button: OnceCell<gtk::CheckButton>,
+    pub(super) checksum_combo_box: OnceCell<gtk::ComboBoxText>,
+    pub(super) byte_order_combo_box: OnceCell<gtk::ComboBoxText>,
+    pub(super) repeat_check_button: OnceCell<gtk::CheckButton>,
+    pub(super) repeat_interval_spin_button: OnceCell<gtk::SpinButton>,
+    pub(super) repeat_count_spin_button: OnceCell<gtk::SpinButton>,
+    pub(super) repeat_source_id: RefCell<Option<glib::SourceId>>,
+    pub(super) repeat_payload: RefCell<String>,
+    pub(super) repeat_remaining: Cell<Option<u32>>,
+
+    pub(super) transfer_menu_button: OnceCell<gtk::MenuButton>,
+    pub(super) transfer_dialog: RefCell<Option<ProgressDialog>>,
+    pub(super) transfer_cancel_flag: Arc<Mutex<bool>>,
+    pub(super) is_zmodem_prompt_shown: Cell<bool>,
+    // continue partial ZMODEM files instead of overwriting them
+    pub(super) is_zmodem_resume: Cell<bool>,
+
+    pub(super) trigger_menu: OnceCell<gtk::Menu>,
+    pub(super) triggers: Arc<Mutex<Vec<Trigger>>>,
+
+    pub(super) script_panel: OnceCell<ScriptPanel>,
+    pub(super) script_cancel_flag: Arc<Mutex<bool>>,
+    pub(super) script_line_tx: RefCell<Option<mpsc::Sender<String>>>,
+    pub(super) port_prompt: SharedPrompt,
+
+    pub(super) modbus_panel: OnceCell<ModbusPanel>,
+    pub(super) modbus_slave_panel: OnceCell<ModbusSlavePanel>,
+    pub(super) modbus_slave_map: Arc<Mutex<SlaveMap>>,
+    pub(super) modbus_slave_stop_flag: Arc<Mutex<bool>>,
+
+    pub(super) nmea_panel: OnceCell<NmeaPanel>,
+    pub(super) nmea_state: RefCell<NmeaState>,
+
+    pub(super) plot_panel: OnceCell<PlotPanel>,
+    pub(super) plot_data: RefCell<PlotData>,
+    pub(super) plot_format: RefCell<LineFormat>,
+    pub(super) plot_paused_at: Cell<Option<f64>>,
+
+    pub(super) stats_panel: OnceCell<StatsPanel>,
+    pub(super) port_stats: SharedStats,
+
+    pub(super) read_text_view: OnceCell<gtk::TextView>,
+    pub(super) scrolled_window: OnceCell<gtk::ScrolledWindow>,
+    pub(super) scrollback_limit: Cell<ScrollbackLimit>,
+    pub(super) output_bytes: Cell<usize>,
+
+    pub(super) decoder_combo_box: OnceCell<gtk::ComboBoxText>,
+    pub(super) decoder: SharedDecoder,
+    pub(super) decoded_view: OnceCell<gtk::TreeView>,
+    pub(super) decoded_model: OnceCell<gtk::TreeStore>,
+    pub(super) decoded_scrolled_window: OnceCell<gtk::ScrolledWindow>,
+
+    pub(super) dropped_label: OnceCell<gtk::Label>,
+    pub(super) dropped_bytes: Cell<usize>,
+
+    pub(super) session_log_check_button: OnceCell<gtk::CheckButton>,
+    pub(super) session_log: RefCell<Option<io::LineWriter<File>>>,
+
+    pub(super) search_bar: OnceCell<gtk::SearchBar>,
+    pub(super) search_entry: OnceCell<gtk::SearchEntry>,
+    pub(super) match_case_check_button: OnceCell<gtk::CheckButton>,
+    pub(super) regex_check_button: OnceCell<gtk::CheckButton>,
+    pub(super) filter_combo_box: OnceCell<gtk::ComboBoxText>,
+    pub(super) search_result_label: OnceCell<gtk::Label>,
+    pub(super) search_matcher: RefCell<Option<Regex>>,
+    pub(super) search_matches: RefCell<Vec<(i32, i32)>>,
+    pub(super) current_match_index: Cell<Option<usize>>,
+
+    pub(super) highlight_rules: RefCell<Vec<HighlightRule>>,
+    pub(super) highlight_tags: RefCell<Vec<gtk::TextTag>>,
+
+    pub(super) timestamp_check_button: OnceCell<gtk::CheckButton>,
+    pub(super) show_tx_check_button: OnceCell<gtk::CheckButton>,
+    pub(super) suppress_echo_check_button: OnceCell<gtk::CheckButton>,
+    pub(super) echo_filter: RefCell<EchoFilter>,
+    pub(super) auto_scroll_check_button: OnceCell<gtk::CheckButton>,
+
+    pub(super) baud_rate_combo_box: OnceCell<gtk::ComboBoxText>,
+    pub(super) open_close_button: OnceCell<gtk::Button>,
+
+    pub(super) port_close_flag: Arc<Mutex<bool>>,
+    pub(super) is_port_opened: Cell<bool>,
+
+    pub(super) usb_detect_pause_flag: Arc<Mutex<bool>>,  // use only when hotplug is not supported
 }
 
 #[glib::object_subclass]
@@ -103,9 +234,71 @@ impl ObjectImpl for MainWindow {
             priv_.on_port_refresh_button_clicked();
         }));
 
+        // control lines
+        let dtr_toggle_button = gtk::ToggleButton::builder()
+            .label("DTR")
+            .active(true)
+            .sensitive(false)
+            .build();
+
+        dtr_toggle_button.connect_toggled(clone!(@weak obj => move |button| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.send_port_command(PortCommand::SetDtr(button.is_active()));
+        }));
+
+        let rts_toggle_button = gtk::ToggleButton::builder()
+            .label("RTS")
+            .active(true)
+            .sensitive(false)
+            .build();
+
+        rts_toggle_button.connect_toggled(clone!(@weak obj => move |button| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.send_port_command(PortCommand::SetRts(button.is_active()));
+        }));
+
+        let break_button = gtk::Button::builder()
+            .label("Send Break")
+            .margin_start(5)
+            .sensitive(false)
+            .build();
+
+        break_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_break_button_clicked();
+        }));
+
+        let break_duration_spin_button = gtk::SpinButton::with_range(10.0, 5000.0, 10.0);
+        break_duration_spin_button.set_value(250.0);
+        break_duration_spin_button.set_tooltip_text(Some("Break duration (ms)"));
+
+        let sequence_menu = gtk::Menu::new();
+        let sequence_menu_button = gtk::MenuButton::builder()
+            .label("Reset")
+            .popup(&sequence_menu)
+            .build();
+
+        let mut modem_status_labels = Vec::new();
+        for name in ["CTS", "DSR", "DCD", "RI"] {
+            let label = gtk::Label::builder()
+                .label(&format!("\u{25CF} {}", name))
+                .margin_start(5)
+                .build();
+            label.style_context().add_class("led");
+            modem_status_labels.push((name.to_lowercase(), label));
+        }
+
         box1.pack_start(&port_label, false, false, 0);
         box1.pack_start(&port_combo_box, true, true, 0);
         box1.pack_start(&port_refresh_button, false, false, 0);
+        box1.pack_start(&dtr_toggle_button, false, false, 0);
+        box1.pack_start(&rts_toggle_button, false, false, 0);
+        box1.pack_start(&break_button, false, false, 0);
+        box1.pack_start(&break_duration_spin_button, false, false, 0);
+        box1.pack_start(&sequence_menu_button, false, false, 0);
+        for (_, label) in &modem_status_labels {
+            box1.pack_start(label, false, false, 0);
+        }
 
         
         // box2
@@ -135,8 +328,130 @@ impl ObjectImpl for MainWindow {
             priv_.on_write_entry_activate();
         }));
 
+        // control_menu_button
+        let control_menu = gtk::Menu::new();
+        for (name, byte, shortcut) in escape::CONTROL_CHARACTERS {
+            let item = gtk::MenuItem::with_label(name);
+            if let Some(key) = shortcut {
+                item.set_tooltip_text(Some(&format!("Ctrl+Shift+{}", key.to_ascii_uppercase())));
+            }
+            item.connect_activate(clone!(@weak obj => move |_| {
+                let priv_ = MainWindow::from_instance(&obj);
+                priv_.send_port_command(PortCommand::WriteBytes(vec![byte]));
+            }));
+            control_menu.append(&item);
+        }
+        control_menu.show_all();
+
+        let control_menu_button = gtk::MenuButton::builder()
+            .label("Ctrl")
+            .popup(&control_menu)
+            .tooltip_text("Send a control character")
+            .sensitive(false)
+            .build();
+
+        let escapes_check_button = gtk::CheckButton::builder()
+            .label("Escapes")
+            .tooltip_text("Interpret escapes in the line: \\r \\n \\t \\0 \\xHH \\u{HHHH} \\\\")
+            .build();
+
+        let hex_check_button = gtk::CheckButton::builder()
+            .label("Hex")
+            .tooltip_text("Send the line as hex bytes (e.g. 01 03 00 0A), without a line ending")
+            .build();
+
+        let checksum_combo_box = gtk::ComboBoxText::new();
+        checksum_combo_box.set_tooltip_text(Some("Append a checksum to the payload, before the line ending"));
+        checksum_combo_box.append_text("No Checksum");
+        for kind in ChecksumKind::all() {
+            checksum_combo_box.append_text(kind.name());
+        }
+        checksum_combo_box.set_active(Some(0));
+
+        let byte_order_combo_box = gtk::ComboBoxText::new();
+        byte_order_combo_box.set_tooltip_text(Some("Checksum byte order"));
+        byte_order_combo_box.append_text("Big Endian");
+        byte_order_combo_box.append_text("Little Endian");
+        byte_order_combo_box.set_active(Some(0));
+        byte_order_combo_box.set_sensitive(false);
+
+        checksum_combo_box.connect_changed(clone!(@weak byte_order_combo_box => move |combo| {
+            let kind = selected_checksum_kind(combo);
+            byte_order_combo_box.set_sensitive(kind.is_some());
+            if let Some(kind) = kind {
+                let index = if kind.default_byte_order() == ByteOrder::LittleEndian { 1 } else { 0 };
+                byte_order_combo_box.set_active(Some(index));
+            }
+        }));
+
+        // repeat send
+        let repeat_check_button = gtk::CheckButton::builder()
+            .label("Repeat")
+            .tooltip_text("Keep sending the line until stopped")
+            .build();
+        let repeat_interval_spin_button = gtk::SpinButton::with_range(10.0, 3_600_000.0, 100.0);
+        repeat_interval_spin_button.set_value(1000.0);
+        repeat_interval_spin_button.set_tooltip_text(Some("Repeat interval (ms)"));
+        let repeat_count_spin_button = gtk::SpinButton::with_range(0.0, 1_000_000.0, 1.0);
+        repeat_count_spin_button.set_tooltip_text(Some("Repeat count (0 = until stopped)"));
+
+        // transfer_menu_button
+        let transfer_menu = gtk::Menu::new();
+        for is_send in [true, false] {
+            if !is_send {
+                transfer_menu.append(&gtk::SeparatorMenuItem::new());
+            }
+            for protocol in Protocol::all() {
+                let label = if is_send {
+                    format!("Send File ({})...", protocol.name())
+                } else {
+                    format!("Receive File ({})...", protocol.name())
+                };
+                let item = gtk::MenuItem::with_label(&label);
+                item.connect_activate(clone!(@weak obj => move |_| {
+                    let priv_ = MainWindow::from_instance(&obj);
+                    priv_.on_transfer_menu_item_activate(protocol, is_send);
+                }));
+                transfer_menu.append(&item);
+            }
+        }
+        transfer_menu.append(&gtk::SeparatorMenuItem::new());
+        let resume_item = gtk::CheckMenuItem::with_label("Resume Partial ZMODEM Files");
+        resume_item.set_tooltip_text(Some("Continue files that already exist by name instead of overwriting them, only for interrupted copies of the same file"));
+        resume_item.connect_toggled(clone!(@weak obj => move |item| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.is_zmodem_resume.set(item.is_active());
+        }));
+        transfer_menu.append(&resume_item);
+        transfer_menu.show_all();
+
+        let transfer_menu_button = gtk::MenuButton::builder()
+            .label("Transfer")
+            .popup(&transfer_menu)
+            .margin_end(5)
+            .sensitive(false)
+            .build();
+
+        // trigger_menu_button
+        let trigger_menu = gtk::Menu::new();
+        let trigger_menu_button = gtk::MenuButton::builder()
+            .label("Triggers")
+            .popup(&trigger_menu)
+            .margin_end(5)
+            .build();
+
         box2.pack_start(&write_entry, true, true, 0);
         box2.pack_start(&write_button, false, false, 0);
+        box2.pack_start(&control_menu_button, false, false, 0);
+        box2.pack_start(&escapes_check_button, false, false, 0);
+        box2.pack_start(&hex_check_button, false, false, 0);
+        box2.pack_start(&checksum_combo_box, false, false, 0);
+        box2.pack_start(&byte_order_combo_box, false, false, 0);
+        box2.pack_start(&repeat_check_button, false, false, 0);
+        box2.pack_start(&repeat_interval_spin_button, false, false, 0);
+        box2.pack_start(&repeat_count_spin_button, false, false, 0);
+        box2.pack_start(&transfer_menu_button, false, false, 0);
+        box2.pack_start(&trigger_menu_button, false, false, 0);
 
 
         // read_text_view
@@ -145,6 +460,12 @@ impl ObjectImpl for MainWindow {
             .build();
             
         read_text_view.set_widget_name("read_text_view");
+        if let Some(tag_table) = read_text_view.buffer().and_then(|b| b.tag_table()) {
+            tag_table.add(&gtk::TextTag::builder().name("search_match").background("#fff59d").build());
+            tag_table.add(&gtk::TextTag::builder().name("search_current").background("#ffb74d").build());
+            tag_table.add(&gtk::TextTag::builder().name("filtered_out").invisible(true).build());
+            tag_table.add(&gtk::TextTag::builder().name("tx_echo").foreground("#1e88e5").build());
+        }
         read_text_view.connect_size_allocate(clone!(@weak obj => move |_,_| {
             let priv_ = MainWindow::from_instance(&obj);
             priv_.on_read_text_view_size_allocate();
@@ -159,6 +480,126 @@ impl ObjectImpl for MainWindow {
             .margin_end(10)
             .build();
 
+        // decoded frames, beside the output while a decoder is selected
+        let decoded_model = model::create_decoded_frame_model();
+        let decoded_view = gtk::TreeView::with_model(&decoded_model);
+        append_text_columns(&decoded_view, &["Time", "Frame"]);
+        let decoded_scrolled_window = gtk::ScrolledWindow::builder()
+            .child(&decoded_view)
+            .width_request(300)
+            .margin_end(10)
+            .no_show_all(true)
+            .build();
+
+        let output_paned = gtk::Paned::new(gtk::Orientation::Horizontal);
+        output_paned.pack1(&scrolled_window, true, false);
+        output_paned.pack2(&decoded_scrolled_window, false, false);
+
+
+        // search_bar
+        let search_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .homogeneous(false)
+            .spacing(5)
+            .build();
+
+        let search_entry = gtk::SearchEntry::builder()
+            .width_chars(30)
+            .build();
+
+        search_entry.connect_search_changed(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_search_changed();
+        }));
+        search_entry.connect_activate(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.select_next_match(true);
+        }));
+        search_entry.connect_next_match(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.select_next_match(true);
+        }));
+        search_entry.connect_previous_match(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.select_next_match(false);
+        }));
+
+        let previous_match_button = gtk::Button::builder()
+            .label("Previous")
+            .build();
+
+        previous_match_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.select_next_match(false);
+        }));
+
+        let next_match_button = gtk::Button::builder()
+            .label("Next")
+            .build();
+
+        next_match_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.select_next_match(true);
+        }));
+
+        let match_case_check_button = gtk::CheckButton::builder()
+            .label("Match Case")
+            .margin_start(5)
+            .build();
+        let regex_check_button = gtk::CheckButton::builder()
+            .label("Regex")
+            .margin_start(5)
+            .build();
+
+        for check_button in [&match_case_check_button, &regex_check_button] {
+            check_button.connect_toggled(clone!(@weak obj => move |_| {
+                let priv_ = MainWindow::from_instance(&obj);
+                priv_.on_search_changed();
+            }));
+        }
+
+        let filter_combo_box = gtk::ComboBoxText::new();
+        for mode in FilterMode::all() {
+            filter_combo_box.append_text(mode.name());
+        }
+        filter_combo_box.set_active(Some(0));
+
+        filter_combo_box.connect_changed(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_search_changed();
+        }));
+
+        let search_result_label = gtk::Label::builder()
+            .margin_start(5)
+            .build();
+
+        search_box.pack_start(&search_entry, false, false, 0);
+        search_box.pack_start(&previous_match_button, false, false, 0);
+        search_box.pack_start(&next_match_button, false, false, 0);
+        search_box.pack_start(&match_case_check_button, false, false, 0);
+        search_box.pack_start(&regex_check_button, false, false, 0);
+        search_box.pack_start(&filter_combo_box, false, false, 0);
+        search_box.pack_start(&search_result_label, false, false, 0);
+
+        let search_bar = gtk::SearchBar::builder()
+            .show_close_button(true)
+            .build();
+        search_bar.add(&search_box);
+        search_bar.connect_entry(&search_entry);
+
+        search_bar.connect_search_mode_enabled_notify(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_search_changed();
+        }));
+
+        // Ctrl+F, F3
+        obj.connect_key_press_event(clone!(@weak obj => @default-return gtk::Inhibit(false),
+            move |_, event| {
+                let priv_ = MainWindow::from_instance(&obj);
+                gtk::Inhibit(priv_.on_key_press_event(event))
+            }
+        ));
+
 
         // box3
         let box3 = gtk::Box::builder()
@@ -190,6 +631,79 @@ impl ObjectImpl for MainWindow {
             .active(true)
             .build();
 
+        let show_tx_check_button = gtk::CheckButton::builder()
+            .label("Show TX")
+            .margin_start(5)
+            .tooltip_text("Echo transmitted data into the output, with TX/RX markers")
+            .build();
+
+        let suppress_echo_check_button = gtk::CheckButton::builder()
+            .label("Hide Remote Echo")
+            .margin_start(5)
+            .tooltip_text("Hide the device's echo of the lines sent")
+            .build();
+
+        let session_log_check_button = gtk::CheckButton::builder()
+            .label("Session Log")
+            .margin_start(5)
+            .build();
+
+        session_log_check_button.connect_toggled(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_session_log_toggled();
+        }));
+
+        let options_menu = gtk::Menu::new();
+        let options_items: [(&str, fn(&MainWindow)); 12] = [
+            ("Save Output As...", MainWindow::on_save_output_activate),
+            ("Scrollback Limit...", MainWindow::on_scrollback_limit_activate),
+            ("Highlight Rules...", MainWindow::on_edit_highlight_rules_activate),
+            ("Import Highlight Rules...", MainWindow::on_import_highlight_rules_activate),
+            ("Export Highlight Rules...", MainWindow::on_export_highlight_rules_activate),
+            ("Script Panel...", MainWindow::on_script_panel_activate),
+            ("Modbus Master...", MainWindow::on_modbus_master_activate),
+            ("Modbus Slave Simulator...", MainWindow::on_modbus_slave_activate),
+            ("NMEA Decoder...", MainWindow::on_nmea_decoder_activate),
+            ("Plotter...", MainWindow::on_plotter_activate),
+            ("Statistics...", MainWindow::on_statistics_activate),
+            ("Frame Format...", MainWindow::on_frame_format_activate),
+        ];
+        for (label, handler) in options_items {
+            let item = gtk::MenuItem::with_label(label);
+            item.connect_activate(clone!(@weak obj => move |_| {
+                let priv_ = MainWindow::from_instance(&obj);
+                handler(priv_);
+            }));
+            options_menu.append(&item);
+        }
+        options_menu.show_all();
+
+        let options_menu_button = gtk::MenuButton::builder()
+            .label("Options")
+            .popup(&options_menu)
+            .margin_start(5)
+            .build();
+
+        let decoder_combo_box = gtk::ComboBoxText::new();
+        decoder_combo_box.set_margin_start(5);
+        decoder_combo_box.set_tooltip_text(Some("Decode the received bytes into frames, binary protocols replace the line output"));
+        decoder_combo_box.append_text("No Decoder");
+        for (name, _) in decoder::DECODERS.iter() {
+            decoder_combo_box.append_text(name);
+        }
+        decoder_combo_box.set_active(Some(0));
+
+        decoder_combo_box.connect_changed(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_decoder_changed();
+        }));
+
+        let dropped_label = gtk::Label::builder()
+            .margin_start(5)
+            .tooltip_text("The display could not keep up with the received data")
+            .build();
+        dropped_label.style_context().add_class("warning");
+
         let baud_rate_label = gtk::Label::builder()
             .label("Baud Rate:")
             .margin_start(55)
@@ -220,6 +734,12 @@ impl ObjectImpl for MainWindow {
         box3.pack_start(&clear_output_button, false, false, 0);
         box3.pack_start(&auto_scroll_check_button, false, false, 0);
         box3.pack_start(&timestamp_check_button, false, false, 0);
+        box3.pack_start(&show_tx_check_button, false, false, 0);
+        box3.pack_start(&suppress_echo_check_button, false, false, 0);
+        box3.pack_start(&session_log_check_button, false, false, 0);
+        box3.pack_start(&options_menu_button, false, false, 0);
+        box3.pack_start(&decoder_combo_box, false, false, 0);
+        box3.pack_start(&dropped_label, false, false, 0);
         box3.pack_end(&open_close_button, false, false, 0);
         box3.pack_end(&baud_rate_combo_box, false, false, 0);
         box3.pack_end(&baud_rate_label, false, false, 0);
@@ -228,7 +748,8 @@ impl ObjectImpl for MainWindow {
         // add components to main_box
         main_box.pack_start(&box1, false, false, 0);
         main_box.pack_start(&box2, false, false, 0);
-        main_box.pack_start(&scrolled_window, true, true, 0);
+        main_box.pack_start(&search_bar, false, false, 0);
+        main_box.pack_start(&output_paned, true, true, 0);
         main_box.pack_start(&box3, false, false, 0);
         
         // set window
@@ -240,14 +761,46 @@ impl ObjectImpl for MainWindow {
         self.port_combo_box.set(port_combo_box).expect("Failed to initialize window state: port_combo_box");
         self.port_refresh_button.set(port_refresh_button).expect("Failed to initialize window state: port_refresh_button");
 
+        self.dtr_toggle_button.set(dtr_toggle_button).expect("Failed to initialize window state: dtr_toggle_button");
+        self.rts_toggle_button.set(rts_toggle_button).expect("Failed to initialize window state: rts_toggle_button");
+        self.break_button.set(break_button).expect("Failed to initialize window state: break_button");
+        self.break_duration_spin_button.set(break_duration_spin_button).expect("Failed to initialize window state: break_duration_spin_button");
+        self.modem_status_labels.set(modem_status_labels).expect("Failed to initialize window state: modem_status_labels");
+        self.sequence_menu.set(sequence_menu).expect("Failed to initialize window state: sequence_menu");
+
         self.write_entry.set(write_entry).expect("Failed to initialize window state: write_entry");
         self.write_button.set(write_button).expect("Failed to initialize window state: write_button");
+        self.control_menu_button.set(control_menu_button).expect("Failed to initialize window state: control_menu_button");
+        self.escapes_check_button.set(escapes_check_button).expect("Failed to initialize window state: escapes_check_button");
+        self.hex_check_button.set(hex_check_button).expect("Failed to initialize window state: hex_check_button");
+        self.checksum_combo_box.set(checksum_combo_box).expect("Failed to initialize window state: checksum_combo_box");
+        self.byte_order_combo_box.set(byte_order_combo_box).expect("Failed to initialize window state: byte_order_combo_box");
+        self.repeat_check_button.set(repeat_check_button).expect("Failed to initialize window state: repeat_check_button");
+        self.repeat_interval_spin_button.set(repeat_interval_spin_button).expect("Failed to initialize window state: repeat_interval_spin_button");
+        self.repeat_count_spin_button.set(repeat_count_spin_button).expect("Failed to initialize window state: repeat_count_spin_button");
+        self.transfer_menu_button.set(transfer_menu_button).expect("Failed to initialize window state: transfer_menu_button");
+        self.trigger_menu.set(trigger_menu).expect("Failed to initialize window state: trigger_menu");
 
         self.read_text_view.set(read_text_view).expect("Failed to initialize window state: read_text_view");
         self.scrolled_window.set(scrolled_window).expect("Failed to initialize window state: scrolled_window");
+        self.decoder_combo_box.set(decoder_combo_box).expect("Failed to initialize window state: decoder_combo_box");
+        self.decoded_view.set(decoded_view).expect("Failed to initialize window state: decoded_view");
+        self.decoded_model.set(decoded_model).expect("Failed to initialize window state: decoded_model");
+        self.decoded_scrolled_window.set(decoded_scrolled_window).expect("Failed to initialize window state: decoded_scrolled_window");
+
+        self.search_bar.set(search_bar).expect("Failed to initialize window state: search_bar");
+        self.search_entry.set(search_entry).expect("Failed to initialize window state: search_entry");
+        self.match_case_check_button.set(match_case_check_button).expect("Failed to initialize window state: match_case_check_button");
+        self.regex_check_button.set(regex_check_button).expect("Failed to initialize window state: regex_check_button");
+        self.filter_combo_box.set(filter_combo_box).expect("Failed to initialize window state: filter_combo_box");
+        self.search_result_label.set(search_result_label).expect("Failed to initialize window state: search_result_label");
         
         self.timestamp_check_button.set(timestamp_check_button).expect("Failed to initialize window state: timestamp_check_button");
+        self.show_tx_check_button.set(show_tx_check_button).expect("Failed to initialize window state: show_tx_check_button");
+        self.suppress_echo_check_button.set(suppress_echo_check_button).expect("Failed to initialize window state: suppress_echo_check_button");
         self.auto_scroll_check_button.set(auto_scroll_check_button).expect("Failed to initialize window state: auto_scroll_check_button");
+        self.session_log_check_button.set(session_log_check_button).expect("Failed to initialize window state: session_log_check_button");
+        self.dropped_label.set(dropped_label).expect("Failed to initialize window state: dropped_label");
         
         self.baud_rate_combo_box.set(baud_rate_combo_box).expect("Failed to initialize window state: baud_rate_combo_box");
         self.open_close_button.set(open_close_button).expect("Failed to initialize window state: open_port_button");
@@ -256,6 +809,19 @@ impl ObjectImpl for MainWindow {
         // click port_refresh_button
         self.port_refresh_button.get().unwrap().clicked();
 
+        self.user_sequences.replace(sequence::load_user_sequences());
+        self.rebuild_sequence_menu();
+        self.set_highlight_rules(highlight::load_rules());
+        self.set_triggers(trigger::load_triggers());
+        self.scrollback_limit.set(scrollback::load_limit());
+        *self.modbus_slave_map.lock().unwrap() = slave::load_slave_map();
+
+        glib::timeout_add_seconds_local(1, clone!(@weak obj => @default-return glib::Continue(false), move || {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_stats_timeout();
+            glib::Continue(true)
+        }));
+
         // usb hotplug detection
         let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
 
@@ -295,10 +861,142 @@ impl MainWindow {
         *port_close_flag = flag;
     }
 
+    // false if there is no port task (anymore), e.g. after an unplug before its close event arrived
+    pub(super) fn send_port_command(&self, command: PortCommand) -> bool {
+        let write_tx = self.write_tx.borrow();
+        match write_tx.as_ref().map(|write_tx| write_tx.unbounded_send(command)) {
+            Some(Ok(())) => true,
+            Some(Err(e)) => {
+                eprintln!("Port command not sent: {}", e);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn on_break_button_clicked(&self) {
+        let duration = self.break_duration_spin_button.get().unwrap().value_as_int();
+        self.send_port_command(PortCommand::SendBreak(Duration::from_millis(duration as u64)));
+    }
+
     fn on_write_entry_activate(&self) {
         self.write_button.get().unwrap().clicked();
     }
 
+    fn on_write_button_clicked(&self) {
+        if self.repeat_source_id.borrow().is_some() {
+            self.stop_repeat();
+            return;
+        }
+
+        let write_entry = self.write_entry.get().unwrap();
+        let line = write_entry.text().to_string();
+        let command = match self.line_command(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                let obj = MainWindow::instance(self);
+                glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+                    show_alert_dialog(&obj, e).await;
+                }));
+                return;
+            }
+        };
+        self.send_port_command(command);
+        if !self.repeat_check_button.get().unwrap().is_active() {
+            write_entry.set_text("");
+            return;
+        }
+
+        // keep the line in the entry, it is what gets repeated
+        let count = self.repeat_count_spin_button.get().unwrap().value_as_int() as u32;
+        if count == 1 {
+            return;
+        }
+        self.repeat_payload.replace(line);
+        self.repeat_remaining.set(if count > 0 { Some(count - 1) } else { None });
+
+        let interval = self.repeat_interval_spin_button.get().unwrap().value_as_int() as u64;
+        let obj = MainWindow::instance(self);
+        let source_id = glib::timeout_add_local(
+            Duration::from_millis(interval),
+            clone!(@weak obj => @default-return glib::Continue(false), move || {
+                let priv_ = MainWindow::from_instance(&obj);
+                priv_.on_repeat_timeout()
+            })
+        );
+        self.repeat_source_id.replace(Some(source_id));
+        self.repeat_widgets_running(true);
+    }
+
+    // the command sending `line` as set up in the send bar:
+    // text (escapes resolved if enabled) and a newline, or hex bytes,
+    // with the checksum appended to the payload
+    fn line_command(&self, line: &str) -> Result<PortCommand, String> {
+        let is_hex = self.hex_check_button.get().unwrap().is_active();
+        let is_escapes = self.escapes_check_button.get().unwrap().is_active();
+        let checksum = selected_checksum_kind(self.checksum_combo_box.get().unwrap());
+        if !is_hex && !is_escapes && checksum.is_none() {
+            return Ok(PortCommand::Write(line.to_string()));
+        }
+
+        let mut bytes = if is_hex {
+            escape::parse_hex(line)?
+        } else if is_escapes {
+            escape::unescape(line)?
+        } else {
+            line.as_bytes().to_vec()
+        };
+        if let Some(kind) = checksum {
+            let order = match self.byte_order_combo_box.get().unwrap().active() {
+                Some(1) => ByteOrder::LittleEndian,
+                _ => ByteOrder::BigEndian,
+            };
+            let value = kind.compute_with_order(&bytes, order);
+            bytes.extend_from_slice(&value);
+        }
+        if !is_hex {
+            bytes.push(b'\n');
+        }
+        Ok(PortCommand::WriteBytes(bytes))
+    }
+
+    fn on_repeat_timeout(&self) -> glib::Continue {
+        // already checked by the first send, and the widgets are locked while repeating
+        if let Ok(command) = self.line_command(&self.repeat_payload.borrow()) {
+            self.send_port_command(command);
+        }
+        let remaining = self.repeat_remaining.get().map(|n| n.saturating_sub(1));
+        self.repeat_remaining.set(remaining);
+        if remaining == Some(0) {
+            // returning `Continue(false)` removes the source, so don't remove it again
+            self.repeat_source_id.replace(None);
+            self.repeat_widgets_running(false);
+            return glib::Continue(false);
+        }
+        glib::Continue(true)
+    }
+
+    pub(super) fn stop_repeat(&self) {
+        if let Some(source_id) = self.repeat_source_id.take() {
+            source_id.remove();
+        }
+        self.repeat_widgets_running(false);
+    }
+
+    fn repeat_widgets_running(&self, running: bool) {
+        self.write_button.get().unwrap().set_label(if running { "Stop" } else { "Send" });
+        self.escapes_check_button.get().unwrap().set_sensitive(!running);
+        self.hex_check_button.get().unwrap().set_sensitive(!running);
+        let checksum_combo_box = self.checksum_combo_box.get().unwrap();
+        checksum_combo_box.set_sensitive(!running);
+        // the byte order only applies with a checksum selected
+        let has_checksum = selected_checksum_kind(checksum_combo_box).is_some();
+        self.byte_order_combo_box.get().unwrap().set_sensitive(!running && has_checksum);
+        self.repeat_check_button.get().unwrap().set_sensitive(!running);
+        self.repeat_interval_spin_button.get().unwrap().set_sensitive(!running);
+        self.repeat_count_spin_button.get().unwrap().set_sensitive(!running);
+    }
+
     fn on_read_text_view_size_allocate(&self) {
         let is_auto_scroll = self.auto_scroll_check_button.get().unwrap().is_active();
         if is_auto_scroll {        
@@ -312,6 +1010,44 @@ impl MainWindow {
         if let Some(buffer) = text_view.buffer() {
             buffer.set_text("");
         }
+        self.output_bytes.set(0);
+        self.decoded_model.get().unwrap().clear();
+        self.search_matches.borrow_mut().clear();
+        self.current_match_index.set(None);
+        self.update_search_result_label();
+    }
+
+    fn on_data_dropped(&self, value: &str) {
+        let bytes = value.parse::<usize>().unwrap_or(0);
+        self.dropped_bytes.set(self.dropped_bytes.get() + bytes);
+        let text = format!("\u{26A0} {} bytes dropped", self.dropped_bytes.get());
+        self.dropped_label.get().unwrap().set_text(&text);
+    }
+
+    fn on_key_press_event(&self, event: &gdk::EventKey) -> bool {
+        let keyval = event.keyval();
+        let is_ctrl = event.state().contains(gdk::ModifierType::CONTROL_MASK);
+        let is_shift = event.state().contains(gdk::ModifierType::SHIFT_MASK);
+
+        if is_ctrl && (keyval == keys::f || keyval == keys::F) {
+            self.search_bar.get().unwrap().set_search_mode(true);
+            self.search_entry.get().unwrap().grab_focus();
+            return true;
+        }
+        // the send widgets are off while the port is closed or busy with a transfer or the slave
+        if is_ctrl && is_shift && self.write_button.get().unwrap().is_sensitive() {
+            let key = keyval.to_lower().to_unicode();
+            let control = escape::CONTROL_CHARACTERS.iter().find(|(_, _, shortcut)| shortcut.is_some() && *shortcut == key);
+            if let Some((_, byte, _)) = control {
+                self.send_port_command(PortCommand::WriteBytes(vec![*byte]));
+                return true;
+            }
+        }
+        if keyval == keys::F3 && self.search_bar.get().unwrap().is_search_mode() {
+            self.select_next_match(!is_shift);
+            return true;
+        }
+        false
     }
 
     fn on_port_refresh_button_clicked(&self) {
@@ -353,8 +1089,12 @@ impl MainWindow {
             let priv_ = MainWindow::from_instance(&obj);
             let answer = show_question_dialog(&obj, String::from("Close this port?")).await;
             if let gtk::ResponseType::Ok = answer {
+                priv_.set_transfer_cancel_flag(true);
+                priv_.set_modbus_slave_stop_flag(true);
                 priv_.set_port_close_flag(true);
-                priv_.write_button.get().unwrap().clicked();
+                // a repeat would keep sending into the closing port
+                priv_.stop_repeat();
+                priv_.send_port_command(PortCommand::Close);
             }
         })); 
     }
@@ -420,14 +1160,14 @@ impl MainWindow {
     fn open_port(&self, port_name: String, baud_rate: u32) {
         let obj = MainWindow::instance(self);
 
-        // receive port string, display to text_view
-        let (read_tx, read_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        // receive port string (batches of lines), display to text_view
+        let (read_tx, read_rx) = glib::MainContext::sync_channel(glib::PRIORITY_DEFAULT, READ_CHANNEL_BOUND);
         read_rx.attach(
             None,
             clone!(@weak obj => @default-return Continue(false),
-                move |line| {
+                move |text| {
                     let priv_ = MainWindow::from_instance(&obj);
-                    priv_.handle_output(line);
+                    priv_.handle_output(text);
                     glib::Continue(true)
                 }
             )
@@ -435,16 +1175,15 @@ impl MainWindow {
 
         // send string to port
         let (write_tx, write_rx) = unbounded();
-        let write_entry = self.write_entry.get().unwrap();
         let write_button = self.write_button.get().unwrap();
         let write_button_handler_id = write_button.connect_clicked(
-            clone!(@weak write_entry, @strong write_tx => move |_| {
-                let line = write_entry.text().to_string();
-                write_tx.unbounded_send(line).expect("Could not send through channel");
-                write_entry.set_text("");
+            clone!(@weak obj => move |_| {
+                let priv_ = MainWindow::from_instance(&obj);
+                priv_.on_write_button_clicked();
             })
         );
         self.write_button_handler_id.replace(Some(write_button_handler_id));
+        self.write_tx.replace(Some(write_tx));
 
         let (state_tx, state_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
         
@@ -464,23 +1203,67 @@ impl MainWindow {
         self.set_port_close_flag(false);
 
         let port_close_flag = self.port_close_flag.clone();
+        let triggers = self.triggers.clone();
+        let decoder = self.decoder.clone();
+        let stats = self.port_stats.clone();
+        let prompt = self.port_prompt.clone();
         tokio::task::spawn(async move {
-            open_port_async(port_name, baud_rate, write_rx, read_tx, state_tx, port_close_flag).await;
+            open_port_async(port_name, baud_rate, write_rx, read_tx, state_tx, port_close_flag, triggers, decoder, stats, prompt).await;
         });
     }
 
-    fn handle_output(&self, line: String) {
+    fn handle_output(&self, output: Output) {
+        self.decode_nmea(&output);
+        self.plot_output(&output);
+        if let Some(line_tx) = self.script_line_tx.borrow().as_ref() {
+            for (_, text) in output.iter().filter(|(direction, _)| *direction == DataDirection::Rx) {
+                for line in text.split_inclusive('\n') {
+                    let _ = line_tx.send(line.to_string());
+                }
+            }
+        }
         let text_view = self.read_text_view.get().unwrap();
         if let Some(buffer) = text_view.buffer() {
             let mut end_iter = buffer.end_iter();
             let is_show_timestamp = self.timestamp_check_button.get().unwrap().is_active();
-            let s: String;
-            if is_show_timestamp {
-                s = format!("{} -> {}", current_timestamp_string(), line);
-            } else {
-                s = line;
+            let is_show_tx = self.show_tx_check_button.get().unwrap().is_active();
+            let is_suppress_echo = self.suppress_echo_check_button.get().unwrap().is_active();
+            let mut echo_filter = self.echo_filter.borrow_mut();
+            let timestamp = if is_show_timestamp { Some(current_timestamp_string()) } else { None };
+            let start_offset = end_iter.offset();
+            for (direction, mut text) in output {
+                if is_suppress_echo {
+                    match direction {
+                        DataDirection::Tx => text.split_inclusive('\n').for_each(|line| echo_filter.sent(line)),
+                        DataDirection::Rx => {
+                            text = text.split_inclusive('\n')
+                                .filter(|line| !echo_filter.is_echo(line))
+                                .collect();
+                        }
+                    }
+                }
+                if text.is_empty() || (direction == DataDirection::Tx && !is_show_tx) {
+                    continue;
+                }
+                // direction markers are only needed once both directions are shown
+                let marker = if is_show_tx { Some(direction) } else { None };
+                let s: String = text.split_inclusive('\n')
+                    .map(|line| transcript::format_line(timestamp.as_deref(), marker, line))
+                    .collect();
+                self.write_session_log(&s);
+                let chunk_offset = end_iter.offset();
+                buffer.insert(&mut end_iter, &s);
+                self.output_bytes.set(self.output_bytes.get() + s.len());
+                if direction == DataDirection::Tx {
+                    buffer.apply_tag_by_name("tx_echo", &buffer.iter_at_offset(chunk_offset), &end_iter);
+                }
+            }
+            if end_iter.offset() == start_offset {
+                return;
             }
-            buffer.insert(&mut end_iter, &s);
+            self.apply_highlight_rules(&buffer, start_offset);
+            self.decorate_text(&buffer, start_offset);
+            self.trim_scrollback(&buffer);
         }
     }
 
@@ -490,9 +1273,46 @@ impl MainWindow {
         self.baud_rate_combo_box.get().unwrap().set_sensitive(enable);
     }
 
-    fn write_widgets_enable(&self, enable: bool) {
+    pub(super) fn write_widgets_enable(&self, enable: bool) {
         self.write_entry.get().unwrap().set_sensitive(enable);
         self.write_button.get().unwrap().set_sensitive(enable);
+        self.control_menu_button.get().unwrap().set_sensitive(enable);
+        self.transfer_menu_button.get().unwrap().set_sensitive(enable);
+    }
+
+    fn control_line_widgets_enable(&self, enable: bool) {
+        let dtr_toggle_button = self.dtr_toggle_button.get().unwrap();
+        let rts_toggle_button = self.rts_toggle_button.get().unwrap();
+        dtr_toggle_button.set_sensitive(enable);
+        rts_toggle_button.set_sensitive(enable);
+        self.break_button.get().unwrap().set_sensitive(enable);
+
+        if enable {
+            // apply the current toggle states to the freshly opened port
+            self.send_port_command(PortCommand::SetDtr(dtr_toggle_button.is_active()));
+            self.send_port_command(PortCommand::SetRts(rts_toggle_button.is_active()));
+        } else {
+            self.on_modem_status_changed("cts=0,dsr=0,dcd=0,ri=0");
+        }
+    }
+
+    fn on_modem_status_changed(&self, value: &str) {
+        //
+        // value format: cts=1,dsr=0,dcd=0,ri=0
+        //
+        let labels = self.modem_status_labels.get().unwrap();
+        for item in value.split(',') {
+            let mut parts = item.split('=');
+            if let (Some(name), Some(level)) = (parts.next(), parts.next()) {
+                if let Some((_, label)) = labels.iter().find(|(n, _)| n == name) {
+                    if level == "1" {
+                        label.style_context().add_class("on");
+                    } else {
+                        label.style_context().remove_class("on");
+                    }
+                }
+            }
+        }
     }
 
     fn set_open_close_button(&self, state: PortState) {
@@ -514,8 +1334,9 @@ impl MainWindow {
     fn get_state_event_and_value(&self, msg: String) -> (String, String) {
         //
         // msg format: [event_name](event_value)
+        //   the value runs to the final `)`, so it may contain any text (file names, errors)
         //
-        let re = Regex::new(r"\[(?P<event>.+?)\]\((?P<value>.*?)\)").unwrap();
+        let re = Regex::new(r"(?s)^\[(?P<event>[^\]]+)\]\((?P<value>.*)\)$").unwrap();
         match re.captures(&msg) {
             Some(caps) => {
                 let event = &caps["event"];
@@ -530,8 +1351,11 @@ impl MainWindow {
         let (event, value) = self.get_state_event_and_value(state_msg);
         if event == "open_port" && value == "ok" {
             self.is_port_opened.set(true);
+            self.dropped_bytes.set(0);
+            self.dropped_label.get().unwrap().set_text("");
             self.set_open_close_button(PortState::Opened);
             self.write_widgets_enable(true);
+            self.control_line_widgets_enable(true);
             self.set_usb_detect_pause_flag(true);
         } else if (event == "open_port" && value == "failed") || event == "close_port" {
             self.is_port_opened.set(false);
@@ -544,6 +1368,14 @@ impl MainWindow {
             }
             self.handle_close(dialog_text);
             self.set_usb_detect_pause_flag(false);
+        } else if event == "dropped" {
+            self.on_data_dropped(&value);
+        } else if event == "modem_status" {
+            self.on_modem_status_changed(&value);
+        } else if event == "zmodem" && value == "detected" {
+            self.on_zmodem_detected();
+        } else if event.starts_with("transfer_") {
+            self.on_transfer_state_changed(&event, value);
         } else if event == "usb_hotplug" && value == "changed" {
             if !self.is_port_opened.get() {
                 self.port_refresh_button.get().unwrap().clicked();
@@ -555,7 +1387,18 @@ impl MainWindow {
         if let Some(id) = self.write_button_handler_id.borrow_mut().take() {
             self.write_button.get().unwrap().disconnect(id)
         }
+        self.write_tx.replace(None);
+        // a running script sees the port closing on its next `expect`
+        self.script_line_tx.replace(None);
+        self.echo_filter.borrow_mut().clear();
+        self.stop_repeat();
+        self.set_modbus_slave_stop_flag(true);
+        self.modbus_slave_widgets_running(false);
+        if let Some(dialog) = self.transfer_dialog.take() {
+            dialog.close();
+        }
         self.write_widgets_enable(false);
+        self.control_line_widgets_enable(false);
         self.port_widgets_enable(true);
         self.open_close_button.get().unwrap().set_label("Open Port");
         self.port_refresh_button.get().unwrap().clicked();
@@ -1,4 +1,18 @@
+mod decoder;
+mod export;
+mod highlight;
 mod imp;
+mod modbus;
+mod nmea;
+mod plot;
+mod script;
+mod scrollback;
+mod search;
+mod sequence;
+mod stats;
+mod transcript;
+mod transfer;
+mod trigger;
 
 use gtk::glib;
 
@@ -0,0 +1,474 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use std::time::Duration;
+use futures::channel::oneshot;
+
+use crate::escape;
+use crate::model;
+use crate::modbus::{self, FunctionCode, ModbusJob, Response, Transaction};
+use crate::modbus::slave::{self, SlaveEvent, SlaveJob, Table};
+use crate::my_tools::*;
+use crate::port::PortCommand;
+
+use super::imp::{append_text_columns, MainWindow};
+
+#[derive(Debug)]
+pub(super) struct ModbusPanel {
+    window: gtk::Window,
+    slave_id_spin_button: gtk::SpinButton,
+    function_combo_box: gtk::ComboBoxText,
+    address_spin_button: gtk::SpinButton,
+    count_spin_button: gtk::SpinButton,
+    values_entry: gtk::Entry,
+    timeout_spin_button: gtk::SpinButton,
+    send_button: gtk::Button,
+    status_label: gtk::Label,
+    frames_label: gtk::Label,
+    register_model: gtk::ListStore,
+}
+
+#[derive(Debug)]
+pub(super) struct ModbusSlavePanel {
+    window: gtk::Window,
+    slave_id_spin_button: gtk::SpinButton,
+    start_button: gtk::Button,
+    stop_button: gtk::Button,
+    map_model: gtk::ListStore,
+    log_view: gtk::TextView,
+}
+
+impl MainWindow {
+    pub(super) fn on_modbus_master_activate(&self) {
+        let panel = self.modbus_panel.get_or_init(|| self.build_modbus_panel());
+        panel.window.show_all();
+        panel.window.present();
+    }
+
+    fn build_modbus_panel(&self) -> ModbusPanel {
+        let obj = MainWindow::instance(self);
+        let window = gtk::Window::builder()
+            .title("Modbus Master")
+            .transient_for(&obj)
+            .default_width(450)
+            .default_height(500)
+            .build();
+        window.connect_delete_event(|window, _| window.hide_on_delete());
+
+        let slave_id_spin_button = gtk::SpinButton::with_range(1.0, 247.0, 1.0);
+        let function_combo_box = gtk::ComboBoxText::new();
+        for function in FunctionCode::all() {
+            function_combo_box.append_text(function.name());
+        }
+        function_combo_box.set_active(Some(2));
+        let address_spin_button = gtk::SpinButton::with_range(0.0, 65535.0, 1.0);
+        let count_spin_button = gtk::SpinButton::with_range(1.0, modbus::MAX_READ_BITS as f64, 1.0);
+        count_spin_button.set_value(10.0);
+        let values_entry = gtk::Entry::builder()
+            .placeholder_text("e.g. 1, 2, 0x10 (coils: 0 or 1)")
+            .sensitive(false)
+            .build();
+        let timeout_spin_button = gtk::SpinButton::with_range(50.0, 10000.0, 50.0);
+        timeout_spin_button.set_value(1000.0);
+        let send_button = gtk::Button::with_label("Send");
+
+        function_combo_box.connect_changed(clone!(@weak count_spin_button, @weak values_entry => move |combo| {
+            let is_write = combo.active()
+                .and_then(|i| FunctionCode::all().get(i as usize).copied())
+                .map_or(false, |f| f.is_write());
+            count_spin_button.set_sensitive(!is_write);
+            values_entry.set_sensitive(is_write);
+        }));
+        send_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_modbus_send_clicked();
+        }));
+
+        let grid = gtk::Grid::builder()
+            .row_spacing(5)
+            .column_spacing(5)
+            .margin(5)
+            .build();
+        let label = |text: &str| gtk::Label::builder().label(text).xalign(0.0).build();
+        grid.attach(&label("Slave ID:"), 0, 0, 1, 1);
+        grid.attach(&slave_id_spin_button, 1, 0, 1, 1);
+        grid.attach(&label("Function:"), 2, 0, 1, 1);
+        grid.attach(&function_combo_box, 3, 0, 1, 1);
+        grid.attach(&label("Address:"), 0, 1, 1, 1);
+        grid.attach(&address_spin_button, 1, 1, 1, 1);
+        grid.attach(&label("Count:"), 2, 1, 1, 1);
+        grid.attach(&count_spin_button, 3, 1, 1, 1);
+        grid.attach(&label("Values:"), 0, 2, 1, 1);
+        grid.attach(&values_entry, 1, 2, 3, 1);
+        grid.attach(&label("Timeout (ms):"), 0, 3, 1, 1);
+        grid.attach(&timeout_spin_button, 1, 3, 1, 1);
+        grid.attach(&send_button, 3, 3, 1, 1);
+
+        let status_label = gtk::Label::builder()
+            .xalign(0.0)
+            .margin_start(5)
+            .margin_end(5)
+            .build();
+        let frames_label = gtk::Label::builder()
+            .xalign(0.0)
+            .selectable(true)
+            .wrap(true)
+            .margin_start(5)
+            .margin_end(5)
+            .build();
+
+        let register_model = model::create_register_model();
+        let tree_view = gtk::TreeView::with_model(&register_model);
+        append_text_columns(&tree_view, &["Address", "Value", "Hex"]);
+        let scrolled_window = gtk::ScrolledWindow::builder()
+            .child(&tree_view)
+            .margin(5)
+            .build();
+
+        let main_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        main_box.pack_start(&grid, false, false, 0);
+        main_box.pack_start(&status_label, false, false, 0);
+        main_box.pack_start(&frames_label, false, false, 0);
+        main_box.pack_start(&scrolled_window, true, true, 0);
+        window.add(&main_box);
+
+        ModbusPanel {
+            window,
+            slave_id_spin_button,
+            function_combo_box,
+            address_spin_button,
+            count_spin_button,
+            values_entry,
+            timeout_spin_button,
+            send_button,
+            status_label,
+            frames_label,
+            register_model,
+        }
+    }
+
+    fn modbus_request(&self) -> Result<modbus::Request, String> {
+        let panel = self.modbus_panel.get().unwrap();
+        let function = panel.function_combo_box.active()
+            .and_then(|i| FunctionCode::all().get(i as usize).copied())
+            .ok_or_else(|| String::from("Please select a function"))?;
+        let values = if function.is_write() {
+            modbus::parse_values(&panel.values_entry.text())?
+        } else {
+            Vec::new()
+        };
+        modbus::Request::new(
+            panel.slave_id_spin_button.value_as_int() as u8,
+            function,
+            panel.address_spin_button.value_as_int() as u16,
+            panel.count_spin_button.value_as_int() as u16,
+            values,
+        )
+    }
+
+    fn on_modbus_send_clicked(&self) {
+        let panel = self.modbus_panel.get().unwrap();
+        let request = if self.write_tx.borrow().is_none() {
+            Err(String::from("Please open a port first!"))
+        } else {
+            self.modbus_request()
+        };
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => {
+                let window = panel.window.clone();
+                glib::MainContext::default().spawn_local(async move {
+                    show_alert_dialog(&window, e).await;
+                });
+                return;
+            }
+        };
+
+        let address = request.address;
+        let timeout = Duration::from_millis(panel.timeout_spin_button.value_as_int() as u64);
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send_port_command(PortCommand::Modbus(ModbusJob { request, timeout, reply_tx }));
+        panel.send_button.set_sensitive(false);
+        panel.status_label.set_text("Waiting for the response...");
+
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_modbus_transaction(address, reply_rx.await.ok());
+        }));
+    }
+
+    // None if the port was closed before the request went out
+    fn on_modbus_transaction(&self, address: u16, transaction: Option<Transaction>) {
+        let panel = self.modbus_panel.get().unwrap();
+        panel.send_button.set_sensitive(true);
+        let transaction = match transaction {
+            Some(transaction) => transaction,
+            None => {
+                panel.status_label.style_context().add_class("warning");
+                panel.status_label.set_text("The port is closed");
+                return;
+            }
+        };
+
+        panel.frames_label.set_text(&format!(
+            "TX: {}\nRX: {}",
+            escape::format_hex(&transaction.request_frame),
+            escape::format_hex(&transaction.response_frame)
+        ));
+        let style_context = panel.status_label.style_context();
+        if transaction.result.is_err() {
+            style_context.add_class("warning");
+        } else {
+            style_context.remove_class("warning");
+        }
+        let status = match transaction.result {
+            Ok(Response::Bits(bits)) => {
+                panel.register_model.clear();
+                for (i, bit) in bits.iter().enumerate() {
+                    model::add_register_item(&panel.register_model, address as u32 + i as u32, (*bit as u8).to_string(), String::new());
+                }
+                format!("OK, {} bits read", bits.len())
+            }
+            Ok(Response::Registers(registers)) => {
+                panel.register_model.clear();
+                for (i, value) in registers.iter().enumerate() {
+                    model::add_register_item(&panel.register_model, address as u32 + i as u32, value.to_string(), format!("0x{:04X}", value));
+                }
+                format!("OK, {} registers read", registers.len())
+            }
+            Ok(Response::Written(address, value)) => format!("OK, written (address {}, value/quantity {})", address, value),
+            Err(e) => e.to_string(),
+        };
+        panel.status_label.set_text(&status);
+    }
+
+    pub(super) fn set_modbus_slave_stop_flag(&self, flag: bool) {
+        let mut modbus_slave_stop_flag = self.modbus_slave_stop_flag.lock().unwrap();
+        *modbus_slave_stop_flag = flag;
+    }
+
+    pub(super) fn on_modbus_slave_activate(&self) {
+        let panel = self.modbus_slave_panel.get_or_init(|| self.build_modbus_slave_panel());
+        self.refresh_slave_map_model(true);
+        panel.window.show_all();
+        panel.window.present();
+    }
+
+    fn build_modbus_slave_panel(&self) -> ModbusSlavePanel {
+        let obj = MainWindow::instance(self);
+        let window = gtk::Window::builder()
+            .title("Modbus Slave Simulator")
+            .transient_for(&obj)
+            .default_width(450)
+            .default_height(550)
+            .build();
+        window.connect_delete_event(|window, _| window.hide_on_delete());
+
+        let button_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .margin(5)
+            .spacing(5)
+            .build();
+        let slave_id_label = gtk::Label::new(Some("Slave ID:"));
+        let slave_id_spin_button = gtk::SpinButton::with_range(1.0, 247.0, 1.0);
+        let edit_button = gtk::Button::with_label("Edit Map...");
+        let start_button = gtk::Button::with_label("Start");
+        let stop_button = gtk::Button::builder()
+            .label("Stop")
+            .sensitive(false)
+            .build();
+        edit_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_modbus_slave_edit_clicked();
+        }));
+        start_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_modbus_slave_start_clicked();
+        }));
+        stop_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.set_modbus_slave_stop_flag(true);
+        }));
+        button_box.pack_start(&slave_id_label, false, false, 0);
+        button_box.pack_start(&slave_id_spin_button, false, false, 0);
+        button_box.pack_start(&edit_button, false, false, 0);
+        button_box.pack_end(&stop_button, false, false, 0);
+        button_box.pack_end(&start_button, false, false, 0);
+
+        let map_model = model::create_slave_map_model();
+        let tree_view = gtk::TreeView::with_model(&map_model);
+        let renderers = append_text_columns(&tree_view, &["Table", "Address", "Value"]);
+        renderers[2].set_editable(true);
+        renderers[2].connect_edited(clone!(@weak obj => move |_, path, text| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_slave_map_value_edited(path, text);
+        }));
+
+        let log_view = gtk::TextView::builder()
+            .monospace(true)
+            .editable(false)
+            .cursor_visible(false)
+            .build();
+        let paned = gtk::Paned::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .position(250)
+            .margin(5)
+            .build();
+        paned.pack1(&gtk::ScrolledWindow::builder().child(&tree_view).build(), true, false);
+        paned.pack2(&gtk::ScrolledWindow::builder().child(&log_view).build(), true, false);
+
+        let main_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        main_box.pack_start(&button_box, false, false, 0);
+        main_box.pack_start(&paned, true, true, 0);
+        window.add(&main_box);
+
+        ModbusSlavePanel { window, slave_id_spin_button, start_button, stop_button, map_model, log_view }
+    }
+
+    // `rebuild` after addresses were added or removed, otherwise only the values are updated
+    fn refresh_slave_map_model(&self, rebuild: bool) {
+        let panel = self.modbus_slave_panel.get().unwrap();
+        let map = self.modbus_slave_map.lock().unwrap();
+        if rebuild {
+            panel.map_model.clear();
+            for (table, address, value) in map.iter() {
+                model::add_slave_map_item(&panel.map_model, table.name(), address as u32, value.to_string());
+            }
+            return;
+        }
+        for (i, (_, _, value)) in map.iter().enumerate() {
+            let iter = match panel.map_model.iter_nth_child(None, i as i32) {
+                Some(iter) => iter,
+                None => break,
+            };
+            let value = value.to_string();
+            // leave unchanged rows alone, so an edit in progress is not interrupted
+            if panel.map_model.value(&iter, 2).get::<String>().ok().as_ref() != Some(&value) {
+                panel.map_model.set_value(&iter, 2, &value.to_value());
+            }
+        }
+    }
+
+    fn on_slave_map_value_edited(&self, path: gtk::TreePath, text: &str) {
+        let panel = self.modbus_slave_panel.get().unwrap();
+        let iter = match panel.map_model.iter(&path) {
+            Some(iter) => iter,
+            None => return,
+        };
+        let table = panel.map_model.value(&iter, 0).get::<String>().ok().and_then(|name| Table::from_name(&name));
+        let address = panel.map_model.value(&iter, 1).get::<u32>().ok();
+        let values = modbus::parse_values(text).unwrap_or_default();
+        if let (Some(table), Some(address), &[value]) = (table, address, values.as_slice()) {
+            let mut map = self.modbus_slave_map.lock().unwrap();
+            map.set(table, address as u16, value);
+            if let Err(e) = slave::save_slave_map(&map) {
+                eprintln!("Failed to save the slave map: {}", e);
+            }
+        }
+        self.refresh_slave_map_model(false);
+    }
+
+    fn on_modbus_slave_edit_clicked(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let panel = priv_.modbus_slave_panel.get().unwrap();
+            let text = slave::format_slave_map(&priv_.modbus_slave_map.lock().unwrap());
+            let hint = "One address per line: <table> <address> [value], table is coil, discrete, holding or input\n\
+                        e.g. holding 0 0x1234, requests to addresses not listed get an Illegal Data Address exception";
+            let text = match show_text_edit_dialog(&panel.window, "Modbus Slave Map", hint, &text).await {
+                Some(text) => text,
+                None => return,
+            };
+            let map = match slave::parse_slave_map(&text) {
+                Ok(map) => map,
+                Err(e) => {
+                    show_alert_dialog(&panel.window, e).await;
+                    return;
+                }
+            };
+            if let Err(e) = slave::save_slave_map(&map) {
+                show_alert_dialog(&panel.window, format!("Failed to save the slave map: {}", e)).await;
+            }
+            *priv_.modbus_slave_map.lock().unwrap() = map;
+            priv_.refresh_slave_map_model(true);
+        }));
+    }
+
+    fn on_modbus_slave_start_clicked(&self) {
+        let panel = self.modbus_slave_panel.get().unwrap();
+        if self.write_tx.borrow().is_none() {
+            let window = panel.window.clone();
+            glib::MainContext::default().spawn_local(async move {
+                show_alert_dialog(&window, String::from("Please open a port first!")).await;
+            });
+            return;
+        }
+
+        let obj = MainWindow::instance(self);
+        let (event_tx, event_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        event_rx.attach(
+            None,
+            clone!(@weak obj => @default-return glib::Continue(false),
+                move |event| {
+                    let priv_ = MainWindow::from_instance(&obj);
+                    priv_.on_modbus_slave_event(event);
+                    glib::Continue(true)
+                }
+            )
+        );
+
+        let slave_id = panel.slave_id_spin_button.value_as_int() as u8;
+        self.set_modbus_slave_stop_flag(false);
+        // the port loop serves the slave until it stops, a repeat would only pile up behind it
+        self.stop_repeat();
+        let is_sent = self.send_port_command(PortCommand::ModbusSlave(SlaveJob {
+            slave_id,
+            map: self.modbus_slave_map.clone(),
+            stop_flag: self.modbus_slave_stop_flag.clone(),
+            event_tx,
+        }));
+        if !is_sent {
+            return;
+        }
+        self.modbus_slave_widgets_running(true);
+        self.append_modbus_slave_log(&format!("Serving as slave {}", slave_id));
+    }
+
+    fn on_modbus_slave_event(&self, event: SlaveEvent) {
+        match event {
+            SlaveEvent::Log(text) => {
+                self.append_modbus_slave_log(&text);
+                self.refresh_slave_map_model(false);
+            }
+            SlaveEvent::Stopped(reason) => {
+                self.modbus_slave_widgets_running(false);
+                self.append_modbus_slave_log(&reason);
+            }
+        }
+    }
+
+    fn append_modbus_slave_log(&self, text: &str) {
+        let panel = self.modbus_slave_panel.get().unwrap();
+        if let Some(buffer) = panel.log_view.buffer() {
+            let mut end_iter = buffer.end_iter();
+            buffer.insert(&mut end_iter, &format!("[{}] {}\n", current_timestamp_string(), text));
+            panel.log_view.scroll_to_iter(&mut end_iter, 0.0, false, 0.0, 0.0);
+        }
+    }
+
+    pub(super) fn modbus_slave_widgets_running(&self, running: bool) {
+        if let Some(panel) = self.modbus_slave_panel.get() {
+            panel.start_button.set_sensitive(!running);
+            panel.stop_button.set_sensitive(running);
+            panel.slave_id_spin_button.set_sensitive(!running);
+        }
+        // anything sent meanwhile would be queued and go out when the slave stops
+        if self.is_port_opened.get() {
+            self.write_widgets_enable(!running);
+        }
+    }
+}
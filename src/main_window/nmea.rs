@@ -0,0 +1,150 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use crate::model;
+use crate::my_tools::*;
+use crate::nmea::{NmeaError, NmeaState};
+use crate::port::{DataDirection, Output};
+
+use super::imp::{append_text_columns, MainWindow};
+
+#[derive(Debug)]
+pub(super) struct NmeaPanel {
+    window: gtk::Window,
+    summary_model: gtk::ListStore,
+    satellite_model: gtk::ListStore,
+    log_view: gtk::TextView,
+}
+
+impl MainWindow {
+    pub(super) fn on_nmea_decoder_activate(&self) {
+        let panel = self.nmea_panel.get_or_init(|| self.build_nmea_panel());
+        self.refresh_nmea_panel();
+        panel.window.show_all();
+        panel.window.present();
+    }
+
+    fn build_nmea_panel(&self) -> NmeaPanel {
+        let obj = MainWindow::instance(self);
+        let window = gtk::Window::builder()
+            .title("NMEA Decoder")
+            .transient_for(&obj)
+            .default_width(650)
+            .default_height(550)
+            .build();
+        window.connect_delete_event(|window, _| window.hide_on_delete());
+
+        let button_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .margin(5)
+            .spacing(5)
+            .build();
+        let hint_label = gtk::Label::new(Some("Decodes GGA, RMC, GSA and GSV sentences while this window is open"));
+        let reset_button = gtk::Button::with_label("Reset");
+        reset_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_nmea_reset_clicked();
+        }));
+        button_box.pack_start(&hint_label, false, false, 0);
+        button_box.pack_end(&reset_button, false, false, 0);
+
+        let summary_model = model::create_nmea_summary_model();
+        let summary_view = gtk::TreeView::with_model(&summary_model);
+        append_text_columns(&summary_view, &["Field", "Value"]);
+        let satellite_model = model::create_satellite_model();
+        let satellite_view = gtk::TreeView::with_model(&satellite_model);
+        append_text_columns(&satellite_view, &["PRN", "Elevation", "Azimuth", "SNR", "Used"]);
+        let tables = gtk::Paned::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .position(300)
+            .build();
+        tables.pack1(&gtk::ScrolledWindow::builder().child(&summary_view).build(), true, false);
+        tables.pack2(&gtk::ScrolledWindow::builder().child(&satellite_view).build(), true, false);
+
+        let log_view = gtk::TextView::builder()
+            .monospace(true)
+            .editable(false)
+            .cursor_visible(false)
+            .build();
+        if let Some(tag_table) = log_view.buffer().and_then(|b| b.tag_table()) {
+            tag_table.add(&gtk::TextTag::builder().name("warning").foreground("#e67e22").build());
+        }
+        let paned = gtk::Paned::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .position(380)
+            .margin(5)
+            .build();
+        paned.pack1(&tables, true, false);
+        paned.pack2(&gtk::ScrolledWindow::builder().child(&log_view).build(), true, false);
+
+        let main_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        main_box.pack_start(&button_box, false, false, 0);
+        main_box.pack_start(&paned, true, true, 0);
+        window.add(&main_box);
+
+        NmeaPanel { window, summary_model, satellite_model, log_view }
+    }
+
+    fn on_nmea_reset_clicked(&self) {
+        let panel = self.nmea_panel.get().unwrap();
+        self.nmea_state.replace(NmeaState::default());
+        if let Some(buffer) = panel.log_view.buffer() {
+            buffer.set_text("");
+        }
+        self.refresh_nmea_panel();
+    }
+
+    // received lines are only decoded while the decoder window is shown
+    pub(super) fn decode_nmea(&self, output: &Output) {
+        let panel = match self.nmea_panel.get() {
+            Some(panel) if panel.window.is_visible() => panel,
+            _ => return,
+        };
+        let mut updated = false;
+        let mut state = self.nmea_state.borrow_mut();
+        for (_, text) in output.iter().filter(|(direction, _)| *direction == DataDirection::Rx) {
+            for line in text.lines() {
+                match state.update(line) {
+                    Ok(()) => updated = true,
+                    Err(NmeaError::NotNmea) => {}
+                    Err(e) => {
+                        updated = true;
+                        if let Some(buffer) = panel.log_view.buffer() {
+                            let text = format!("[{}] {}  ({})\n", current_timestamp_string(), line, e);
+                            let mut end_iter = buffer.end_iter();
+                            buffer.insert_with_tags_by_name(&mut end_iter, &text, &["warning"]);
+                            panel.log_view.scroll_to_iter(&mut end_iter, 0.0, false, 0.0, 0.0);
+                        }
+                    }
+                }
+            }
+        }
+        drop(state);
+        if updated {
+            self.refresh_nmea_panel();
+        }
+    }
+
+    fn refresh_nmea_panel(&self) {
+        let panel = self.nmea_panel.get().unwrap();
+        let state = self.nmea_state.borrow();
+        panel.summary_model.clear();
+        for (name, value) in state.summary() {
+            model::add_nmea_summary_item(&panel.summary_model, name, &value);
+        }
+        let text = |value: Option<u32>| value.map(|v| v.to_string()).unwrap_or_default();
+        panel.satellite_model.clear();
+        for ((talker, prn), satellite) in state.satellites.iter() {
+            model::add_satellite_item(
+                &panel.satellite_model,
+                &format!("{} {}", talker, prn),
+                &text(satellite.elevation),
+                &text(satellite.azimuth),
+                &text(satellite.snr),
+                if state.used_prns.contains(prn) { "✓" } else { "" },
+            );
+        }
+    }
+}
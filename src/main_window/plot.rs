@@ -0,0 +1,207 @@
+use glib::clone;
+use gtk::gdk;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use regex::Regex;
+
+use crate::my_tools::*;
+use crate::plot::{self, LineFormat};
+use crate::port::{DataDirection, Output};
+
+use super::imp::MainWindow;
+
+#[derive(Debug)]
+pub(super) struct PlotPanel {
+    window: gtk::Window,
+    format_combo_box: gtk::ComboBoxText,
+    regex_entry: gtk::Entry,
+    span_spin_button: gtk::SpinButton,
+    status_label: gtk::Label,
+    drawing_area: gtk::DrawingArea,
+}
+
+impl MainWindow {
+    pub(super) fn on_plotter_activate(&self) {
+        let panel = self.plot_panel.get_or_init(|| self.build_plot_panel());
+        panel.window.show_all();
+        panel.window.present();
+    }
+
+    fn build_plot_panel(&self) -> PlotPanel {
+        let obj = MainWindow::instance(self);
+        let window = gtk::Window::builder()
+            .title("Plotter")
+            .transient_for(&obj)
+            .default_width(800)
+            .default_height(450)
+            .build();
+        window.connect_delete_event(|window, _| window.hide_on_delete());
+
+        let format_combo_box = gtk::ComboBoxText::new();
+        for name in ["CSV", "key=value", "Regex"] {
+            format_combo_box.append_text(name);
+        }
+        format_combo_box.set_active(Some(0));
+        let regex_entry = gtk::Entry::builder()
+            .placeholder_text("e.g. T=(?P<temp>[-\\d.]+)")
+            .sensitive(false)
+            .build();
+        let pause_toggle_button = gtk::ToggleButton::with_label("Pause");
+        let span_spin_button = gtk::SpinButton::with_range(0.5, 3600.0, 1.0);
+        span_spin_button.set_digits(1);
+        span_spin_button.set_value(10.0);
+        span_spin_button.set_tooltip_text(Some("Seconds shown, scroll over the plot to zoom"));
+        let clear_button = gtk::Button::with_label("Clear");
+        let export_button = gtk::Button::with_label("Export CSV...");
+        let status_label = gtk::Label::new(None);
+
+        format_combo_box.connect_changed(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_plot_format_changed();
+        }));
+        regex_entry.connect_changed(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_plot_format_changed();
+        }));
+        pause_toggle_button.connect_toggled(clone!(@weak obj => move |button| {
+            let priv_ = MainWindow::from_instance(&obj);
+            let paused_at = if button.is_active() { Some(priv_.plot_data.borrow().now()) } else { None };
+            priv_.plot_paused_at.set(paused_at);
+            priv_.refresh_plot_panel();
+        }));
+        span_spin_button.connect_value_changed(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.refresh_plot_panel();
+        }));
+        clear_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.plot_data.borrow_mut().clear();
+            priv_.refresh_plot_panel();
+        }));
+        export_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_plot_export_clicked();
+        }));
+
+        let drawing_area = gtk::DrawingArea::new();
+        drawing_area.add_events(gdk::EventMask::SCROLL_MASK | gdk::EventMask::SMOOTH_SCROLL_MASK);
+        drawing_area.connect_draw(clone!(@weak obj => @default-return gtk::Inhibit(false),
+            move |area, cr| {
+                let priv_ = MainWindow::from_instance(&obj);
+                let panel = priv_.plot_panel.get().unwrap();
+                let data = priv_.plot_data.borrow();
+                let end = priv_.plot_paused_at.get().unwrap_or_else(|| data.now());
+                let span = panel.span_spin_button.value();
+                let (width, height) = (area.allocated_width() as f64, area.allocated_height() as f64);
+                let _ = plot::draw(cr, width, height, &data, end.max(span), span);
+                gtk::Inhibit(true)
+            }
+        ));
+        drawing_area.connect_scroll_event(clone!(@weak span_spin_button => @default-return gtk::Inhibit(false),
+            move |_, event| {
+                let zoom_in = match event.direction() {
+                    gdk::ScrollDirection::Up => true,
+                    gdk::ScrollDirection::Down => false,
+                    gdk::ScrollDirection::Smooth => event.delta().1 < 0.0,
+                    _ => return gtk::Inhibit(false),
+                };
+                let factor = if zoom_in { 1.0 / 1.25 } else { 1.25 };
+                span_spin_button.set_value(span_spin_button.value() * factor);
+                gtk::Inhibit(true)
+            }
+        ));
+
+        let button_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .margin(5)
+            .spacing(5)
+            .build();
+        button_box.pack_start(&format_combo_box, false, false, 0);
+        button_box.pack_start(&regex_entry, true, true, 0);
+        button_box.pack_start(&gtk::Label::new(Some("Span (s):")), false, false, 0);
+        button_box.pack_start(&span_spin_button, false, false, 0);
+        button_box.pack_start(&pause_toggle_button, false, false, 0);
+        button_box.pack_start(&clear_button, false, false, 0);
+        button_box.pack_start(&export_button, false, false, 0);
+
+        status_label.set_halign(gtk::Align::Start);
+        status_label.set_margin_start(5);
+        status_label.set_margin_bottom(5);
+        let main_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        main_box.pack_start(&button_box, false, false, 0);
+        main_box.pack_start(&drawing_area, true, true, 0);
+        main_box.pack_start(&status_label, false, false, 0);
+        window.add(&main_box);
+
+        PlotPanel { window, format_combo_box, regex_entry, span_spin_button, status_label, drawing_area }
+    }
+
+    fn on_plot_format_changed(&self) {
+        let panel = self.plot_panel.get().unwrap();
+        let style_context = panel.status_label.style_context();
+        style_context.remove_class("warning");
+        let format = match panel.format_combo_box.active() {
+            Some(1) => LineFormat::KeyValue,
+            Some(2) => match Regex::new(&panel.regex_entry.text()) {
+                Ok(regex) => LineFormat::Regex(regex),
+                Err(e) => {
+                    style_context.add_class("warning");
+                    panel.status_label.set_text(&format!("Invalid regex: {}", e));
+                    panel.regex_entry.set_sensitive(true);
+                    return;
+                }
+            },
+            _ => LineFormat::Csv,
+        };
+        panel.regex_entry.set_sensitive(matches!(format, LineFormat::Regex(_)));
+        self.plot_format.replace(format);
+        self.refresh_plot_panel();
+    }
+
+    fn on_plot_export_clicked(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let window = priv_.plot_panel.get().unwrap().window.clone();
+            let files = show_file_chooser_dialog(&window, "Export Plot Data (.csv)", gtk::FileChooserAction::Save, false).await;
+            let path = match files.first() {
+                Some(path) => path,
+                None => return,
+            };
+            let csv = priv_.plot_data.borrow().to_csv();
+            if let Err(e) = std::fs::write(path, csv) {
+                show_alert_dialog(&window, format!("Failed to export plot data: {}", e)).await;
+            }
+        }));
+    }
+
+    // received lines are only plotted while the plotter window is shown
+    pub(super) fn plot_output(&self, output: &Output) {
+        match self.plot_panel.get() {
+            Some(panel) if panel.window.is_visible() => {}
+            _ => return,
+        }
+        let format = self.plot_format.borrow();
+        let mut data = self.plot_data.borrow_mut();
+        for (_, text) in output.iter().filter(|(direction, _)| *direction == DataDirection::Rx) {
+            for line in text.lines() {
+                data.add(plot::parse_line(&format, line));
+            }
+        }
+        drop(data);
+        self.refresh_plot_panel();
+    }
+
+    fn refresh_plot_panel(&self) {
+        let panel = self.plot_panel.get().unwrap();
+        // keep an invalid regex message until it is fixed
+        if !panel.status_label.style_context().has_class("warning") {
+            let data = self.plot_data.borrow();
+            panel.status_label.set_text(&format!("{} series, {} samples", data.names.len(), data.samples.len()));
+        }
+        // a paused plot keeps its time range, so new samples stay out of view
+        panel.drawing_area.queue_draw();
+    }
+}
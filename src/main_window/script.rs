@@ -0,0 +1,217 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use std::sync::mpsc;
+
+use crate::my_tools::*;
+use crate::script::{self, ScriptMessage};
+
+use super::imp::MainWindow;
+
+#[derive(Debug)]
+pub(super) struct ScriptPanel {
+    window: gtk::Window,
+    text_view: gtk::TextView,
+    log_view: gtk::TextView,
+    run_button: gtk::Button,
+    stop_button: gtk::Button,
+}
+
+impl MainWindow {
+    fn set_script_cancel_flag(&self, flag: bool) {
+        let mut script_cancel_flag = self.script_cancel_flag.lock().unwrap();
+        *script_cancel_flag = flag;
+    }
+
+    pub(super) fn on_script_panel_activate(&self) {
+        let panel = self.script_panel.get_or_init(|| self.build_script_panel());
+        panel.window.show_all();
+        panel.window.present();
+    }
+
+    fn build_script_panel(&self) -> ScriptPanel {
+        let obj = MainWindow::instance(self);
+        let window = gtk::Window::builder()
+            .title("Script")
+            .transient_for(&obj)
+            .default_width(600)
+            .default_height(500)
+            .build();
+        window.connect_delete_event(|window, _| window.hide_on_delete());
+
+        let button_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .margin(5)
+            .spacing(5)
+            .build();
+        let open_button = gtk::Button::with_label("Open...");
+        let save_button = gtk::Button::with_label("Save...");
+        let run_button = gtk::Button::with_label("Run");
+        let stop_button = gtk::Button::builder()
+            .label("Stop")
+            .sensitive(false)
+            .build();
+        open_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_script_open_clicked();
+        }));
+        save_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_script_save_clicked();
+        }));
+        run_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_script_run_clicked();
+        }));
+        stop_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.set_script_cancel_flag(true);
+        }));
+        button_box.pack_start(&open_button, false, false, 0);
+        button_box.pack_start(&save_button, false, false, 0);
+        button_box.pack_end(&stop_button, false, false, 0);
+        button_box.pack_end(&run_button, false, false, 0);
+
+        let help_label = gtk::Label::builder()
+            .label(script::SCRIPT_HELP)
+            .xalign(0.0)
+            .wrap(true)
+            .margin_start(5)
+            .margin_end(5)
+            .build();
+
+        let text_view = gtk::TextView::builder()
+            .monospace(true)
+            .build();
+        let log_view = gtk::TextView::builder()
+            .monospace(true)
+            .editable(false)
+            .cursor_visible(false)
+            .build();
+        let paned = gtk::Paned::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .position(300)
+            .margin(5)
+            .build();
+        paned.pack1(&gtk::ScrolledWindow::builder().child(&text_view).build(), true, false);
+        paned.pack2(&gtk::ScrolledWindow::builder().child(&log_view).build(), true, false);
+
+        let main_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        main_box.pack_start(&button_box, false, false, 0);
+        main_box.pack_start(&help_label, false, false, 0);
+        main_box.pack_start(&paned, true, true, 0);
+        window.add(&main_box);
+
+        ScriptPanel { window, text_view, log_view, run_button, stop_button }
+    }
+
+    fn on_script_open_clicked(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let panel = priv_.script_panel.get().unwrap();
+            let files = show_file_chooser_dialog(&panel.window, "Open Script", gtk::FileChooserAction::Open, false).await;
+            let path = match files.first() {
+                Some(path) => path,
+                None => return,
+            };
+            match std::fs::read_to_string(path) {
+                Ok(text) => {
+                    if let Some(buffer) = panel.text_view.buffer() {
+                        buffer.set_text(&text);
+                    }
+                }
+                Err(e) => show_alert_dialog(&panel.window, format!("Failed to open script: {}", e)).await,
+            }
+        }));
+    }
+
+    fn on_script_save_clicked(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let panel = priv_.script_panel.get().unwrap();
+            let files = show_file_chooser_dialog(&panel.window, "Save Script", gtk::FileChooserAction::Save, false).await;
+            let path = match files.first() {
+                Some(path) => path,
+                None => return,
+            };
+            if let Err(e) = std::fs::write(path, priv_.script_source()) {
+                show_alert_dialog(&panel.window, format!("Failed to save script: {}", e)).await;
+            }
+        }));
+    }
+
+    fn script_source(&self) -> String {
+        let panel = self.script_panel.get().unwrap();
+        match panel.text_view.buffer() {
+            Some(buffer) => {
+                let (start, end) = buffer.bounds();
+                buffer.text(&start, &end, false).map(|s| s.to_string()).unwrap_or_default()
+            }
+            None => String::new(),
+        }
+    }
+
+    fn on_script_run_clicked(&self) {
+        let panel = self.script_panel.get().unwrap();
+        let write_tx = match self.write_tx.borrow().as_ref() {
+            Some(write_tx) => write_tx.clone(),
+            None => {
+                let window = panel.window.clone();
+                glib::MainContext::default().spawn_local(async move {
+                    show_alert_dialog(&window, String::from("Please open a port first!")).await;
+                });
+                return;
+            }
+        };
+
+        if let Some(buffer) = panel.log_view.buffer() {
+            buffer.set_text("");
+        }
+        panel.run_button.set_sensitive(false);
+        panel.stop_button.set_sensitive(true);
+        self.set_script_cancel_flag(false);
+
+        let (line_tx, line_rx) = mpsc::channel();
+        self.script_line_tx.replace(Some(line_tx));
+
+        let obj = MainWindow::instance(self);
+        let (message_tx, message_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        message_rx.attach(
+            None,
+            clone!(@weak obj => @default-return glib::Continue(false),
+                move |message| {
+                    let priv_ = MainWindow::from_instance(&obj);
+                    priv_.on_script_message(message);
+                    glib::Continue(true)
+                }
+            )
+        );
+        let prompt = self.port_prompt.clone();
+        script::spawn_script(self.script_source(), write_tx, line_rx, prompt, self.script_cancel_flag.clone(), message_tx);
+    }
+
+    fn on_script_message(&self, message: ScriptMessage) {
+        let panel = self.script_panel.get().unwrap();
+        let text = match message {
+            ScriptMessage::Log(s) => s,
+            ScriptMessage::Done(result) => {
+                self.script_line_tx.replace(None);
+                panel.run_button.set_sensitive(true);
+                panel.stop_button.set_sensitive(false);
+                match result {
+                    Ok(()) => String::from("Script passed"),
+                    Err(e) => format!("Script failed: {}", e),
+                }
+            }
+        };
+        if let Some(buffer) = panel.log_view.buffer() {
+            let mut end_iter = buffer.end_iter();
+            buffer.insert(&mut end_iter, &format!("{}\n", text));
+            panel.log_view.scroll_to_iter(&mut end_iter, 0.0, false, 0.0, 0.0);
+        }
+    }
+}
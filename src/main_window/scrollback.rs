@@ -0,0 +1,119 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use crate::my_tools::*;
+use crate::scrollback::{self, ScrollbackLimit};
+
+use super::imp::MainWindow;
+
+impl MainWindow {
+    pub(super) fn on_scrollback_limit_activate(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let dialog = gtk::Dialog::builder()
+                .transient_for(&obj)
+                .modal(true)
+                .title("Scrollback Limit")
+                .window_position(gtk::WindowPosition::CenterOnParent)
+                .build();
+            dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+            dialog.add_button("OK", gtk::ResponseType::Ok);
+
+            let (unit, value) = priv_.scrollback_limit.get().unit();
+            let value_spin_button = gtk::SpinButton::with_range(1.0, 1_000_000_000.0, 1000.0);
+            value_spin_button.set_value(if value > 0 { value as f64 } else { 100_000.0 });
+            value_spin_button.set_sensitive(unit != 0);
+            let unit_combo_box = gtk::ComboBoxText::new();
+            for name in ScrollbackLimit::unit_names() {
+                unit_combo_box.append_text(name);
+            }
+            unit_combo_box.set_active(Some(unit as u32));
+            unit_combo_box.connect_changed(clone!(@weak value_spin_button => move |combo| {
+                value_spin_button.set_sensitive(combo.active() != Some(0));
+            }));
+
+            let limit_box = gtk::Box::builder()
+                .orientation(gtk::Orientation::Horizontal)
+                .margin(10)
+                .spacing(5)
+                .build();
+            limit_box.pack_start(&gtk::Label::new(Some("Keep at most:")), false, false, 0);
+            limit_box.pack_start(&value_spin_button, true, true, 0);
+            limit_box.pack_start(&unit_combo_box, false, false, 0);
+            dialog.content_area().pack_start(&limit_box, true, true, 0);
+            dialog.show_all();
+
+            let answer = dialog.run_future().await;
+            let unit = unit_combo_box.active().unwrap_or(0) as usize;
+            let limit = ScrollbackLimit::from_unit(unit, value_spin_button.value_as_int() as usize);
+            dialog.close();
+            if answer != gtk::ResponseType::Ok {
+                return;
+            }
+
+            priv_.scrollback_limit.set(limit);
+            if let Some(buffer) = priv_.read_text_view.get().unwrap().buffer() {
+                priv_.trim_scrollback(&buffer);
+            }
+            if let Err(e) = scrollback::save_limit(limit) {
+                show_alert_dialog(&obj, format!("Failed to save scrollback limit: {}", e)).await;
+            }
+        }));
+    }
+
+    // drop the oldest lines once the buffer grows past the scrollback limit
+    pub(super) fn trim_scrollback(&self, buffer: &gtk::TextBuffer) {
+        let line_count = buffer.line_count();
+        let output_bytes = self.output_bytes.get();
+        let trim_lines = match self.scrollback_limit.get() {
+            ScrollbackLimit::Lines(limit) if line_count as usize > limit => {
+                line_count - ScrollbackLimit::trim_target(limit) as i32
+            }
+            ScrollbackLimit::Bytes(limit) if output_bytes > limit => {
+                let excess = output_bytes - ScrollbackLimit::trim_target(limit);
+                let mut bytes = 0;
+                let mut lines = 0;
+                while bytes < excess && lines < line_count {
+                    bytes += buffer.iter_at_line(lines).bytes_in_line() as usize;
+                    lines += 1;
+                }
+                lines
+            }
+            _ => 0,
+        };
+        if trim_lines <= 0 {
+            return;
+        }
+
+        let mut start = buffer.start_iter();
+        let mut end = buffer.iter_at_line(trim_lines);
+        if trim_lines >= line_count {
+            end = buffer.end_iter();
+        }
+        let removed_bytes = buffer.text(&start, &end, true).map_or(0, |text| text.len());
+        let removed_chars = end.offset();
+        buffer.delete(&mut start, &mut end);
+        self.output_bytes.set(output_bytes.saturating_sub(removed_bytes));
+
+        // search matches are kept as offsets, move them along with the text
+        let mut search_matches = self.search_matches.borrow_mut();
+        let removed_matches = search_matches.iter().take_while(|m| m.0 < removed_chars).count();
+        search_matches.drain(..removed_matches);
+        for m in search_matches.iter_mut() {
+            m.0 -= removed_chars;
+            m.1 -= removed_chars;
+        }
+        drop(search_matches);
+        let index = match self.current_match_index.get() {
+            Some(i) if i >= removed_matches => Some(i - removed_matches),
+            _ => None,
+        };
+        self.current_match_index.set(index);
+        if removed_matches > 0 {
+            self.update_search_result_label();
+        }
+    }
+}
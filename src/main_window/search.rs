@@ -0,0 +1,120 @@
+use gtk::prelude::*;
+
+use crate::search::{self, FilterMode};
+
+use super::imp::MainWindow;
+
+impl MainWindow {
+    fn filter_mode(&self) -> FilterMode {
+        let index = self.filter_combo_box.get().unwrap().active().unwrap_or(0) as usize;
+        FilterMode::all().get(index).copied().unwrap_or(FilterMode::Off)
+    }
+
+    pub(super) fn on_search_changed(&self) {
+        let pattern = self.search_entry.get().unwrap().text().to_string();
+        let mut matcher = None;
+        if self.search_bar.get().unwrap().is_search_mode() && !pattern.is_empty() {
+            let case_sensitive = self.match_case_check_button.get().unwrap().is_active();
+            let use_regex = self.regex_check_button.get().unwrap().is_active();
+            match search::build_matcher(&pattern, case_sensitive, use_regex) {
+                Ok(m) => matcher = Some(m),
+                Err(_) => {
+                    self.search_result_label.get().unwrap().set_text("Invalid pattern");
+                }
+            }
+        }
+        let is_valid = matcher.is_some() || pattern.is_empty();
+        self.search_matcher.replace(matcher);
+        self.current_match_index.set(None);
+
+        let text_view = self.read_text_view.get().unwrap();
+        if let Some(buffer) = text_view.buffer() {
+            let (start, end) = buffer.bounds();
+            buffer.remove_tag_by_name("search_match", &start, &end);
+            buffer.remove_tag_by_name("search_current", &start, &end);
+            buffer.remove_tag_by_name("filtered_out", &start, &end);
+            self.search_matches.borrow_mut().clear();
+            self.decorate_text(&buffer, 0);
+        }
+        if is_valid {
+            self.update_search_result_label();
+        }
+    }
+
+    // highlight matches and apply the line filter from `start_offset` to the end of the buffer
+    pub(super) fn decorate_text(&self, buffer: &gtk::TextBuffer, start_offset: i32) {
+        let matcher = self.search_matcher.borrow();
+        let matcher = match matcher.as_ref() {
+            Some(m) => m,
+            None => return,
+        };
+
+        let start = buffer.iter_at_offset(start_offset);
+        let end = buffer.end_iter();
+        let text = match buffer.text(&start, &end, true) {
+            Some(text) => text.to_string(),
+            None => return,
+        };
+
+        let mut search_matches = self.search_matches.borrow_mut();
+        for (match_start, match_end) in search::find_matches(matcher, &text) {
+            let range = (start_offset + match_start, start_offset + match_end);
+            buffer.apply_tag_by_name("search_match", &buffer.iter_at_offset(range.0), &buffer.iter_at_offset(range.1));
+            search_matches.push(range);
+        }
+
+        let mode = self.filter_mode();
+        if mode != FilterMode::Off {
+            for line in start.line()..buffer.line_count() {
+                let line_start = buffer.iter_at_line(line);
+                let mut line_end = line_start.clone();
+                line_end.forward_line();
+                if let Some(line_text) = buffer.text(&line_start, &line_end, true) {
+                    if !mode.is_visible(matcher, line_text.trim_end_matches('\n')) {
+                        buffer.apply_tag_by_name("filtered_out", &line_start, &line_end);
+                    }
+                }
+            }
+        }
+    }
+
+    pub(super) fn update_search_result_label(&self) {
+        let count = self.search_matches.borrow().len();
+        let text = match (self.search_matcher.borrow().is_some(), self.current_match_index.get()) {
+            (false, _) => String::from(""),
+            (true, Some(index)) => format!("{} of {} matches", index + 1, count),
+            (true, None) => format!("{} matches", count),
+        };
+        self.search_result_label.get().unwrap().set_text(&text);
+    }
+
+    pub(super) fn select_next_match(&self, forward: bool) {
+        let search_matches = self.search_matches.borrow();
+        if search_matches.is_empty() {
+            return;
+        }
+        let count = search_matches.len();
+        let index = match (self.current_match_index.get(), forward) {
+            (None, true) => 0,
+            (None, false) => count - 1,
+            (Some(i), true) => (i + 1) % count,
+            (Some(i), false) => (i + count - 1) % count,
+        };
+        self.current_match_index.set(Some(index));
+
+        let text_view = self.read_text_view.get().unwrap();
+        if let Some(buffer) = text_view.buffer() {
+            let (start, end) = buffer.bounds();
+            buffer.remove_tag_by_name("search_current", &start, &end);
+
+            let (match_start, match_end) = search_matches[index];
+            let mut match_start = buffer.iter_at_offset(match_start);
+            let match_end = buffer.iter_at_offset(match_end);
+            buffer.apply_tag_by_name("search_current", &match_start, &match_end);
+            buffer.select_range(&match_start, &match_end);
+            text_view.scroll_to_iter(&mut match_start, 0.1, false, 0.0, 0.0);
+        }
+        drop(search_matches);
+        self.update_search_result_label();
+    }
+}
@@ -0,0 +1,108 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use crate::my_tools::*;
+use crate::port::PortCommand;
+use crate::sequence::{self, Sequence};
+
+use super::imp::MainWindow;
+
+impl MainWindow {
+    pub(super) fn rebuild_sequence_menu(&self) {
+        let menu = self.sequence_menu.get().unwrap();
+        for child in menu.children() {
+            menu.remove(&child);
+        }
+
+        let obj = MainWindow::instance(self);
+        let groups = [sequence::predefined_sequences(), self.user_sequences.borrow().clone()];
+        for sequences in groups.iter().filter(|g| !g.is_empty()) {
+            for seq in sequences {
+                let item = gtk::MenuItem::with_label(&seq.name);
+                let spec = seq.spec.clone();
+                item.connect_activate(clone!(@weak obj => move |_| {
+                    let priv_ = MainWindow::from_instance(&obj);
+                    priv_.run_sequence(&spec);
+                }));
+                menu.append(&item);
+            }
+            menu.append(&gtk::SeparatorMenuItem::new());
+        }
+
+        let edit_item = gtk::MenuItem::with_label("Edit Sequences...");
+        edit_item.connect_activate(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_edit_sequences_activate();
+        }));
+        menu.append(&edit_item);
+        menu.show_all();
+    }
+
+    fn run_sequence(&self, spec: &str) {
+        let dialog_text = if !self.is_port_opened.get() {
+            String::from("Please open a port first!")
+        } else {
+            match sequence::parse_sequence(spec) {
+                Ok(steps) => {
+                    let (dtr, rts) = sequence::final_levels(&steps);
+                    self.send_port_command(PortCommand::RunSequence(steps));
+                    // reflect the final levels, the resulting SetDtr/SetRts commands change nothing
+                    if let Some(level) = dtr {
+                        self.dtr_toggle_button.get().unwrap().set_active(level);
+                    }
+                    if let Some(level) = rts {
+                        self.rts_toggle_button.get().unwrap().set_active(level);
+                    }
+                    return;
+                }
+                Err(e) => format!("Invalid sequence: {}", e),
+            }
+        };
+
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            show_alert_dialog(&obj, dialog_text).await;
+        }));
+    }
+
+    fn on_edit_sequences_activate(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let text = priv_.user_sequences.borrow().iter()
+                .map(|s| format!("{} = {}", s.name, s.spec))
+                .collect::<Vec<String>>()
+                .join("\n");
+            let hint = "One sequence per line: <name> = <steps>\n\
+                        Steps: D0/D1 set DTR, R0/R1 set RTS, W<seconds> wait, e.g. D0|R1|W0.1|D1|R0|W0.05|D0";
+            let text = match show_text_edit_dialog(&obj, "Reset Sequences", hint, &text).await {
+                Some(text) => text,
+                None => return,
+            };
+
+            let mut sequences = Vec::new();
+            for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+                let (name, spec) = line.rsplit_once('=').unwrap_or(("", line));
+                let (name, spec) = (name.trim(), spec.trim());
+                let error = match sequence::parse_sequence(spec) {
+                    Ok(_) if name.is_empty() => Some(String::from("Missing name")),
+                    Ok(_) => None,
+                    Err(e) => Some(e),
+                };
+                if let Some(e) = error {
+                    show_alert_dialog(&obj, format!("{}\n\n{}", e, line)).await;
+                    return;
+                }
+                sequences.push(Sequence::new(name, spec));
+            }
+
+            if let Err(e) = sequence::save_user_sequences(&sequences) {
+                show_alert_dialog(&obj, format!("Failed to save sequences: {}", e)).await;
+            }
+            priv_.user_sequences.replace(sequences);
+            priv_.rebuild_sequence_menu();
+        }));
+    }
+}
@@ -0,0 +1,80 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use crate::model;
+
+use super::imp::{append_text_columns, MainWindow};
+
+#[derive(Debug)]
+pub(super) struct StatsPanel {
+    window: gtk::Window,
+    model: gtk::ListStore,
+}
+
+impl MainWindow {
+    pub(super) fn on_statistics_activate(&self) {
+        let panel = self.stats_panel.get_or_init(|| self.build_stats_panel());
+        self.refresh_stats_panel();
+        panel.window.show_all();
+        panel.window.present();
+    }
+
+    fn build_stats_panel(&self) -> StatsPanel {
+        let obj = MainWindow::instance(self);
+        let window = gtk::Window::builder()
+            .title("Statistics")
+            .transient_for(&obj)
+            .default_width(400)
+            .default_height(420)
+            .build();
+        window.connect_delete_event(|window, _| window.hide_on_delete());
+
+        let button_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .margin(5)
+            .spacing(5)
+            .build();
+        let hint_label = gtk::Label::new(Some("Line mode traffic, updated every second"));
+        let reset_button = gtk::Button::with_label("Reset");
+        reset_button.connect_clicked(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.port_stats.lock().unwrap().reset();
+            priv_.refresh_stats_panel();
+        }));
+        button_box.pack_start(&hint_label, false, false, 0);
+        button_box.pack_end(&reset_button, false, false, 0);
+
+        let model = model::create_stats_model();
+        let view = gtk::TreeView::with_model(&model);
+        append_text_columns(&view, &["Counter", "Value"]);
+        let scrolled_window = gtk::ScrolledWindow::builder()
+            .child(&view)
+            .margin(5)
+            .build();
+
+        let main_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        main_box.pack_start(&button_box, false, false, 0);
+        main_box.pack_start(&scrolled_window, true, true, 0);
+        window.add(&main_box);
+
+        StatsPanel { window, model }
+    }
+
+    // the throughput is sampled while the panel is hidden too, for the peaks
+    pub(super) fn on_stats_timeout(&self) {
+        self.port_stats.lock().unwrap().sample();
+        if matches!(self.stats_panel.get(), Some(panel) if panel.window.is_visible()) {
+            self.refresh_stats_panel();
+        }
+    }
+
+    fn refresh_stats_panel(&self) {
+        let panel = self.stats_panel.get().unwrap();
+        panel.model.clear();
+        for (name, value) in self.port_stats.lock().unwrap().summary() {
+            model::add_stats_item(&panel.model, name, &value);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::my_tools::*;
+
+use super::imp::MainWindow;
+
+impl MainWindow {
+    pub(super) fn on_session_log_toggled(&self) {
+        let check_button = self.session_log_check_button.get().unwrap();
+        if !check_button.is_active() {
+            self.session_log.replace(None);
+            return;
+        }
+        if self.session_log.borrow().is_some() {
+            return;
+        }
+
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let check_button = priv_.session_log_check_button.get().unwrap();
+            let files = show_file_chooser_dialog(&obj, "Session Log", gtk::FileChooserAction::Save, false).await;
+            let path = match files.first() {
+                Some(path) => path,
+                None => {
+                    check_button.set_active(false);
+                    return;
+                }
+            };
+            match File::create(path) {
+                Ok(file) => {
+                    priv_.session_log.replace(Some(io::LineWriter::new(file)));
+                }
+                Err(e) => {
+                    check_button.set_active(false);
+                    show_alert_dialog(&obj, format!("Failed to create session log: {}", e)).await;
+                }
+            }
+        }));
+    }
+
+    // the session log keeps the full history, regardless of the scrollback limit
+    pub(super) fn write_session_log(&self, text: &str) {
+        let result = match self.session_log.borrow_mut().as_mut() {
+            Some(log) => log.write_all(text.as_bytes()),
+            None => return,
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to write session log: {}", e);
+            self.session_log.replace(None);
+            self.session_log_check_button.get().unwrap().set_active(false);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use crate::my_tools::*;
+use crate::port::PortCommand;
+use crate::xmodem::{Direction, Protocol, TransferJob};
+use crate::zmodem;
+
+use super::imp::MainWindow;
+
+impl MainWindow {
+    pub(super) fn set_transfer_cancel_flag(&self, flag: bool) {
+        let mut transfer_cancel_flag = self.transfer_cancel_flag.lock().unwrap();
+        *transfer_cancel_flag = flag;
+    }
+
+    pub(super) fn on_transfer_menu_item_activate(&self, protocol: Protocol, is_send: bool) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let (title, action) = match (is_send, protocol.is_batch()) {
+                (true, _) => ("Choose files to send", gtk::FileChooserAction::Open),
+                (false, true) => ("Choose a folder for received files", gtk::FileChooserAction::SelectFolder),
+                (false, false) => ("Save received file as", gtk::FileChooserAction::Save),
+            };
+            let select_multiple = is_send && protocol.is_batch();
+            let mut files = show_file_chooser_dialog(&obj, title, action, select_multiple).await;
+            if files.is_empty() {
+                return;
+            }
+
+            let direction = if is_send {
+                Direction::Send(files)
+            } else {
+                Direction::Receive(files.remove(0))
+            };
+            priv_.start_transfer(protocol, direction);
+        }));
+    }
+
+    pub(super) fn on_zmodem_detected(&self) {
+        // `sz` repeats its request while we are asking
+        if self.is_zmodem_prompt_shown.get() || self.transfer_dialog.borrow().is_some() {
+            return;
+        }
+        self.is_zmodem_prompt_shown.set(true);
+
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let message = String::from("Incoming ZMODEM transfer detected. Accept the files?");
+            let mut dirs = Vec::new();
+            if let gtk::ResponseType::Ok = show_question_dialog(&obj, message).await {
+                dirs = show_file_chooser_dialog(
+                    &obj,
+                    "Choose a folder for received files",
+                    gtk::FileChooserAction::SelectFolder,
+                    false
+                ).await;
+            }
+            priv_.is_zmodem_prompt_shown.set(false);
+
+            if dirs.is_empty() {
+                priv_.send_port_command(PortCommand::WriteBytes(zmodem::abort_sequence()));
+                return;
+            }
+            priv_.start_transfer(Protocol::Zmodem, Direction::Receive(dirs.remove(0)));
+        }));
+    }
+
+    fn start_transfer(&self, protocol: Protocol, direction: Direction) {
+        if self.write_tx.borrow().is_none() {
+            return;
+        }
+
+        let obj = MainWindow::instance(self);
+        let dialog = ProgressDialog::new(&obj, &format!("{} Transfer", protocol.name()));
+        dialog.connect_cancel(clone!(@weak obj => move || {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.set_transfer_cancel_flag(true);
+        }));
+        self.transfer_dialog.replace(Some(dialog));
+        self.write_widgets_enable(false);
+        self.set_transfer_cancel_flag(false);
+
+        let job = TransferJob {
+            protocol,
+            direction,
+            resume: self.is_zmodem_resume.get(),
+            cancel_flag: self.transfer_cancel_flag.clone(),
+        };
+        if !self.send_port_command(PortCommand::Transfer(job)) {
+            // the port is closing, `handle_close` takes care of the widgets
+            if let Some(dialog) = self.transfer_dialog.take() {
+                dialog.close();
+            }
+        }
+    }
+
+    pub(super) fn on_transfer_state_changed(&self, event: &str, value: String) {
+        if event == "transfer_done" {
+            if let Some(dialog) = self.transfer_dialog.take() {
+                dialog.close();
+            }
+            if self.is_port_opened.get() {
+                self.write_widgets_enable(true);
+            }
+
+            let dialog_text = if value == "ok" {
+                String::from("Transfer completed.")
+            } else {
+                format!("Transfer failed: {}", value)
+            };
+            let obj = MainWindow::instance(self);
+            glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+                show_alert_dialog(&obj, dialog_text).await;
+            }));
+            return;
+        }
+
+        if let Some(dialog) = self.transfer_dialog.borrow().as_ref() {
+            if event == "transfer_file" {
+                dialog.set_status(&value);
+            } else if event == "transfer_retry" {
+                dialog.set_retries(&value);
+            } else if event == "transfer_progress" {
+                //
+                // value format: current/total (total is 0 when unknown)
+                //
+                let mut parts = value.split('/').map(|s| s.parse::<u64>().unwrap_or(0));
+                let current = parts.next().unwrap_or(0);
+                let total = parts.next().unwrap_or(0);
+                dialog.set_progress(current, total);
+            }
+        }
+    }
+}
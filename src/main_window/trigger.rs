@@ -0,0 +1,85 @@
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+
+use crate::my_tools::*;
+use crate::trigger::{self, Trigger};
+
+use super::imp::MainWindow;
+
+impl MainWindow {
+    pub(super) fn set_triggers(&self, triggers: Vec<Trigger>) {
+        // shared with the port task, changes apply to an open port immediately
+        *self.triggers.lock().unwrap() = triggers;
+        self.rebuild_trigger_menu();
+    }
+
+    fn rebuild_trigger_menu(&self) {
+        let menu = self.trigger_menu.get().unwrap();
+        for child in menu.children() {
+            menu.remove(&child);
+        }
+
+        let obj = MainWindow::instance(self);
+        let triggers = self.triggers.lock().unwrap().clone();
+        for (i, t) in triggers.iter().enumerate() {
+            let item = gtk::CheckMenuItem::with_label(&format!("{} => {}", t.regex.as_str(), t.response));
+            item.set_active(t.enabled);
+            item.connect_toggled(clone!(@weak obj => move |item| {
+                let priv_ = MainWindow::from_instance(&obj);
+                priv_.on_trigger_toggled(i, item.is_active());
+            }));
+            menu.append(&item);
+        }
+        if !triggers.is_empty() {
+            menu.append(&gtk::SeparatorMenuItem::new());
+        }
+
+        let edit_item = gtk::MenuItem::with_label("Edit Triggers...");
+        edit_item.connect_activate(clone!(@weak obj => move |_| {
+            let priv_ = MainWindow::from_instance(&obj);
+            priv_.on_edit_triggers_activate();
+        }));
+        menu.append(&edit_item);
+        menu.show_all();
+    }
+
+    fn on_trigger_toggled(&self, index: usize, enabled: bool) {
+        let result = {
+            let mut triggers = self.triggers.lock().unwrap();
+            if let Some(t) = triggers.get_mut(index) {
+                t.enabled = enabled;
+            }
+            trigger::save_triggers(&triggers)
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to save triggers: {}", e);
+        }
+    }
+
+    fn on_edit_triggers_activate(&self) {
+        let obj = MainWindow::instance(self);
+        glib::MainContext::default().spawn_local(clone!(@weak obj => async move {
+            let priv_ = MainWindow::from_instance(&obj);
+            let text = trigger::format_triggers(&priv_.triggers.lock().unwrap());
+            let hint = "One trigger per line: [x] <regex> => <response>, `[ ]` disables a trigger\n\
+                        The response supports escapes such as \\r \\n \\x20, e.g. [x] login:\\s*$ => root\\r";
+            let text = match show_text_edit_dialog(&obj, "Triggers", hint, &text).await {
+                Some(text) => text,
+                None => return,
+            };
+            let triggers = match trigger::parse_triggers(&text) {
+                Ok(triggers) => triggers,
+                Err(e) => {
+                    show_alert_dialog(&obj, e).await;
+                    return;
+                }
+            };
+            if let Err(e) = trigger::save_triggers(&triggers) {
+                show_alert_dialog(&obj, format!("Failed to save triggers: {}", e)).await;
+            }
+            priv_.set_triggers(triggers);
+        }));
+    }
+}
@@ -0,0 +1,428 @@
+use std::fmt;
+use std::time::Duration;
+
+use bytes::BytesMut;
+use futures::channel::oneshot;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::{timeout_at, Instant};
+
+use crate::checksum::crc16_modbus;
+
+pub mod slave;
+
+//
+// Modbus RTU frame: slave id, function code, data, CRC-16/MODBUS (low byte first)
+//
+
+pub const MAX_READ_BITS: u16 = 2000;
+pub const MAX_READ_REGISTERS: u16 = 125;
+pub const MAX_WRITE_BITS: usize = 1968;
+pub const MAX_WRITE_REGISTERS: usize = 123;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FunctionCode {
+    ReadCoils,
+    ReadDiscreteInputs,
+    ReadHoldingRegisters,
+    ReadInputRegisters,
+    WriteSingleCoil,
+    WriteSingleRegister,
+    WriteMultipleCoils,
+    WriteMultipleRegisters,
+}
+
+impl FunctionCode {
+    pub fn all() -> [FunctionCode; 8] {
+        [
+            FunctionCode::ReadCoils,
+            FunctionCode::ReadDiscreteInputs,
+            FunctionCode::ReadHoldingRegisters,
+            FunctionCode::ReadInputRegisters,
+            FunctionCode::WriteSingleCoil,
+            FunctionCode::WriteSingleRegister,
+            FunctionCode::WriteMultipleCoils,
+            FunctionCode::WriteMultipleRegisters,
+        ]
+    }
+
+    pub fn from_code(code: u8) -> Option<FunctionCode> {
+        FunctionCode::all().iter().find(|f| f.code() == code).copied()
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            FunctionCode::ReadCoils => 0x01,
+            FunctionCode::ReadDiscreteInputs => 0x02,
+            FunctionCode::ReadHoldingRegisters => 0x03,
+            FunctionCode::ReadInputRegisters => 0x04,
+            FunctionCode::WriteSingleCoil => 0x05,
+            FunctionCode::WriteSingleRegister => 0x06,
+            FunctionCode::WriteMultipleCoils => 0x0f,
+            FunctionCode::WriteMultipleRegisters => 0x10,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FunctionCode::ReadCoils => "01 Read Coils",
+            FunctionCode::ReadDiscreteInputs => "02 Read Discrete Inputs",
+            FunctionCode::ReadHoldingRegisters => "03 Read Holding Registers",
+            FunctionCode::ReadInputRegisters => "04 Read Input Registers",
+            FunctionCode::WriteSingleCoil => "05 Write Single Coil",
+            FunctionCode::WriteSingleRegister => "06 Write Single Register",
+            FunctionCode::WriteMultipleCoils => "15 Write Multiple Coils",
+            FunctionCode::WriteMultipleRegisters => "16 Write Multiple Registers",
+        }
+    }
+
+    pub fn is_write(&self) -> bool {
+        matches!(self,
+            FunctionCode::WriteSingleCoil
+            | FunctionCode::WriteSingleRegister
+            | FunctionCode::WriteMultipleCoils
+            | FunctionCode::WriteMultipleRegisters)
+    }
+
+    // coils and discrete inputs are bits, everything else is 16-bit registers
+    pub fn is_bits(&self) -> bool {
+        matches!(self,
+            FunctionCode::ReadCoils
+            | FunctionCode::ReadDiscreteInputs
+            | FunctionCode::WriteSingleCoil
+            | FunctionCode::WriteMultipleCoils)
+    }
+}
+
+pub fn exception_name(code: u8) -> &'static str {
+    match code {
+        0x01 => "Illegal Function",
+        0x02 => "Illegal Data Address",
+        0x03 => "Illegal Data Value",
+        0x04 => "Slave Device Failure",
+        0x05 => "Acknowledge",
+        0x06 => "Slave Device Busy",
+        0x08 => "Memory Parity Error",
+        0x0a => "Gateway Path Unavailable",
+        0x0b => "Gateway Target Device Failed to Respond",
+        _ => "Unknown Exception",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub slave_id: u8,
+    pub function: FunctionCode,
+    pub address: u16,
+    // number of coils or registers to read, unused by writes
+    pub count: u16,
+    // coils (0 or 1) or registers to write, unused by reads
+    pub values: Vec<u16>,
+}
+
+impl Request {
+    pub fn new(slave_id: u8, function: FunctionCode, address: u16, count: u16, values: Vec<u16>) -> Result<Self, String> {
+        let request = Request { slave_id, function, address, count, values };
+        request.validate()?;
+        Ok(request)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let (max, quantity) = match self.function {
+            FunctionCode::ReadCoils | FunctionCode::ReadDiscreteInputs => (MAX_READ_BITS as usize, self.count as usize),
+            FunctionCode::ReadHoldingRegisters | FunctionCode::ReadInputRegisters => (MAX_READ_REGISTERS as usize, self.count as usize),
+            FunctionCode::WriteSingleCoil | FunctionCode::WriteSingleRegister => (1, self.values.len()),
+            FunctionCode::WriteMultipleCoils => (MAX_WRITE_BITS, self.values.len()),
+            FunctionCode::WriteMultipleRegisters => (MAX_WRITE_REGISTERS, self.values.len()),
+        };
+        if quantity == 0 || quantity > max {
+            let unit = if self.function.is_bits() { "coils" } else { "registers" };
+            return Err(format!("{} takes 1 to {} {}, got {}", self.function.name(), max, unit, quantity));
+        }
+        if self.function.is_bits() && self.function.is_write() && self.values.iter().any(|v| *v > 1) {
+            return Err(String::from("Coil values must be 0 or 1"));
+        }
+        if self.address as usize + quantity > 0x10000 {
+            return Err(String::from("The address range goes past 65535"));
+        }
+        Ok(())
+    }
+
+    pub fn to_frame(&self) -> Vec<u8> {
+        let mut frame = vec![self.slave_id, self.function.code()];
+        frame.extend_from_slice(&self.address.to_be_bytes());
+        match self.function {
+            FunctionCode::ReadCoils
+            | FunctionCode::ReadDiscreteInputs
+            | FunctionCode::ReadHoldingRegisters
+            | FunctionCode::ReadInputRegisters => frame.extend_from_slice(&self.count.to_be_bytes()),
+            FunctionCode::WriteSingleCoil => {
+                let value: u16 = if self.values[0] != 0 { 0xff00 } else { 0x0000 };
+                frame.extend_from_slice(&value.to_be_bytes());
+            }
+            FunctionCode::WriteSingleRegister => frame.extend_from_slice(&self.values[0].to_be_bytes()),
+            FunctionCode::WriteMultipleCoils => {
+                let bytes = pack_bits(&self.values);
+                frame.extend_from_slice(&(self.values.len() as u16).to_be_bytes());
+                frame.push(bytes.len() as u8);
+                frame.extend_from_slice(&bytes);
+            }
+            FunctionCode::WriteMultipleRegisters => {
+                frame.extend_from_slice(&(self.values.len() as u16).to_be_bytes());
+                frame.push((self.values.len() * 2) as u8);
+                for value in &self.values {
+                    frame.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+        with_crc(frame)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Bits(Vec<bool>),
+    Registers(Vec<u16>),
+    // a write echoed back: address and value (05/06) or quantity written (15/16)
+    Written(u16, u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModbusError {
+    Timeout,
+    Crc,
+    Exception(u8),
+    Invalid(String),
+    Io(String),
+}
+
+impl fmt::Display for ModbusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModbusError::Timeout => write!(f, "Timed out waiting for the response"),
+            ModbusError::Crc => write!(f, "CRC error"),
+            ModbusError::Exception(code) => write!(f, "Exception {:02X}: {}", code, exception_name(*code)),
+            ModbusError::Invalid(s) => write!(f, "Invalid response: {}", s),
+            ModbusError::Io(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+// values such as `1, 2, 0x10` or `1 0 1 1`
+pub fn parse_values(s: &str) -> Result<Vec<u16>, String> {
+    s.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            let value = match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => token.parse::<u16>(),
+            };
+            value.map_err(|_| format!("Invalid value: {}", token))
+        })
+        .collect()
+}
+
+fn pack_bits(bits: &[u16]) -> Vec<u8> {
+    let mut bytes = vec![0u8; bits.len().div_ceil(8)];
+    for (i, bit) in bits.iter().enumerate() {
+        if *bit != 0 {
+            bytes[i / 8] |= 1 << (i % 8);
+        }
+    }
+    bytes
+}
+
+fn with_crc(mut frame: Vec<u8>) -> Vec<u8> {
+    let crc = crc16_modbus(&frame);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+pub fn check_crc(frame: &[u8]) -> bool {
+    if frame.len() < 4 {
+        return false;
+    }
+    let (data, crc) = frame.split_at(frame.len() - 2);
+    crc16_modbus(data).to_le_bytes() == crc
+}
+
+fn read_u16(frame: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([frame[offset], frame[offset + 1]])
+}
+
+// length of the response to `function`, None until enough of it arrived to tell
+fn response_len(function: FunctionCode, head: &[u8]) -> Option<usize> {
+    if head.len() < 2 {
+        return None;
+    }
+    if head[1] & 0x80 != 0 {
+        return Some(5);
+    }
+    if function.is_write() {
+        return Some(8);
+    }
+    head.get(2).map(|byte_count| 5 + *byte_count as usize)
+}
+
+pub fn parse_response(request: &Request, frame: &[u8]) -> Result<Response, ModbusError> {
+    if frame.len() < 5 {
+        return Err(ModbusError::Invalid(String::from("Frame too short")));
+    }
+    if !check_crc(frame) {
+        return Err(ModbusError::Crc);
+    }
+    if frame[0] != request.slave_id {
+        return Err(ModbusError::Invalid(format!("Unexpected slave id {}", frame[0])));
+    }
+    let code = request.function.code();
+    if frame[1] == code | 0x80 {
+        return Err(ModbusError::Exception(frame[2]));
+    }
+    if frame[1] != code {
+        return Err(ModbusError::Invalid(format!("Unexpected function code {:02X}", frame[1])));
+    }
+
+    if request.function.is_write() {
+        if frame.len() != 8 {
+            return Err(ModbusError::Invalid(String::from("Wrong length")));
+        }
+        return Ok(Response::Written(read_u16(frame, 2), read_u16(frame, 4)));
+    }
+
+    let byte_count = frame[2] as usize;
+    let data = &frame[3..frame.len() - 2];
+    let count = request.count as usize;
+    if data.len() != byte_count {
+        return Err(ModbusError::Invalid(String::from("Byte count does not match the frame length")));
+    }
+    if request.function.is_bits() {
+        if byte_count != count.div_ceil(8) {
+            return Err(ModbusError::Invalid(format!("Expected {} bytes, got {}", count.div_ceil(8), byte_count)));
+        }
+        let bits = (0..count).map(|i| data[i / 8] & (1 << (i % 8)) != 0).collect();
+        Ok(Response::Bits(bits))
+    } else {
+        if byte_count != count * 2 {
+            return Err(ModbusError::Invalid(format!("Expected {} bytes, got {}", count * 2, byte_count)));
+        }
+        let registers = (0..count).map(|i| read_u16(data, i * 2)).collect();
+        Ok(Response::Registers(registers))
+    }
+}
+
+#[derive(Debug)]
+pub struct Transaction {
+    pub request_frame: Vec<u8>,
+    // whatever was received, even if incomplete
+    pub response_frame: Vec<u8>,
+    pub result: Result<Response, ModbusError>,
+}
+
+#[derive(Debug)]
+pub struct ModbusJob {
+    pub request: Request,
+    pub timeout: Duration,
+    pub reply_tx: oneshot::Sender<Transaction>,
+}
+
+pub async fn run_request<S>(port: &mut S, job: ModbusJob)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request_frame = job.request.to_frame();
+    let transaction = match port.write_all(&request_frame).await {
+        Ok(()) => {
+            let (response_frame, result) = read_response(port, &job.request, job.timeout).await;
+            Transaction { request_frame, response_frame, result }
+        }
+        Err(e) => Transaction { request_frame, response_frame: Vec::new(), result: Err(ModbusError::Io(e.to_string())) },
+    };
+    let _ = job.reply_tx.send(transaction);
+}
+
+async fn read_response<S>(port: &mut S, request: &Request, wait: Duration) -> (Vec<u8>, Result<Response, ModbusError>)
+where
+    S: AsyncRead + Unpin,
+{
+    let deadline = Instant::now() + wait;
+    let mut buf = BytesMut::new();
+    loop {
+        if let Some(len) = response_len(request.function, &buf) {
+            if buf.len() >= len {
+                // anything after the frame is not ours
+                buf.truncate(len);
+                let result = parse_response(request, &buf);
+                return (buf.to_vec(), result);
+            }
+        }
+        let mut chunk = [0u8; 256];
+        match timeout_at(deadline, port.read(&mut chunk)).await {
+            Ok(Ok(0)) => return (buf.to_vec(), Err(ModbusError::Io(String::from("Port closed")))),
+            Ok(Ok(n)) => buf.extend_from_slice(&chunk[..n]),
+            Ok(Err(e)) => return (buf.to_vec(), Err(ModbusError::Io(e.to_string()))),
+            Err(_) => return (buf.to_vec(), Err(ModbusError::Timeout)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // read 10 holding registers from address 0 of slave 1, from the Modbus specification
+    const READ_REQUEST: [u8; 8] = [0x01, 0x03, 0x00, 0x00, 0x00, 0x0a, 0xc5, 0xcd];
+
+    #[test]
+    fn request_frame() {
+        let request = Request::new(1, FunctionCode::ReadHoldingRegisters, 0, 10, Vec::new()).unwrap();
+        assert_eq!(request.to_frame(), READ_REQUEST);
+        assert!(check_crc(&READ_REQUEST));
+        assert!(!check_crc(&READ_REQUEST[..7]));
+    }
+
+    #[test]
+    fn request_validation() {
+        assert!(Request::new(1, FunctionCode::ReadHoldingRegisters, 0, 0, Vec::new()).is_err());
+        assert!(Request::new(1, FunctionCode::ReadHoldingRegisters, 0, 126, Vec::new()).is_err());
+        assert!(Request::new(1, FunctionCode::ReadCoils, 65535, 2, Vec::new()).is_err());
+        assert!(Request::new(1, FunctionCode::WriteMultipleCoils, 0, 0, vec![1, 2]).is_err());
+    }
+
+    #[test]
+    fn write_coils_frame() {
+        let request = Request::new(1, FunctionCode::WriteMultipleCoils, 0x13, 0, vec![1, 0, 1, 1, 0, 0, 1, 1, 1, 0]).unwrap();
+        let frame = request.to_frame();
+        assert_eq!(frame[..9], [0x01, 0x0f, 0x00, 0x13, 0x00, 0x0a, 0x02, 0xcd, 0x01]);
+        assert!(check_crc(&frame));
+    }
+
+    #[test]
+    fn registers_response() {
+        let request = Request::new(1, FunctionCode::ReadHoldingRegisters, 0, 2, Vec::new()).unwrap();
+        let frame = with_crc(vec![0x01, 0x03, 0x04, 0x00, 0x0a, 0x12, 0x34]);
+        assert_eq!(parse_response(&request, &frame), Ok(Response::Registers(vec![10, 0x1234])));
+
+        let mut corrupted = frame.clone();
+        corrupted[4] ^= 0xff;
+        assert_eq!(parse_response(&request, &corrupted), Err(ModbusError::Crc));
+        let short = with_crc(vec![0x01, 0x03, 0x02, 0x00, 0x0a]);
+        assert!(matches!(parse_response(&request, &short), Err(ModbusError::Invalid(_))));
+    }
+
+    #[test]
+    fn bits_and_exception_responses() {
+        let request = Request::new(1, FunctionCode::ReadCoils, 0, 10, Vec::new()).unwrap();
+        let frame = with_crc(vec![0x01, 0x01, 0x02, 0x05, 0x02]);
+        let bits = vec![true, false, true, false, false, false, false, false, false, true];
+        assert_eq!(parse_response(&request, &frame), Ok(Response::Bits(bits)));
+
+        let exception = with_crc(vec![0x01, 0x81, 0x02]);
+        assert_eq!(parse_response(&request, &exception), Err(ModbusError::Exception(0x02)));
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_values("1, 2 0x10").unwrap(), vec![1, 2, 16]);
+        assert!(parse_values("1, x").is_err());
+        assert!(parse_values("65536").is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout;
+
+use crate::config;
+use crate::escape;
+use super::{check_crc, parse_values, read_u16, with_crc, FunctionCode, ModbusError, Request};
+
+const SLAVE_MAP_FILE: &str = "modbus_slave.txt";
+
+// how often the stop flag is checked, a partial frame older than this is dropped
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Table {
+    Coils,
+    DiscreteInputs,
+    HoldingRegisters,
+    InputRegisters,
+}
+
+impl Table {
+    pub fn all() -> [Table; 4] {
+        [Table::Coils, Table::DiscreteInputs, Table::HoldingRegisters, Table::InputRegisters]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Table::Coils => "coil",
+            Table::DiscreteInputs => "discrete",
+            Table::HoldingRegisters => "holding",
+            Table::InputRegisters => "input",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Table> {
+        Table::all().iter().find(|t| t.name() == name).copied()
+    }
+
+    pub fn is_bits(&self) -> bool {
+        matches!(self, Table::Coils | Table::DiscreteInputs)
+    }
+
+    fn of(function: FunctionCode) -> Table {
+        match function {
+            FunctionCode::ReadCoils | FunctionCode::WriteSingleCoil | FunctionCode::WriteMultipleCoils => Table::Coils,
+            FunctionCode::ReadDiscreteInputs => Table::DiscreteInputs,
+            FunctionCode::ReadHoldingRegisters
+            | FunctionCode::WriteSingleRegister
+            | FunctionCode::WriteMultipleRegisters => Table::HoldingRegisters,
+            FunctionCode::ReadInputRegisters => Table::InputRegisters,
+        }
+    }
+}
+
+//
+// The addresses served by the simulator, requests touching any other
+// address are answered with an Illegal Data Address exception.
+//
+#[derive(Debug, Clone, Default)]
+pub struct SlaveMap {
+    values: BTreeMap<(Table, u16), u16>,
+}
+
+impl SlaveMap {
+    pub fn get(&self, table: Table, address: u16) -> Option<u16> {
+        self.values.get(&(table, address)).copied()
+    }
+
+    pub fn set(&mut self, table: Table, address: u16, value: u16) {
+        let value = if table.is_bits() { (value != 0) as u16 } else { value };
+        self.values.insert((table, address), value);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Table, u16, u16)> + '_ {
+        self.values.iter().map(|((table, address), value)| (*table, *address, *value))
+    }
+
+    fn contains_range(&self, table: Table, address: u16, count: usize) -> bool {
+        (0..count).all(|i| self.values.contains_key(&(table, address.wrapping_add(i as u16))))
+    }
+
+    // the response frame to `request`, an exception response if it cannot be served
+    pub fn serve(&mut self, request: &Request) -> Vec<u8> {
+        let mut frame = vec![request.slave_id, request.function.code()];
+        match self.execute(request, &mut frame) {
+            Ok(()) => with_crc(frame),
+            Err(exception) => with_crc(vec![request.slave_id, request.function.code() | 0x80, exception]),
+        }
+    }
+
+    fn execute(&mut self, request: &Request, frame: &mut Vec<u8>) -> Result<(), u8> {
+        let table = Table::of(request.function);
+        let count = if request.function.is_write() { request.values.len() } else { request.count as usize };
+        if !self.contains_range(table, request.address, count) {
+            return Err(ILLEGAL_DATA_ADDRESS);
+        }
+        let address = request.address;
+        match request.function {
+            FunctionCode::ReadCoils | FunctionCode::ReadDiscreteInputs => {
+                let bits: Vec<u16> = (0..count).map(|i| self.get(table, address + i as u16).unwrap_or(0)).collect();
+                let bytes = super::pack_bits(&bits);
+                frame.push(bytes.len() as u8);
+                frame.extend_from_slice(&bytes);
+            }
+            FunctionCode::ReadHoldingRegisters | FunctionCode::ReadInputRegisters => {
+                frame.push((count * 2) as u8);
+                for i in 0..count {
+                    frame.extend_from_slice(&self.get(table, address + i as u16).unwrap_or(0).to_be_bytes());
+                }
+            }
+            FunctionCode::WriteSingleCoil | FunctionCode::WriteSingleRegister => {
+                self.set(table, address, request.values[0]);
+                let value = match request.function {
+                    FunctionCode::WriteSingleCoil if request.values[0] != 0 => 0xff00,
+                    FunctionCode::WriteSingleCoil => 0x0000,
+                    _ => request.values[0],
+                };
+                frame.extend_from_slice(&address.to_be_bytes());
+                frame.extend_from_slice(&value.to_be_bytes());
+            }
+            FunctionCode::WriteMultipleCoils | FunctionCode::WriteMultipleRegisters => {
+                for (i, value) in request.values.iter().enumerate() {
+                    self.set(table, address + i as u16, *value);
+                }
+                frame.extend_from_slice(&address.to_be_bytes());
+                frame.extend_from_slice(&(count as u16).to_be_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_slave_map(text: &str) -> Result<SlaveMap, String> {
+    //
+    // one address per line: <table> <address> [value]
+    //   table is coil, discrete, holding or input; the value defaults to 0
+    //   e.g. holding 0 0x1234
+    //        coil 10 1
+    //
+    let mut map = SlaveMap::default();
+    for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let table = Table::from_name(fields[0]).ok_or_else(|| format!("Unknown table `{}`: {}", fields[0], line))?;
+        let numbers = parse_values(&fields[1..].join(" ")).map_err(|e| format!("{}: {}", e, line))?;
+        match numbers[..] {
+            [address] => map.set(table, address, 0),
+            [address, value] => map.set(table, address, value),
+            _ => return Err(format!("Expected <table> <address> [value]: {}", line)),
+        }
+    }
+    Ok(map)
+}
+
+pub fn format_slave_map(map: &SlaveMap) -> String {
+    map.iter()
+        .map(|(table, address, value)| format!("{} {} {}\n", table.name(), address, value))
+        .collect()
+}
+
+pub fn load_slave_map() -> SlaveMap {
+    let mut map = SlaveMap::default();
+    for record in config::load_records(SLAVE_MAP_FILE).iter().filter(|record| record.len() >= 3) {
+        if let (Some(table), Ok(address), Ok(value)) = (Table::from_name(&record[0]), record[1].parse(), record[2].parse()) {
+            map.set(table, address, value);
+        }
+    }
+    map
+}
+
+pub fn save_slave_map(map: &SlaveMap) -> io::Result<()> {
+    let records: Vec<Vec<String>> = map.iter()
+        .map(|(table, address, value)| vec![table.name().to_string(), address.to_string(), value.to_string()])
+        .collect();
+    config::save_records(SLAVE_MAP_FILE, &records)
+}
+
+// length of the request starting at `head`, None until enough of it arrived to tell
+fn request_len(head: &[u8]) -> Option<usize> {
+    match FunctionCode::from_code(*head.get(1)?)? {
+        FunctionCode::WriteMultipleCoils | FunctionCode::WriteMultipleRegisters => head.get(6).map(|n| 9 + *n as usize),
+        _ => Some(8),
+    }
+}
+
+pub fn parse_request(frame: &[u8]) -> Result<Request, ModbusError> {
+    if !check_crc(frame) {
+        return Err(ModbusError::Crc);
+    }
+    let function = FunctionCode::from_code(frame[1]).ok_or(ModbusError::Exception(ILLEGAL_FUNCTION))?;
+    let address = read_u16(frame, 2);
+    let (count, values) = match function {
+        FunctionCode::ReadCoils
+        | FunctionCode::ReadDiscreteInputs
+        | FunctionCode::ReadHoldingRegisters
+        | FunctionCode::ReadInputRegisters => (read_u16(frame, 4), Vec::new()),
+        FunctionCode::WriteSingleCoil => match read_u16(frame, 4) {
+            0xff00 => (0, vec![1]),
+            0x0000 => (0, vec![0]),
+            _ => return Err(ModbusError::Exception(ILLEGAL_DATA_VALUE)),
+        },
+        FunctionCode::WriteSingleRegister => (0, vec![read_u16(frame, 4)]),
+        FunctionCode::WriteMultipleCoils => {
+            let quantity = read_u16(frame, 4) as usize;
+            let data = &frame[7..frame.len() - 2];
+            if data.len() != quantity.div_ceil(8) {
+                return Err(ModbusError::Exception(ILLEGAL_DATA_VALUE));
+            }
+            (0, (0..quantity).map(|i| (data[i / 8] >> (i % 8)) as u16 & 1).collect())
+        }
+        FunctionCode::WriteMultipleRegisters => {
+            let quantity = read_u16(frame, 4) as usize;
+            let data = &frame[7..frame.len() - 2];
+            if data.len() != quantity * 2 {
+                return Err(ModbusError::Exception(ILLEGAL_DATA_VALUE));
+            }
+            (0, (0..quantity).map(|i| read_u16(data, i * 2)).collect())
+        }
+    };
+    Request::new(frame[0], function, address, count, values).map_err(|_| ModbusError::Exception(ILLEGAL_DATA_VALUE))
+}
+
+#[derive(Debug)]
+pub enum SlaveEvent {
+    // a request was seen on the bus, the map may have changed
+    Log(String),
+    Stopped(String),
+}
+
+#[derive(Debug)]
+pub struct SlaveJob {
+    pub slave_id: u8,
+    pub map: Arc<Mutex<SlaveMap>>,
+    pub stop_flag: Arc<Mutex<bool>>,
+    pub event_tx: glib::Sender<SlaveEvent>,
+}
+
+//
+// Serves requests until the stop flag is set. The port stays in binary mode
+// meanwhile, so the output and the send line are paused.
+//
+pub async fn run_slave<S>(port: &mut S, pending: BytesMut, job: SlaveJob)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let result = serve_requests(port, pending, &job).await;
+    let reason = match result {
+        Ok(()) => String::from("Stopped"),
+        Err(e) => format!("Stopped: {}", e),
+    };
+    let _ = job.event_tx.send(SlaveEvent::Stopped(reason));
+}
+
+async fn serve_requests<S>(port: &mut S, mut buf: BytesMut, job: &SlaveJob) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let log = |text: String| {
+        let _ = job.event_tx.send(SlaveEvent::Log(text));
+    };
+    while !*job.stop_flag.lock().unwrap() {
+        let mut chunk = [0u8; 256];
+        match timeout(POLL_INTERVAL, port.read(&mut chunk)).await {
+            Ok(Ok(0)) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Port closed")),
+            Ok(Ok(n)) => buf.extend_from_slice(&chunk[..n]),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => {
+                // the line went quiet in the middle of a frame
+                if buf.len() >= 4 && FunctionCode::from_code(buf[1]).is_none() && check_crc(&buf) {
+                    let response = with_crc(vec![buf[0], buf[1] | 0x80, ILLEGAL_FUNCTION]);
+                    log(format!("RX {}\nTX {} (Illegal Function)", escape::format_hex(&buf), escape::format_hex(&response)));
+                    if buf[0] == job.slave_id {
+                        port.write_all(&response).await?;
+                    }
+                } else if !buf.is_empty() {
+                    log(format!("RX {} (incomplete frame dropped)", escape::format_hex(&buf)));
+                }
+                buf.clear();
+                continue;
+            }
+        }
+
+        while let Some(len) = request_len(&buf).filter(|len| buf.len() >= *len) {
+            let frame = buf.split_to(len);
+            let rx = format!("RX {}", escape::format_hex(&frame));
+            // 0 is broadcast: writes are executed, nothing is sent back
+            if frame[0] != job.slave_id && frame[0] != 0 {
+                log(format!("{} (slave {}, ignored)", rx, frame[0]));
+                continue;
+            }
+            let response = match parse_request(&frame) {
+                Ok(request) => {
+                    let response = job.map.lock().unwrap().serve(&request);
+                    log(format!("{} ({})\nTX {}", rx, request.function.name(), escape::format_hex(&response)));
+                    response
+                }
+                Err(ModbusError::Exception(code)) => {
+                    let response = with_crc(vec![frame[0], frame[1] | 0x80, code]);
+                    log(format!("{} ({})\nTX {}", rx, ModbusError::Exception(code), escape::format_hex(&response)));
+                    response
+                }
+                Err(e) => {
+                    // a corrupt frame gets no response, the master times out
+                    log(format!("{} ({})", rx, e));
+                    buf.clear();
+                    continue;
+                }
+            };
+            if frame[0] != 0 {
+                port.write_all(&response).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_spec_request() {
+        let request = parse_request(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0a, 0xc5, 0xcd]).unwrap();
+        assert_eq!(request, Request::new(1, FunctionCode::ReadHoldingRegisters, 0, 10, Vec::new()).unwrap());
+        assert_eq!(parse_request(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0a, 0xc5, 0xce]), Err(ModbusError::Crc));
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            Request::new(7, FunctionCode::WriteSingleCoil, 3, 0, vec![1]).unwrap(),
+            Request::new(7, FunctionCode::WriteMultipleCoils, 3, 0, vec![1, 0, 1, 1, 0, 0, 1, 1, 1]).unwrap(),
+            Request::new(7, FunctionCode::WriteMultipleRegisters, 3, 0, vec![1, 0xffff]).unwrap(),
+        ];
+        for request in requests.iter() {
+            assert_eq!(parse_request(&request.to_frame()).as_ref(), Ok(request));
+        }
+    }
+
+    #[test]
+    fn serve() {
+        let mut map = parse_slave_map("holding 0 0x1234\nholding 1 7\ncoil 0 1").unwrap();
+        let read = Request::new(1, FunctionCode::ReadHoldingRegisters, 0, 2, Vec::new()).unwrap();
+        assert_eq!(map.serve(&read), with_crc(vec![0x01, 0x03, 0x04, 0x12, 0x34, 0x00, 0x07]));
+
+        let write = Request::new(1, FunctionCode::WriteSingleRegister, 1, 0, vec![9]).unwrap();
+        assert_eq!(map.serve(&write), write.to_frame());
+        assert_eq!(map.get(Table::HoldingRegisters, 1), Some(9));
+
+        // address 2 is not in the map
+        let read = Request::new(1, FunctionCode::ReadHoldingRegisters, 1, 2, Vec::new()).unwrap();
+        assert_eq!(map.serve(&read), with_crc(vec![0x01, 0x83, ILLEGAL_DATA_ADDRESS]));
+    }
+
+    #[test]
+    fn slave_map_text() {
+        let map = parse_slave_map("# comment\nholding 0 0x1234\ncoil 10 5\ninput 3").unwrap();
+        assert_eq!(format_slave_map(&map), "coil 10 1\nholding 0 4660\ninput 3 0\n");
+        assert!(parse_slave_map("register 0 1").is_err());
+        assert!(parse_slave_map("holding").is_err());
+    }
+}
@@ -62,3 +62,117 @@ pub fn set_baud_rate_combo_box_items(model: &gtk::ListStore) {
         model.set(&model.append(), &values); 
     }
 }
+
+pub fn create_register_model() -> gtk::ListStore {
+    let types = [
+        glib::Type::U32,
+        glib::Type::STRING,
+        glib::Type::STRING
+    ];
+    let model = gtk::ListStore::new(&types);
+    model
+}
+
+pub fn add_register_item(model: &gtk::ListStore, address: u32, value: String, hex: String) {
+    let values: [(u32, &dyn ToValue); 3] = [
+        (0, &address),
+        (1, &value),
+        (2, &hex)
+    ];
+    model.set(&model.append(), &values);
+}
+
+pub fn create_slave_map_model() -> gtk::ListStore {
+    let types = [
+        glib::Type::STRING,
+        glib::Type::U32,
+        glib::Type::STRING
+    ];
+    let model = gtk::ListStore::new(&types);
+    model
+}
+
+pub fn add_slave_map_item(model: &gtk::ListStore, table: &str, address: u32, value: String) {
+    let values: [(u32, &dyn ToValue); 3] = [
+        (0, &table),
+        (1, &address),
+        (2, &value)
+    ];
+    model.set(&model.append(), &values);
+}
+
+pub fn create_nmea_summary_model() -> gtk::ListStore {
+    let types = [
+        glib::Type::STRING,
+        glib::Type::STRING
+    ];
+    let model = gtk::ListStore::new(&types);
+    model
+}
+
+pub fn add_nmea_summary_item(model: &gtk::ListStore, name: &str, value: &str) {
+    let values: [(u32, &dyn ToValue); 2] = [
+        (0, &name),
+        (1, &value)
+    ];
+    model.set(&model.append(), &values);
+}
+
+pub fn create_satellite_model() -> gtk::ListStore {
+    let types = [
+        glib::Type::STRING,
+        glib::Type::STRING,
+        glib::Type::STRING,
+        glib::Type::STRING,
+        glib::Type::STRING
+    ];
+    let model = gtk::ListStore::new(&types);
+    model
+}
+
+pub fn add_satellite_item(model: &gtk::ListStore, prn: &str, elevation: &str, azimuth: &str, snr: &str, used: &str) {
+    let values: [(u32, &dyn ToValue); 5] = [
+        (0, &prn),
+        (1, &elevation),
+        (2, &azimuth),
+        (3, &snr),
+        (4, &used)
+    ];
+    model.set(&model.append(), &values);
+}
+
+pub fn create_decoded_frame_model() -> gtk::TreeStore {
+    let types = [
+        glib::Type::STRING,
+        glib::Type::STRING
+    ];
+    let model = gtk::TreeStore::new(&types);
+    model
+}
+
+pub fn add_decoded_frame_item(model: &gtk::TreeStore, parent: Option<&gtk::TreeIter>, time: &str, text: &str) -> gtk::TreeIter {
+    let values: [(u32, &dyn ToValue); 2] = [
+        (0, &time),
+        (1, &text)
+    ];
+    let iter = model.append(parent);
+    model.set(&iter, &values);
+    iter
+}
+
+pub fn create_stats_model() -> gtk::ListStore {
+    let types = [
+        glib::Type::STRING,
+        glib::Type::STRING
+    ];
+    let model = gtk::ListStore::new(&types);
+    model
+}
+
+pub fn add_stats_item(model: &gtk::ListStore, name: &str, value: &str) {
+    let values: [(u32, &dyn ToValue); 2] = [
+        (0, &name),
+        (1, &value)
+    ];
+    model.set(&model.append(), &values);
+}
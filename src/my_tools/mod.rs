@@ -1,5 +1,6 @@
 use gtk::prelude::*;
 use chrono::prelude::*;
+use std::path::PathBuf;
 
 pub async fn show_alert_dialog<W: IsA<gtk::Window>>(window: &W, message: String) {
     let dialog = gtk::MessageDialog::builder()
@@ -30,6 +31,157 @@ pub async fn show_question_dialog<W: IsA<gtk::Window>>(window: &W, message: Stri
     answer
 }
 
+pub async fn show_file_chooser_dialog<W: IsA<gtk::Window>>(
+    window: &W,
+    title: &str,
+    action: gtk::FileChooserAction,
+    select_multiple: bool) -> Vec<PathBuf>
+{
+    let accept_label = match action {
+        gtk::FileChooserAction::Save => "Save",
+        gtk::FileChooserAction::SelectFolder => "Select",
+        _ => "Open",
+    };
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some(title),
+        Some(window),
+        action,
+        &[("Cancel", gtk::ResponseType::Cancel), (accept_label, gtk::ResponseType::Accept)]
+    );
+    dialog.set_select_multiple(select_multiple);
+    dialog.set_do_overwrite_confirmation(true);
+    let answer = dialog.run_future().await;
+    let files = match answer {
+        gtk::ResponseType::Accept => dialog.filenames(),
+        _ => Vec::new(),
+    };
+    dialog.close();
+    files
+}
+
+pub async fn show_text_edit_dialog<W: IsA<gtk::Window>>(
+    window: &W,
+    title: &str,
+    hint: &str,
+    text: &str) -> Option<String>
+{
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title(title)
+        .default_width(500)
+        .default_height(300)
+        .window_position(gtk::WindowPosition::CenterOnParent)
+        .build();
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Save", gtk::ResponseType::Ok);
+
+    let hint_label = gtk::Label::builder()
+        .label(hint)
+        .xalign(0.0)
+        .margin(10)
+        .build();
+    let text_view = gtk::TextView::builder()
+        .monospace(true)
+        .build();
+    if let Some(buffer) = text_view.buffer() {
+        buffer.set_text(text);
+    }
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .child(&text_view)
+        .margin_start(10)
+        .margin_end(10)
+        .margin_bottom(10)
+        .build();
+
+    let content_area = dialog.content_area();
+    content_area.pack_start(&hint_label, false, false, 0);
+    content_area.pack_start(&scrolled_window, true, true, 0);
+    dialog.show_all();
+
+    let answer = dialog.run_future().await;
+    let result = match (answer, text_view.buffer()) {
+        (gtk::ResponseType::Ok, Some(buffer)) => {
+            let (start, end) = buffer.bounds();
+            buffer.text(&start, &end, false).map(|s| s.to_string())
+        }
+        _ => None,
+    };
+    dialog.close();
+    result
+}
+
+#[derive(Debug)]
+pub struct ProgressDialog {
+    dialog: gtk::Dialog,
+    label: gtk::Label,
+    retry_label: gtk::Label,
+    progress_bar: gtk::ProgressBar,
+}
+
+impl ProgressDialog {
+    pub fn new<W: IsA<gtk::Window>>(window: &W, title: &str) -> Self {
+        let dialog = gtk::Dialog::builder()
+            .transient_for(window)
+            .modal(true)
+            .title(title)
+            .default_width(400)
+            .window_position(gtk::WindowPosition::CenterOnParent)
+            .build();
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+
+        let label = gtk::Label::builder()
+            .label("Waiting for remote...")
+            .xalign(0.0)
+            .margin(10)
+            .build();
+        let retry_label = gtk::Label::builder()
+            .label("Retries: 0")
+            .xalign(0.0)
+            .margin_start(10)
+            .margin_end(10)
+            .build();
+        let progress_bar = gtk::ProgressBar::builder()
+            .show_text(true)
+            .margin(10)
+            .build();
+
+        let content_area = dialog.content_area();
+        content_area.pack_start(&label, false, false, 0);
+        content_area.pack_start(&retry_label, false, false, 0);
+        content_area.pack_start(&progress_bar, false, false, 0);
+        dialog.show_all();
+
+        ProgressDialog { dialog, label, retry_label, progress_bar }
+    }
+
+    pub fn connect_cancel<F: Fn() + 'static>(&self, f: F) {
+        self.dialog.connect_response(move |_, _| f());
+    }
+
+    pub fn set_status(&self, text: &str) {
+        self.label.set_text(text);
+    }
+
+    pub fn set_retries(&self, retries: &str) {
+        self.retry_label.set_text(&format!("Retries: {}", retries));
+    }
+
+    pub fn set_progress(&self, current: u64, total: u64) {
+        if total > 0 {
+            self.progress_bar.set_fraction(current as f64 / total as f64);
+            self.progress_bar.set_text(Some(&format!("{} / {} bytes", current, total)));
+        } else {
+            self.progress_bar.pulse();
+            self.progress_bar.set_text(Some(&format!("{} bytes", current)));
+        }
+    }
+
+    pub fn close(&self) {
+        self.dialog.close();
+    }
+}
+
 pub fn current_timestamp_string() -> String {
     let local: DateTime<Local> = Local::now();
     format!("{:02}:{:02}:{:02}.{}", 
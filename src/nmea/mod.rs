@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::checksum::xor8;
+
+//
+// NMEA 0183 sentence: $<talker><type>,<field>,...*<checksum>
+//   the checksum is the XOR of everything between `$` and `*`, in hex
+//   e.g. $GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47
+//
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sentence {
+    // GP, GL, GA, GN, ...
+    pub talker: String,
+    // GGA, RMC, ...
+    pub kind: String,
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NmeaError {
+    // the line does not look like a sentence at all
+    NotNmea,
+    MissingChecksum,
+    BadChecksum { expected: u8, actual: u8 },
+}
+
+impl fmt::Display for NmeaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NmeaError::NotNmea => write!(f, "Not an NMEA sentence"),
+            NmeaError::MissingChecksum => write!(f, "Missing checksum"),
+            NmeaError::BadChecksum { expected, actual } => write!(f, "Bad checksum: *{:02X}, calculated *{:02X}", expected, actual),
+        }
+    }
+}
+
+// the sentence in `line`, `NotNmea` for other text
+pub fn parse_sentence(line: &str) -> Result<Sentence, NmeaError> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let body = match line.strip_prefix('$').or_else(|| line.strip_prefix('!')) {
+        Some(body) => body,
+        None => return Err(NmeaError::NotNmea),
+    };
+    let (data, checksum) = match body.rsplit_once('*') {
+        Some((data, checksum)) => (data, checksum),
+        None => return Err(NmeaError::MissingChecksum),
+    };
+    let expected = u8::from_str_radix(checksum.trim(), 16).map_err(|_| NmeaError::MissingChecksum)?;
+    let actual = xor8(data.as_bytes());
+    if expected != actual {
+        return Err(NmeaError::BadChecksum { expected, actual });
+    }
+
+    let mut fields = data.split(',').map(String::from);
+    let address = fields.next().unwrap_or_default();
+    if address.len() < 3 || !address.is_ascii() {
+        return Err(NmeaError::NotNmea);
+    }
+    let (talker, kind) = address.split_at(address.len() - 3);
+    Ok(Sentence { talker: talker.to_string(), kind: kind.to_string(), fields: fields.collect() })
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Satellite {
+    pub elevation: Option<u32>,
+    pub azimuth: Option<u32>,
+    // dB-Hz, None while not tracked
+    pub snr: Option<u32>,
+}
+
+//
+// The latest fix, put together from whichever sentences the receiver sends
+//
+#[derive(Debug, Clone, Default)]
+pub struct NmeaState {
+    pub time: Option<String>,
+    pub date: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude: Option<f64>,
+    // GGA fix quality: 0 invalid, 1 GPS, 2 DGPS, ...
+    pub fix_quality: Option<u32>,
+    // GSA fix mode: 1 no fix, 2 2D, 3 3D
+    pub fix_mode: Option<u32>,
+    pub status_valid: Option<bool>,
+    pub satellites_used: Option<u32>,
+    pub speed_knots: Option<f64>,
+    pub course: Option<f64>,
+    pub pdop: Option<f64>,
+    pub hdop: Option<f64>,
+    pub vdop: Option<f64>,
+    // satellites in view by (talker, PRN)
+    pub satellites: BTreeMap<(String, u32), Satellite>,
+    // PRNs of the satellites used in the fix, from GSA
+    pub used_prns: Vec<u32>,
+    pub sentences: usize,
+    pub bad_checksums: usize,
+}
+
+fn field(sentence: &Sentence, i: usize) -> Option<&str> {
+    sentence.fields.get(i).map(|s| s.as_str()).filter(|s| !s.is_empty())
+}
+
+fn number<T: std::str::FromStr>(sentence: &Sentence, i: usize) -> Option<T> {
+    field(sentence, i).and_then(|s| s.parse().ok())
+}
+
+// ddmm.mmmm plus hemisphere to signed decimal degrees
+fn coordinate(sentence: &Sentence, i: usize) -> Option<f64> {
+    let value: f64 = number(sentence, i)?;
+    let degrees = (value / 100.0).trunc();
+    let decimal = degrees + (value - degrees * 100.0) / 60.0;
+    match field(sentence, i + 1)? {
+        "N" | "E" => Some(decimal),
+        "S" | "W" => Some(-decimal),
+        _ => None,
+    }
+}
+
+// hhmmss.ss to hh:mm:ss.ss
+fn format_time(s: &str) -> String {
+    if s.len() >= 6 && s.is_ascii() {
+        format!("{}:{}:{}", &s[0..2], &s[2..4], &s[4..])
+    } else {
+        s.to_string()
+    }
+}
+
+impl NmeaState {
+    // bad checksums are counted, lines that are not NMEA are ignored
+    pub fn update(&mut self, line: &str) -> Result<(), NmeaError> {
+        let sentence = match parse_sentence(line) {
+            Ok(sentence) => sentence,
+            Err(NmeaError::NotNmea) => return Err(NmeaError::NotNmea),
+            Err(e) => {
+                self.bad_checksums += 1;
+                return Err(e);
+            }
+        };
+        self.sentences += 1;
+        match sentence.kind.as_str() {
+            "GGA" => self.update_gga(&sentence),
+            "RMC" => self.update_rmc(&sentence),
+            "GSA" => self.update_gsa(&sentence),
+            "GSV" => self.update_gsv(&sentence),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn update_gga(&mut self, s: &Sentence) {
+        self.time = field(s, 0).map(format_time);
+        self.latitude = coordinate(s, 1);
+        self.longitude = coordinate(s, 3);
+        self.fix_quality = number(s, 5);
+        self.satellites_used = number(s, 6);
+        self.hdop = number(s, 7);
+        self.altitude = number(s, 8);
+    }
+
+    fn update_rmc(&mut self, s: &Sentence) {
+        self.time = field(s, 0).map(format_time);
+        self.status_valid = field(s, 1).map(|status| status == "A");
+        self.latitude = coordinate(s, 2);
+        self.longitude = coordinate(s, 4);
+        self.speed_knots = number(s, 6);
+        self.course = number(s, 7);
+        // ddmmyy
+        self.date = field(s, 8)
+            .filter(|d| d.len() == 6 && d.is_ascii())
+            .map(|d| format!("20{}-{}-{}", &d[4..6], &d[2..4], &d[0..2]));
+    }
+
+    fn update_gsa(&mut self, s: &Sentence) {
+        self.fix_mode = number(s, 1);
+        self.used_prns = (2..14).filter_map(|i| number(s, i)).collect();
+        self.pdop = number(s, 14);
+        self.hdop = number(s, 15);
+        self.vdop = number(s, 16);
+    }
+
+    fn update_gsv(&mut self, s: &Sentence) {
+        // the first message of a group starts a new list for this talker
+        if field(s, 1) == Some("1") {
+            self.satellites.retain(|(talker, _), _| *talker != s.talker);
+        }
+        // only complete groups of four, NMEA 4.10 appends a signal ID after the last one
+        let groups = s.fields.len().saturating_sub(3) / 4;
+        for i in (0..groups).map(|g| 3 + 4 * g) {
+            if let Some(prn) = number(s, i) {
+                let satellite = Satellite {
+                    elevation: number(s, i + 1),
+                    azimuth: number(s, i + 2),
+                    snr: number(s, i + 3),
+                };
+                self.satellites.insert((s.talker.clone(), prn), satellite);
+            }
+        }
+    }
+
+    pub fn fix_name(&self) -> &'static str {
+        match (self.fix_quality, self.fix_mode) {
+            (Some(0), _) | (_, Some(1)) => "No Fix",
+            (Some(2), _) => "DGPS",
+            (Some(4), _) => "RTK Fixed",
+            (Some(5), _) => "RTK Float",
+            (_, Some(3)) => "3D Fix",
+            (_, Some(2)) => "2D Fix",
+            (Some(_), _) => "GPS Fix",
+            (None, _) => "-",
+        }
+    }
+
+    // (name, value) rows for display
+    pub fn summary(&self) -> Vec<(&'static str, String)> {
+        let opt = |value: Option<String>| value.unwrap_or_else(|| String::from("-"));
+        vec![
+            ("Time (UTC)", opt(self.time.clone())),
+            ("Date", opt(self.date.clone())),
+            ("Latitude", opt(self.latitude.map(|v| format!("{:.6}", v)))),
+            ("Longitude", opt(self.longitude.map(|v| format!("{:.6}", v)))),
+            ("Altitude (m)", opt(self.altitude.map(|v| format!("{:.1}", v)))),
+            ("Fix", String::from(self.fix_name())),
+            ("Status", opt(self.status_valid.map(|v| String::from(if v { "Valid" } else { "Warning" })))),
+            ("Satellites Used", opt(self.satellites_used.map(|v| v.to_string()))),
+            ("Satellites in View", self.satellites.len().to_string()),
+            ("Speed (knots)", opt(self.speed_knots.map(|v| format!("{:.1}", v)))),
+            ("Course (°)", opt(self.course.map(|v| format!("{:.1}", v)))),
+            ("PDOP / HDOP / VDOP", format!(
+                "{} / {} / {}",
+                opt(self.pdop.map(|v| v.to_string())),
+                opt(self.hdop.map(|v| v.to_string())),
+                opt(self.vdop.map(|v| v.to_string())))),
+            ("Sentences", self.sentences.to_string()),
+            ("Bad Checksums", self.bad_checksums.to_string()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GGA: &str = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47";
+
+    fn sentence(body: &str) -> String {
+        format!("${}*{:02X}", body, xor8(body.as_bytes()))
+    }
+
+    #[test]
+    fn parse() {
+        let s = parse_sentence(&format!("{}\r\n", GGA)).unwrap();
+        assert_eq!((s.talker.as_str(), s.kind.as_str(), s.fields.len()), ("GP", "GGA", 14));
+        assert_eq!(s.fields[1], "4807.038");
+        assert_eq!(parse_sentence("hello"), Err(NmeaError::NotNmea));
+        assert_eq!(parse_sentence("$GPGGA,1"), Err(NmeaError::MissingChecksum));
+        assert_eq!(parse_sentence("$GPGGA,1*00"), Err(NmeaError::BadChecksum { expected: 0x00, actual: 0x4b }));
+    }
+
+    #[test]
+    fn gga() {
+        let mut state = NmeaState::default();
+        state.update(GGA).unwrap();
+        assert_eq!(state.time.as_deref(), Some("12:35:19"));
+        assert!((state.latitude.unwrap() - 48.1173).abs() < 1e-4);
+        assert!((state.longitude.unwrap() - 11.516_667).abs() < 1e-4);
+        assert_eq!((state.fix_quality, state.satellites_used), (Some(1), Some(8)));
+        assert_eq!(state.altitude, Some(545.4));
+        assert_eq!(state.fix_name(), "GPS Fix");
+    }
+
+    #[test]
+    fn gsv_with_signal_id() {
+        let mut state = NmeaState::default();
+        state.update(&sentence("GPGSV,1,1,02,05,45,120,38,12,10,300,,1")).unwrap();
+        let prns: Vec<u32> = state.satellites.keys().map(|(_, prn)| *prn).collect();
+        assert_eq!(prns, vec![5, 12]);
+        let satellite = state.satellites[&(String::from("GP"), 5)];
+        assert_eq!(satellite, Satellite { elevation: Some(45), azimuth: Some(120), snr: Some(38) });
+        assert_eq!(state.satellites[&(String::from("GP"), 12)].snr, None);
+    }
+
+    #[test]
+    fn bad_checksums_are_counted() {
+        let mut state = NmeaState::default();
+        assert!(state.update("$GPGGA,1*00").is_err());
+        assert_eq!(state.update("not nmea"), Err(NmeaError::NotNmea));
+        assert_eq!((state.sentences, state.bad_checksums), (0, 1));
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use gtk::cairo;
+use regex::Regex;
+
+use crate::export::escape_csv;
+
+// the oldest samples are dropped after this many
+pub const MAX_SAMPLES: usize = 20000;
+// grid lines per axis, whatever the step works out to
+const MAX_TICKS: usize = 50;
+
+const COLORS: [(f64, f64, f64); 8] = [
+    (0.12, 0.47, 0.71),
+    (0.89, 0.10, 0.11),
+    (0.17, 0.63, 0.17),
+    (1.00, 0.50, 0.05),
+    (0.58, 0.40, 0.74),
+    (0.55, 0.34, 0.29),
+    (0.89, 0.47, 0.76),
+    (0.09, 0.75, 0.81),
+];
+
+//
+// How numbers are found in a received line
+//   CSV:       `1.5,20,-3` or `1.5 20 -3`, series are named by column
+//   key=value: `temp=21.5 hum:40`, series are named by key
+//   regex:     the capture groups, named by group name or number
+//
+#[derive(Debug, Clone, Default)]
+pub enum LineFormat {
+    #[default]
+    Csv,
+    KeyValue,
+    Regex(Regex),
+}
+
+fn is_separator(c: char) -> bool {
+    c == ',' || c == ';' || c.is_whitespace()
+}
+
+pub fn parse_line(format: &LineFormat, line: &str) -> Vec<(String, f64)> {
+    let line = line.trim();
+    match format {
+        LineFormat::Csv => line.split(is_separator)
+            .filter(|token| !token.is_empty())
+            .enumerate()
+            .filter_map(|(i, token)| token.parse::<f64>().ok().map(|value| ((i + 1).to_string(), value)))
+            .collect(),
+        LineFormat::KeyValue => line.split(is_separator)
+            .filter_map(|token| token.split_once(['=', ':']))
+            .filter_map(|(key, value)| value.parse::<f64>().ok().map(|value| (key.to_string(), value)))
+            .filter(|(key, _)| !key.is_empty())
+            .collect(),
+        LineFormat::Regex(regex) => {
+            let captures = match regex.captures(line) {
+                Some(captures) => captures,
+                None => return Vec::new(),
+            };
+            regex.capture_names()
+                .enumerate()
+                .skip(1)
+                .filter_map(|(i, name)| {
+                    let value = captures.get(i)?.as_str().trim().parse::<f64>().ok()?;
+                    Some((name.map_or_else(|| i.to_string(), |name| name.to_string()), value))
+                })
+                .collect()
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PlotData {
+    start: Option<Instant>,
+    pub names: Vec<String>,
+    // (seconds since the first sample, value of each series)
+    pub samples: VecDeque<(f64, Vec<Option<f64>>)>,
+}
+
+impl PlotData {
+    // seconds since the first sample
+    pub fn now(&self) -> f64 {
+        self.start.map_or(0.0, |start| start.elapsed().as_secs_f64())
+    }
+
+    pub fn add(&mut self, values: Vec<(String, f64)>) {
+        // `inf` and `NaN` parse as numbers but can't be plotted
+        let values: Vec<(String, f64)> = values.into_iter().filter(|(_, value)| value.is_finite()).collect();
+        if values.is_empty() {
+            return;
+        }
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let mut sample = vec![None; self.names.len()];
+        for (name, value) in values {
+            match self.names.iter().position(|n| *n == name) {
+                Some(i) => sample[i] = Some(value),
+                None => {
+                    self.names.push(name);
+                    sample.push(Some(value));
+                }
+            }
+        }
+        self.samples.push_back((start.elapsed().as_secs_f64(), sample));
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = PlotData::default();
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("time");
+        for name in self.names.iter() {
+            csv.push(',');
+            csv.push_str(&escape_csv(name));
+        }
+        csv.push('\n');
+        for (time, values) in self.samples.iter() {
+            csv.push_str(&format!("{:.3}", time));
+            for i in 0..self.names.len() {
+                csv.push(',');
+                if let Some(value) = values.get(i).copied().flatten() {
+                    csv.push_str(&value.to_string());
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    fn visible(&self, from: f64, to: f64) -> impl Iterator<Item = &(f64, Vec<Option<f64>>)> {
+        self.samples.iter().filter(move |(time, _)| *time >= from && *time <= to)
+    }
+}
+
+// a round step giving about `count` ticks over `range`, and the decimals it needs
+fn tick_step(range: f64, count: f64) -> (f64, usize) {
+    let rough = range / count;
+    let exponent = rough.log10().floor();
+    let magnitude = 10f64.powf(exponent);
+    let step = match rough / magnitude {
+        r if r < 1.5 => magnitude,
+        r if r < 3.5 => 2.0 * magnitude,
+        r if r < 7.5 => 5.0 * magnitude,
+        _ => 10.0 * magnitude,
+    };
+    (step, (-exponent).clamp(0.0, 15.0) as usize)
+}
+
+// the multiples of `step` from `from` to `to`
+fn ticks(from: f64, to: f64, step: f64) -> impl Iterator<Item = f64> {
+    let first = (from / step).ceil();
+    // NaN, for a zero step, casts to no ticks
+    let count = ((to / step).floor() - first + 1.0).clamp(0.0, MAX_TICKS as f64) as usize;
+    (0..count).map(move |k| (first + k as f64) * step)
+}
+
+//
+// Plots the samples between `end - span` and `end` seconds,
+// the value axis fits the visible samples.
+//
+pub fn draw(cr: &cairo::Context, width: f64, height: f64, data: &PlotData, end: f64, span: f64) -> Result<(), cairo::Error> {
+    const LEFT: f64 = 60.0;
+    const RIGHT: f64 = 10.0;
+    const TOP: f64 = 10.0;
+    const BOTTOM: f64 = 25.0;
+
+    cr.set_source_rgb(1.0, 1.0, 1.0);
+    cr.paint()?;
+    cr.set_font_size(11.0);
+
+    let start = end - span;
+    let plot_width = (width - LEFT - RIGHT).max(1.0);
+    let plot_height = (height - TOP - BOTTOM).max(1.0);
+    let (mut min, mut max) = data.visible(start, end)
+        .flat_map(|(_, values)| values.iter().flatten())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(*v), max.max(*v)));
+    if min > max {
+        min = 0.0;
+        max = 1.0;
+    }
+    // widen a flat series relative to its size, +-1 would be lost in the rounding of large values
+    let magnitude = min.abs().max(max.abs());
+    if max - min <= magnitude * 1e-9 {
+        let pad = (magnitude * 1e-9).max(1.0);
+        min -= pad;
+        max += pad;
+    }
+    let x = |time: f64| LEFT + (time - start) / span * plot_width;
+    let y = |value: f64| TOP + (max - value) / (max - min) * plot_height;
+
+    // grid and axis labels
+    cr.set_line_width(1.0);
+    let (step, decimals) = tick_step(max - min, 5.0);
+    for value in ticks(min, max, step) {
+        cr.set_source_rgb(0.9, 0.9, 0.9);
+        cr.move_to(LEFT, y(value).round() + 0.5);
+        cr.line_to(LEFT + plot_width, y(value).round() + 0.5);
+        cr.stroke()?;
+        cr.set_source_rgb(0.3, 0.3, 0.3);
+        let label = format!("{:.*}", decimals, value);
+        let extents = cr.text_extents(&label)?;
+        cr.move_to(LEFT - extents.width - 5.0, y(value) + extents.height / 2.0);
+        cr.show_text(&label)?;
+    }
+    let (step, decimals) = tick_step(span, 6.0);
+    for time in ticks(start.max(0.0), end, step) {
+        cr.set_source_rgb(0.9, 0.9, 0.9);
+        cr.move_to(x(time).round() + 0.5, TOP);
+        cr.line_to(x(time).round() + 0.5, TOP + plot_height);
+        cr.stroke()?;
+        cr.set_source_rgb(0.3, 0.3, 0.3);
+        let label = format!("{:.*}s", decimals, time);
+        let extents = cr.text_extents(&label)?;
+        cr.move_to(x(time) - extents.width / 2.0, height - 8.0);
+        cr.show_text(&label)?;
+    }
+    cr.set_source_rgb(0.5, 0.5, 0.5);
+    cr.rectangle(LEFT + 0.5, TOP + 0.5, plot_width, plot_height);
+    cr.stroke()?;
+
+    // series, with a gap wherever a line had no value
+    cr.save()?;
+    cr.rectangle(LEFT, TOP, plot_width, plot_height);
+    cr.clip();
+    cr.set_line_width(1.5);
+    for i in 0..data.names.len() {
+        let (r, g, b) = COLORS[i % COLORS.len()];
+        cr.set_source_rgb(r, g, b);
+        let mut drawing = false;
+        for (time, values) in data.visible(start, end) {
+            match values.get(i).copied().flatten() {
+                Some(value) if drawing => cr.line_to(x(*time), y(value)),
+                Some(value) => {
+                    cr.move_to(x(*time), y(value));
+                    drawing = true;
+                }
+                None => drawing = false,
+            }
+        }
+        cr.stroke()?;
+    }
+    cr.restore()?;
+
+    // legend
+    let mut legend_x = LEFT + 10.0;
+    for (i, name) in data.names.iter().enumerate() {
+        let (r, g, b) = COLORS[i % COLORS.len()];
+        cr.set_source_rgb(r, g, b);
+        cr.rectangle(legend_x, TOP + 8.0, 10.0, 10.0);
+        cr.fill()?;
+        cr.set_source_rgb(0.2, 0.2, 0.2);
+        cr.move_to(legend_x + 14.0, TOP + 17.0);
+        cr.show_text(name)?;
+        legend_x += 24.0 + cr.text_extents(name)?.x_advance;
+    }
+    Ok(())
+}
@@ -1,30 +1,224 @@
 use std::{io, str};
-use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
 
 use tokio_util::codec::{Decoder, Encoder};
 use bytes::{BufMut, BytesMut};
 
 use futures::channel::mpsc::UnboundedReceiver;
-use futures_util::{future, pin_mut, StreamExt, SinkExt};
-use tokio_serial::SerialPortBuilderExt;
+use futures_util::{StreamExt, SinkExt};
+use tokio_serial::{SerialPort, SerialPortBuilderExt};
 
-#[cfg(windows)]
-use tokio_serial::SerialPort;
+use crate::decoder::SharedDecoder;
+use crate::escape;
+use crate::modbus::{self, ModbusJob};
+use crate::modbus::slave::{run_slave, SlaveJob};
+use crate::sequence::Step;
+use crate::stats::SharedStats;
+use crate::trigger::{Trigger, TriggerMatcher};
+use crate::xmodem::{run_transfer, TransferJob};
+use crate::zmodem::ZRQINIT_PATTERN;
 
-struct LineCodec;
+// received lines are batched and handed to the UI at most this often
+const FLUSH_INTERVAL: Duration = Duration::from_millis(30);
+// received data held back while the UI is busy, anything beyond is dropped
+const MAX_PENDING_BYTES: usize = 4 * 1024 * 1024;
+// batches waiting in the read channel
+pub const READ_CHANNEL_BOUND: usize = 16;
+// received data without a newline is passed on as a line after this many bytes
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataDirection {
+    Rx,
+    Tx,
+}
+
+// the received text after the last newline, so a script can wait for a prompt
+pub type SharedPrompt = Arc<Mutex<String>>;
+
+// received and transmitted text in the order it went over the wire
+pub type Output = Vec<(DataDirection, String)>;
+
+#[derive(Debug, Default)]
+struct OutputBatch {
+    chunks: Output,
+    bytes: usize,
+}
+
+impl OutputBatch {
+    // false if the batch is full and the text was dropped
+    fn push(&mut self, direction: DataDirection, text: &str) -> bool {
+        if self.bytes + text.len() > MAX_PENDING_BYTES {
+            return false;
+        }
+        self.bytes += text.len();
+        match self.chunks.last_mut() {
+            Some((d, s)) if *d == direction => s.push_str(text),
+            _ => self.chunks.push((direction, text.to_string())),
+        }
+        true
+    }
+
+    fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+// how written bytes show up in the output, one line per write
+fn tx_echo(bytes: &[u8]) -> String {
+    let mut text = escape::caret_notation(&String::from_utf8_lossy(bytes));
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+#[derive(Debug)]
+pub enum PortCommand {
+    Write(String),
+    WriteBytes(Vec<u8>),
+    SetDtr(bool),
+    SetRts(bool),
+    SendBreak(Duration),
+    RunSequence(Vec<Step>),
+    Transfer(TransferJob),
+    Modbus(ModbusJob),
+    ModbusSlave(SlaveJob),
+    Close,
+}
+
+enum Frame {
+    Line(String),
+    ZmodemRequest,
+    // a trigger fired, the response goes straight back to the port
+    Response(Vec<u8>),
+}
+
+struct LineCodec {
+    triggers: Arc<Mutex<Vec<Trigger>>>,
+    trigger_matcher: TriggerMatcher,
+    responses: VecDeque<Vec<u8>>,
+    decoder: SharedDecoder,
+    // bytes at the start of the read buffer the decoder has already seen
+    decoded_len: usize,
+    stats: SharedStats,
+    prompt: SharedPrompt,
+}
+
+impl LineCodec {
+    fn new(triggers: Arc<Mutex<Vec<Trigger>>>, decoder: SharedDecoder, stats: SharedStats, prompt: SharedPrompt) -> Self {
+        LineCodec {
+            triggers,
+            trigger_matcher: TriggerMatcher::default(),
+            responses: VecDeque::new(),
+            decoder,
+            decoded_len: 0,
+            stats,
+            prompt,
+        }
+    }
+
+    fn set_prompt(&self, text: &str) {
+        let mut prompt = self.prompt.lock().unwrap();
+        if *prompt != text {
+            prompt.clear();
+            prompt.push_str(text);
+        }
+    }
+
+    // counts newly received bytes and hands them to the protocol decoder, if one is selected;
+    // true if that decoder is binary and the data is not split into lines
+    fn feed_decoder(&mut self, src: &BytesMut) -> bool {
+        let mut decoder = self.decoder.lock().unwrap();
+        if src.len() > self.decoded_len {
+            let data = &src[self.decoded_len..];
+            let mut stats = self.stats.lock().unwrap();
+            stats.rx_bytes += data.len() as u64;
+            if let Some(session) = decoder.as_mut() {
+                let (frames, errors) = session.feed(data);
+                stats.rx_frames += frames as u64;
+                stats.frame_errors += errors as u64;
+            }
+        }
+        self.decoded_len = src.len();
+        decoder.as_ref().is_some_and(|session| session.is_binary())
+    }
+
+    // the read buffer was taken over by a transfer or Modbus, the decoder does not see that data
+    fn buffer_taken(&mut self) {
+        self.decoded_len = 0;
+        self.set_prompt("");
+    }
+
+    fn check_triggers(&mut self, text: &str) {
+        let triggers = self.triggers.lock().unwrap();
+        if triggers.is_empty() {
+            return;
+        }
+        let responses = self.trigger_matcher.check(&triggers, text);
+        self.responses.extend(responses);
+    }
+}
 
 impl Decoder for LineCodec {
-    type Item = String;
+    type Item = Frame;
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let is_binary = self.feed_decoder(src);
+        if let Some(response) = self.responses.pop_front() {
+            return Ok(Some(Frame::Response(response)));
+        }
+        // the decoder has framed the data, there are no lines, prompts or ZMODEM headers in it
+        if is_binary {
+            src.clear();
+            self.decoded_len = 0;
+            self.set_prompt("");
+            return Ok(None);
+        }
+
         let newline = src.as_ref().iter().position(|b| *b == b'\n');
+        let zmodem = src.as_ref().windows(ZRQINIT_PATTERN.len()).position(|w| w == ZRQINIT_PATTERN);
+        if let Some(z) = zmodem {
+            if newline.map_or(true, |n| n > z) {
+                // drop the header (and the "rz\r" in front of it), it is not text
+                src.clear();
+                self.decoded_len = 0;
+                self.trigger_matcher.end_line();
+                self.set_prompt("");
+                return Ok(Some(Frame::ZmodemRequest));
+            }
+        }
+
+        // a long run without a newline is most likely binary, don't scan it again on every read
+        let newline = newline.or_else(|| (src.len() >= MAX_LINE_LENGTH).then(|| src.len() - 1));
         if let Some(n) = newline {
             let line = src.split_to(n + 1);
-            return match str::from_utf8(line.as_ref()) {
-                Ok(s) => Ok(Some(s.to_string())),
-                Err(_) => Err(io::Error::new(io::ErrorKind::Other, "Invalid String")),
-            };
+            self.decoded_len -= line.len();
+            let mut stats = self.stats.lock().unwrap();
+            stats.rx_lines += 1;
+            // a decode error would end the stream, and binary protocols are rarely valid UTF-8
+            if str::from_utf8(line.as_ref()).is_err() {
+                stats.utf8_errors += 1;
+            }
+            drop(stats);
+            let line = String::from_utf8_lossy(line.as_ref()).to_string();
+            self.check_triggers(line.trim_end_matches(|c| c == '\r' || c == '\n'));
+            self.trigger_matcher.end_line();
+            self.set_prompt("");
+            return Ok(Some(Frame::Line(line)));
+        }
+
+        // prompts are usually not terminated by a newline
+        if !src.is_empty() {
+            let tail = String::from_utf8_lossy(src.as_ref());
+            self.set_prompt(&tail);
+            self.check_triggers(&tail);
+            if let Some(response) = self.responses.pop_front() {
+                return Ok(Some(Frame::Response(response)));
+            }
         }
         Ok(None)
     }
@@ -34,7 +228,6 @@ impl Encoder<String> for LineCodec {
     type Error = io::Error;
 
     fn encode(&mut self, item: String, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        eprintln!("In writer {:?}", &item);
         dst.reserve(item.len() + 1);
         dst.put(item.as_bytes());
         dst.put_u8(b'\n');
@@ -42,13 +235,43 @@ impl Encoder<String> for LineCodec {
     }
 }
 
+impl Encoder<Vec<u8>> for LineCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Vec<u8>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        dst.extend_from_slice(&item);
+        Ok(())
+    }
+}
+
+fn read_modem_status<P: SerialPort>(port: &mut P) -> String {
+    //
+    // status format: cts=1,dsr=0,dcd=0,ri=0
+    //
+    let lines = [
+        ("cts", port.read_clear_to_send()),
+        ("dsr", port.read_data_set_ready()),
+        ("dcd", port.read_carrier_detect()),
+        ("ri", port.read_ring_indicator()),
+    ];
+    lines.iter()
+        .map(|(name, level)| format!("{}={}", name, matches!(level, Ok(true)) as u8))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn open_port_async(
     port_name: String,
     baud_rate: u32,
-    write_rx: UnboundedReceiver<String>,
-    read_tx: glib::Sender<String>,
+    write_rx: UnboundedReceiver<PortCommand>,
+    read_tx: glib::SyncSender<Output>,
     state_tx: glib::Sender<String>,
-    port_close_flag: Arc<Mutex<bool>>)
+    port_close_flag: Arc<Mutex<bool>>,
+    triggers: Arc<Mutex<Vec<Trigger>>>,
+    decoder: SharedDecoder,
+    stats: SharedStats,
+    prompt: SharedPrompt)
 {
     let mut port = match tokio_serial::new(port_name, baud_rate).open_native_async() {
         Ok(p) => p,
@@ -68,35 +291,172 @@ pub async fn open_port_async(
     }
 
     state_tx.send(String::from("[open_port](ok)")).expect("Could not send through channel");
+    stats.lock().unwrap().opened(baud_rate);
 
-    let (mut write, mut read) = LineCodec.framed(port).split();
-
+    let mut framed = LineCodec::new(triggers, decoder, stats.clone(), prompt).framed(port);
     let mut write_rx_mut = write_rx;
-    let write_to_port = async {
-        while let Some(s) = write_rx_mut.next().await {
-            let close_flag = *port_close_flag.lock().unwrap();
-            eprintln!("(thread) write_to_port: port_close_flag => {}", close_flag);
-            if close_flag {
-                break;
-            } else {
-                let _ = write.send(s).await;
-            }
-        }
-    };
 
-    let read_from_port = async {
-        while let Some(line_result) = read.next().await {
-            match line_result {
-                Ok(line) => read_tx.send(line).expect("Could not send through channel"),
-                Err(e) => eprintln!("Failed to read line: {}", e)
+    let mut modem_status = String::new();
+    let mut modem_status_interval = tokio::time::interval(Duration::from_millis(100));
+    modem_status_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    let mut pending = OutputBatch::default();
+    let mut dropped_bytes = 0;
+    let mut flush_interval = tokio::time::interval(FLUSH_INTERVAL);
+    flush_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            command = write_rx_mut.next() => {
+                let close_flag = *port_close_flag.lock().unwrap();
+                match command {
+                    Some(_) if close_flag => break,
+                    Some(PortCommand::Write(s)) => {
+                        let echo = tx_echo(s.as_bytes());
+                        // the codec appends a newline
+                        let len = s.len() + 1;
+                        if framed.send(s).await.is_ok() {
+                            stats.lock().unwrap().sent(len);
+                            if !pending.push(DataDirection::Tx, &echo) {
+                                dropped_bytes += echo.len();
+                            }
+                        }
+                    }
+                    Some(PortCommand::WriteBytes(bytes)) => {
+                        let echo = tx_echo(&bytes);
+                        let len = bytes.len();
+                        if framed.send(bytes).await.is_ok() {
+                            stats.lock().unwrap().sent(len);
+                            if !pending.push(DataDirection::Tx, &echo) {
+                                dropped_bytes += echo.len();
+                            }
+                        }
+                    }
+                    Some(PortCommand::SetDtr(level)) => {
+                        if let Err(e) = framed.get_mut().write_data_terminal_ready(level) {
+                            eprintln!("Error: {}", e);
+                        }
+                    }
+                    Some(PortCommand::SetRts(level)) => {
+                        if let Err(e) = framed.get_mut().write_request_to_send(level) {
+                            eprintln!("Error: {}", e);
+                        }
+                    }
+                    Some(PortCommand::RunSequence(steps)) => {
+                        for step in steps {
+                            let result = match step {
+                                Step::Dtr(level) => framed.get_mut().write_data_terminal_ready(level),
+                                Step::Rts(level) => framed.get_mut().write_request_to_send(level),
+                                Step::Wait(duration) => {
+                                    tokio::time::sleep(duration).await;
+                                    Ok(())
+                                }
+                            };
+                            if let Err(e) = result {
+                                eprintln!("Error: {}", e);
+                            }
+                        }
+                    }
+                    Some(PortCommand::SendBreak(duration)) => {
+                        if let Err(e) = framed.get_mut().set_break() {
+                            eprintln!("Error: {}", e);
+                        } else {
+                            tokio::time::sleep(duration).await;
+                            if let Err(e) = framed.get_mut().clear_break() {
+                                eprintln!("Error: {}", e);
+                            }
+                        }
+                    }
+                    Some(PortCommand::Transfer(job)) => {
+                        // the transfer owns the raw port until it is done,
+                        // then we fall back to line mode
+                        let pending = framed.read_buffer_mut().split();
+                        framed.codec_mut().buffer_taken();
+                        run_transfer(framed.get_mut(), pending, job, &state_tx).await;
+                    }
+                    Some(PortCommand::Modbus(job)) => {
+                        // same as a transfer, the response is binary; data received
+                        // before the request is stale, so it is discarded
+                        framed.read_buffer_mut().clear();
+                        framed.codec_mut().buffer_taken();
+                        modbus::run_request(framed.get_mut(), job).await;
+                    }
+                    Some(PortCommand::ModbusSlave(job)) => {
+                        // served in binary mode until the simulator is stopped
+                        let pending = framed.read_buffer_mut().split();
+                        framed.codec_mut().buffer_taken();
+                        run_slave(framed.get_mut(), pending, job).await;
+                    }
+                    Some(PortCommand::Close) | None => break,
+                }
+            }
+            line_result = framed.next() => {
+                match line_result {
+                    Some(Ok(Frame::Line(line))) => {
+                        if !pending.push(DataDirection::Rx, &line) {
+                            dropped_bytes += line.len();
+                        }
+                    }
+                    Some(Ok(Frame::ZmodemRequest)) => {
+                        state_tx.send(String::from("[zmodem](detected)")).expect("Could not send through channel");
+                    }
+                    Some(Ok(Frame::Response(bytes))) => {
+                        let echo = tx_echo(&bytes);
+                        let len = bytes.len();
+                        if framed.send(bytes).await.is_ok() {
+                            stats.lock().unwrap().sent(len);
+                            if !pending.push(DataDirection::Tx, &echo) {
+                                dropped_bytes += echo.len();
+                            }
+                        }
+                    }
+                    Some(Err(e)) => {
+                        eprintln!("Failed to read line: {}", e);
+                        stats.lock().unwrap().read_errors += 1;
+                    }
+                    None => {
+                        eprintln!("(thread) read_from_port: stop...");
+                        break;
+                    }
+                }
+            }
+            _ = flush_interval.tick() => {
+                if !pending.is_empty() {
+                    let batch = std::mem::take(&mut pending);
+                    match read_tx.try_send(batch.chunks) {
+                        Ok(()) => {}
+                        // the UI is behind, keep the batch for the next tick
+                        Err(mpsc::TrySendError::Full(chunks)) => pending = OutputBatch { chunks, bytes: batch.bytes },
+                        Err(mpsc::TrySendError::Disconnected(_)) => break,
+                    }
+                }
+                if dropped_bytes > 0 {
+                    stats.lock().unwrap().dropped_bytes += dropped_bytes as u64;
+                    state_tx.send(format!("[dropped]({})", dropped_bytes)).expect("Could not send through channel");
+                    dropped_bytes = 0;
+                }
+            }
+            _ = modem_status_interval.tick() => {
+                let status = read_modem_status(framed.get_mut());
+                if status != modem_status {
+                    state_tx.send(format!("[modem_status]({})", status)).expect("Could not send through channel");
+                    modem_status = status;
+                }
             }
         }
-        eprintln!("(thread) read_from_port: stop...");
-    };
+    }
 
-    pin_mut!(write_to_port, read_from_port);
-    future::select(write_to_port, read_from_port).await;
+    // blocking here would hold up a runtime thread until the UI catches up,
+    // a batch that does not fit is reported as dropped instead
+    if !pending.is_empty() && read_tx.try_send(pending.chunks).is_err() {
+        dropped_bytes += pending.bytes;
+    }
+    if dropped_bytes > 0 {
+        stats.lock().unwrap().dropped_bytes += dropped_bytes as u64;
+        state_tx.send(format!("[dropped]({})", dropped_bytes)).expect("Could not send through channel");
+    }
 
+    stats.lock().unwrap().closed();
     eprintln!("closing port...");
     state_tx.send(String::from("[close_port]()")).expect("Could not send through channel");
 }
@@ -0,0 +1,350 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::channel::oneshot;
+use regex::Regex;
+use rhai::{Dynamic, Engine, EvalAltResult};
+
+use crate::port::{open_port_async, DataDirection, Output, PortCommand, SharedPrompt, READ_CHANNEL_BOUND};
+use crate::stats::PortStats;
+
+const DEFAULT_EXPECT_TIMEOUT_MS: i64 = 5000;
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// how soon `expect` notices a prompt that has no newline yet
+const PROMPT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+//
+// Script API (Rhai, https://rhai.rs):
+//   send(text)                   write text as is
+//   sendln(text)                 write text and a newline, like the Send button
+//   expect(regex[, timeout_ms])  wait for a received line, or a prompt not yet ended by a newline,
+//                                matching regex, returns the line or prompt
+//   sleep(ms)
+//   set_dtr(bool), set_rts(bool)
+//   log(text), print(text)
+//   assert(condition, message), assert_match(text, regex, message)
+//
+pub const SCRIPT_HELP: &str = "send(text), sendln(text), expect(regex[, timeout_ms]), sleep(ms), \
+                               set_dtr(bool), set_rts(bool), log(text), assert(cond, msg), assert_match(text, regex, msg)";
+
+pub const CLI_USAGE: &str = "Usage: serial-tool --script <file> --port <port> [--baud <rate>]";
+
+#[derive(Debug)]
+pub enum ScriptMessage {
+    Log(String),
+    Done(Result<(), String>),
+}
+
+pub struct ScriptIo {
+    pub write_tx: UnboundedSender<PortCommand>,
+    // received lines, in order
+    pub line_rx: mpsc::Receiver<String>,
+    // the received text after the last newline
+    pub prompt: SharedPrompt,
+    pub log: Box<dyn Fn(String)>,
+    pub cancel_flag: Arc<Mutex<bool>>,
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn is_cancelled(cancel_flag: &Arc<Mutex<bool>>) -> bool {
+    *cancel_flag.lock().unwrap()
+}
+
+fn compile_regex(pattern: &str) -> ScriptResult<Regex> {
+    Regex::new(pattern).map_err(|e| e.to_string().into())
+}
+
+// blocks the calling thread until the script ends, so run it on its own thread
+pub fn run_script(source: &str, io: ScriptIo) -> Result<(), String> {
+    let ScriptIo { write_tx, line_rx, prompt, log, cancel_flag } = io;
+    let log = Rc::new(log);
+    let line_rx = Rc::new(RefCell::new(line_rx));
+
+    let mut engine = Engine::new();
+
+    let cancel = cancel_flag.clone();
+    engine.on_progress(move |_| {
+        if is_cancelled(&cancel) {
+            Some(Dynamic::from("Cancelled"))
+        } else {
+            None
+        }
+    });
+
+    let logger = log.clone();
+    engine.on_print(move |s| logger(s.to_string()));
+    let logger = log.clone();
+    engine.register_fn("log", move |s: &str| logger(s.to_string()));
+
+    let port_command = move |command: PortCommand| -> ScriptResult<()> {
+        write_tx.unbounded_send(command).map_err(|_| "The port is closed".into())
+    };
+
+    let send = port_command.clone();
+    engine.register_fn("send", move |s: &str| send(PortCommand::WriteBytes(s.as_bytes().to_vec())));
+    let send = port_command.clone();
+    engine.register_fn("sendln", move |s: &str| send(PortCommand::Write(s.to_string())));
+    let send = port_command.clone();
+    engine.register_fn("set_dtr", move |level: bool| send(PortCommand::SetDtr(level)));
+    let send = port_command;
+    engine.register_fn("set_rts", move |level: bool| send(PortCommand::SetRts(level)));
+
+    let cancel = cancel_flag.clone();
+    engine.register_fn("sleep", move |ms: i64| -> ScriptResult<()> {
+        let deadline = Instant::now() + Duration::from_millis(ms.max(0) as u64);
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            if is_cancelled(&cancel) {
+                return Err("Cancelled".into());
+            }
+            thread::sleep(remaining.min(CANCEL_POLL_INTERVAL));
+        }
+        Ok(())
+    });
+
+    // the prompt text an earlier `expect` returned, it is not matched again
+    // once more text follows it or its line ends
+    let matched_prompt = RefCell::new(String::new());
+    let expect = move |pattern: &str, timeout_ms: i64| -> ScriptResult<String> {
+        let regex = compile_regex(pattern)?;
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms.max(0) as u64);
+        let line_rx = line_rx.borrow();
+        let mut matched_prompt = matched_prompt.borrow_mut();
+        loop {
+            if is_cancelled(&cancel_flag) {
+                return Err("Cancelled".into());
+            }
+            let remaining = match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) => remaining,
+                None => return Err(format!("Timed out waiting for `{}`", pattern).into()),
+            };
+            match line_rx.recv_timeout(remaining.min(PROMPT_POLL_INTERVAL)) {
+                Ok(line) => {
+                    let matched = std::mem::take(&mut *matched_prompt);
+                    if regex.is_match(line.strip_prefix(matched.as_str()).unwrap_or(&line)) {
+                        return Ok(line);
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    let tail = prompt.lock().unwrap().clone();
+                    let fresh = tail.strip_prefix(matched_prompt.as_str()).unwrap_or(&tail);
+                    if !fresh.is_empty() && regex.is_match(fresh) {
+                        let fresh = fresh.to_string();
+                        *matched_prompt = tail;
+                        return Ok(fresh);
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err("The port is closed".into()),
+            }
+        }
+    };
+    let expect = Rc::new(expect);
+    let f = expect.clone();
+    engine.register_fn("expect", move |pattern: &str, timeout_ms: i64| f(pattern, timeout_ms));
+    engine.register_fn("expect", move |pattern: &str| expect(pattern, DEFAULT_EXPECT_TIMEOUT_MS));
+
+    engine.register_fn("assert", |condition: bool, message: &str| -> ScriptResult<()> {
+        if condition {
+            Ok(())
+        } else {
+            Err(format!("Assertion failed: {}", message).into())
+        }
+    });
+    engine.register_fn("assert_match", |text: &str, pattern: &str, message: &str| -> ScriptResult<()> {
+        if compile_regex(pattern)?.is_match(text) {
+            Ok(())
+        } else {
+            Err(format!("Assertion failed: {} (`{}` does not match `{}`)", message, text.trim_end(), pattern).into())
+        }
+    });
+
+    engine.run(source).map_err(|e| e.to_string())
+}
+
+pub fn spawn_script(
+    source: String,
+    write_tx: UnboundedSender<PortCommand>,
+    line_rx: mpsc::Receiver<String>,
+    prompt: SharedPrompt,
+    cancel_flag: Arc<Mutex<bool>>,
+    message_tx: glib::Sender<ScriptMessage>)
+{
+    thread::spawn(move || {
+        let log_tx = message_tx.clone();
+        let io = ScriptIo {
+            write_tx,
+            line_rx,
+            prompt,
+            log: Box::new(move |s| {
+                let _ = log_tx.send(ScriptMessage::Log(s));
+            }),
+            cancel_flag,
+        };
+        let result = run_script(&source, io);
+        let _ = message_tx.send(ScriptMessage::Done(result));
+    });
+}
+
+//
+// Headless mode: run a script against a port without the window,
+// received data goes to stdout, the exit code is 0 if the script passed.
+//
+pub fn run_headless(args: &[String]) -> i32 {
+    let mut script_path = None;
+    let mut port_name = None;
+    let mut baud_rate = 115200;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match (arg.as_str(), iter.next()) {
+            ("--script", Some(value)) => script_path = Some(value.clone()),
+            ("--port", Some(value)) => port_name = Some(value.clone()),
+            ("--baud", Some(value)) => match value.parse() {
+                Ok(rate) => baud_rate = rate,
+                Err(_) => {
+                    eprintln!("Invalid baud rate: {}\n{}", value, CLI_USAGE);
+                    return 2;
+                }
+            },
+            _ => {
+                eprintln!("{}", CLI_USAGE);
+                return 2;
+            }
+        }
+    }
+    let (script_path, port_name) = match (script_path, port_name) {
+        (Some(script_path), Some(port_name)) => (script_path, port_name),
+        _ => {
+            eprintln!("{}", CLI_USAGE);
+            return 2;
+        }
+    };
+    let source = match fs::read_to_string(&script_path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", script_path, e);
+            return 2;
+        }
+    };
+
+    glib::MainContext::default().block_on(run_headless_async(source, port_name, baud_rate))
+}
+
+async fn run_headless_async(source: String, port_name: String, baud_rate: u32) -> i32 {
+    let (write_tx, write_rx) = unbounded::<PortCommand>();
+    let (read_tx, read_rx) = glib::MainContext::sync_channel(glib::PRIORITY_DEFAULT, READ_CHANNEL_BOUND);
+    let (state_tx, state_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let (line_tx, line_rx) = mpsc::channel();
+
+    read_rx.attach(None, move |output: Output| {
+        for (_, text) in output.iter().filter(|(direction, _)| *direction == DataDirection::Rx) {
+            print!("{}", text);
+            for line in text.split_inclusive('\n') {
+                let _ = line_tx.send(line.to_string());
+            }
+        }
+        glib::Continue(true)
+    });
+
+    let (open_tx, open_rx) = oneshot::channel();
+    let mut open_tx = Some(open_tx);
+    state_rx.attach(None, move |msg: String| {
+        if msg.starts_with("[dropped]") {
+            eprintln!("Received data dropped: {}", msg);
+        } else if msg.starts_with("[open_port]") || msg.starts_with("[close_port]") {
+            if let Some(tx) = open_tx.take() {
+                let _ = tx.send(msg == "[open_port](ok)");
+            }
+        }
+        glib::Continue(true)
+    });
+
+    let port_close_flag = Arc::new(Mutex::new(false));
+    let triggers = Arc::new(Mutex::new(Vec::new()));
+    let decoder = Arc::new(Mutex::new(None));
+    let stats = Arc::new(Mutex::new(PortStats::default()));
+    let prompt = SharedPrompt::default();
+    let port_prompt = prompt.clone();
+    tokio::task::spawn(async move {
+        open_port_async(port_name, baud_rate, write_rx, read_tx, state_tx, port_close_flag, triggers, decoder, stats, port_prompt).await;
+    });
+    if open_rx.await != Ok(true) {
+        eprintln!("Failed to open the port!");
+        return 1;
+    }
+
+    let (message_tx, message_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let (done_tx, done_rx) = oneshot::channel();
+    let mut done_tx = Some(done_tx);
+    message_rx.attach(None, move |message| {
+        match message {
+            ScriptMessage::Log(s) => println!("[script] {}", s),
+            ScriptMessage::Done(result) => {
+                if let Some(tx) = done_tx.take() {
+                    let _ = tx.send(result);
+                }
+            }
+        }
+        glib::Continue(true)
+    });
+    spawn_script(source, write_tx, line_rx, prompt, Arc::new(Mutex::new(false)), message_tx);
+
+    match done_rx.await {
+        Ok(Ok(())) => {
+            eprintln!("Script passed");
+            0
+        }
+        Ok(Err(e)) => {
+            eprintln!("Script failed: {}", e);
+            1
+        }
+        Err(_) => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    fn run(source: &str, lines: &[&str], prompt: &str) -> (Result<(), String>, Vec<PortCommand>) {
+        let (write_tx, write_rx) = unbounded();
+        let (line_tx, line_rx) = mpsc::channel();
+        for line in lines {
+            line_tx.send(line.to_string()).unwrap();
+        }
+        let io = ScriptIo {
+            write_tx,
+            line_rx,
+            prompt: Arc::new(Mutex::new(prompt.to_string())),
+            log: Box::new(|_| {}),
+            cancel_flag: Arc::new(Mutex::new(false)),
+        };
+        let result = run_script(source, io);
+        // the script engine, and with it the sender, is gone
+        (result, futures::executor::block_on(write_rx.collect()))
+    }
+
+    #[test]
+    fn expect_line() {
+        let (result, _) = run(r#"assert_match(expect("OK", 100), "^OK", "reply")"#, &["boot\n", "OK\n"], "");
+        assert_eq!(result, Ok(()));
+        let (result, _) = run(r#"expect("OK", 50)"#, &["boot\n"], "");
+        assert!(result.unwrap_err().contains("Timed out"));
+    }
+
+    #[test]
+    fn expect_unterminated_prompt() {
+        let (result, commands) = run(r#"expect("login: $", 100); sendln("root")"#, &["Welcome\n"], "login: ");
+        assert_eq!(result, Ok(()));
+        assert!(matches!(commands.as_slice(), [PortCommand::Write(s)] if s == "root"));
+        // the same prompt is not matched twice
+        let (result, _) = run(r#"expect("login:", 100); expect("login:", 50)"#, &[], "login: ");
+        assert!(result.unwrap_err().contains("Timed out"));
+    }
+}
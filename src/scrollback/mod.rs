@@ -0,0 +1,101 @@
+use std::io;
+
+use crate::config;
+
+const SCROLLBACK_SETTING: &str = "scrollback_limit";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScrollbackLimit {
+    Unlimited,
+    Lines(usize),
+    Bytes(usize),
+}
+
+impl Default for ScrollbackLimit {
+    fn default() -> Self {
+        ScrollbackLimit::Lines(100_000)
+    }
+}
+
+impl ScrollbackLimit {
+    pub fn unit_names() -> [&'static str; 3] {
+        ["Unlimited", "Lines", "Bytes"]
+    }
+
+    pub fn from_unit(unit: usize, value: usize) -> Self {
+        match unit {
+            1 => ScrollbackLimit::Lines(value.max(1)),
+            2 => ScrollbackLimit::Bytes(value.max(1)),
+            _ => ScrollbackLimit::Unlimited,
+        }
+    }
+
+    // (index into `unit_names`, value)
+    pub fn unit(&self) -> (usize, usize) {
+        match self {
+            ScrollbackLimit::Unlimited => (0, 0),
+            ScrollbackLimit::Lines(n) => (1, *n),
+            ScrollbackLimit::Bytes(n) => (2, *n),
+        }
+    }
+
+    // trimming stops 10% below the limit, so it does not run on every new line
+    pub fn trim_target(limit: usize) -> usize {
+        limit - limit / 10
+    }
+}
+
+pub fn load_limit() -> ScrollbackLimit {
+    config::load_setting(SCROLLBACK_SETTING).map_or_else(ScrollbackLimit::default, |setting| parse_setting(&setting))
+}
+
+pub fn save_limit(limit: ScrollbackLimit) -> io::Result<()> {
+    config::save_setting(SCROLLBACK_SETTING, &format_setting(limit))
+}
+
+fn parse_setting(setting: &str) -> ScrollbackLimit {
+    //
+    // setting format: unlimited, lines:<n> or bytes:<n>
+    //
+    let (unit, value) = setting.split_once(':').unwrap_or((setting, "0"));
+    match (unit, value.parse::<usize>()) {
+        ("unlimited", _) => ScrollbackLimit::Unlimited,
+        ("lines", Ok(n)) if n > 0 => ScrollbackLimit::Lines(n),
+        ("bytes", Ok(n)) if n > 0 => ScrollbackLimit::Bytes(n),
+        _ => ScrollbackLimit::default(),
+    }
+}
+
+fn format_setting(limit: ScrollbackLimit) -> String {
+    match limit {
+        ScrollbackLimit::Unlimited => String::from("unlimited"),
+        ScrollbackLimit::Lines(n) => format!("lines:{}", n),
+        ScrollbackLimit::Bytes(n) => format!("bytes:{}", n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setting_round_trip() {
+        for limit in [ScrollbackLimit::Unlimited, ScrollbackLimit::Lines(5000), ScrollbackLimit::Bytes(1 << 20)] {
+            assert_eq!(parse_setting(&format_setting(limit)), limit);
+        }
+        for invalid in ["", "lines", "lines:0", "bytes:-1", "pages:3"] {
+            assert_eq!(parse_setting(invalid), ScrollbackLimit::default());
+        }
+    }
+
+    #[test]
+    fn units() {
+        for limit in [ScrollbackLimit::Unlimited, ScrollbackLimit::Lines(10), ScrollbackLimit::Bytes(20)] {
+            let (unit, value) = limit.unit();
+            assert_eq!(ScrollbackLimit::from_unit(unit, value), limit);
+        }
+        assert_eq!(ScrollbackLimit::from_unit(1, 0), ScrollbackLimit::Lines(1));
+        assert_eq!(ScrollbackLimit::trim_target(1000), 900);
+        assert_eq!(ScrollbackLimit::trim_target(5), 5);
+    }
+}
@@ -0,0 +1,91 @@
+use regex::{Regex, RegexBuilder};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    Off,
+    ShowMatching,
+    HideMatching,
+}
+
+impl FilterMode {
+    pub fn all() -> [FilterMode; 3] {
+        [FilterMode::Off, FilterMode::ShowMatching, FilterMode::HideMatching]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterMode::Off => "No Filter",
+            FilterMode::ShowMatching => "Show Matching Lines",
+            FilterMode::HideMatching => "Hide Matching Lines",
+        }
+    }
+
+    // whether a line stays visible under this filter
+    pub fn is_visible(&self, matcher: &Regex, line: &str) -> bool {
+        match self {
+            FilterMode::Off => true,
+            FilterMode::ShowMatching => matcher.is_match(line),
+            FilterMode::HideMatching => !matcher.is_match(line),
+        }
+    }
+}
+
+pub fn build_matcher(pattern: &str, case_sensitive: bool, use_regex: bool) -> Result<Regex, String> {
+    let pattern = if use_regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+// match ranges in character offsets, as used by `gtk::TextIter`
+pub fn find_matches(matcher: &Regex, text: &str) -> Vec<(i32, i32)> {
+    let mut matches = Vec::new();
+    let mut char_offset = 0;
+    let mut byte_offset = 0;
+    for m in matcher.find_iter(text).filter(|m| !m.as_str().is_empty()) {
+        char_offset += text[byte_offset..m.start()].chars().count() as i32;
+        let len = m.as_str().chars().count() as i32;
+        matches.push((char_offset, char_offset + len));
+        char_offset += len;
+        byte_offset = m.end();
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_is_escaped() {
+        let matcher = build_matcher("a.b", false, false).unwrap();
+        assert!(matcher.is_match("xA.By"));
+        assert!(!matcher.is_match("axb"));
+        assert!(build_matcher("a.b", true, false).unwrap().is_match("a.b"));
+        assert!(!build_matcher("a.b", true, false).unwrap().is_match("A.B"));
+        assert!(build_matcher("(", false, true).is_err());
+    }
+
+    #[test]
+    fn matches_in_characters() {
+        let matcher = build_matcher("ok", false, false).unwrap();
+        assert_eq!(find_matches(&matcher, "ökok ok"), vec![(2, 4), (5, 7)]);
+        // empty matches are skipped
+        let matcher = build_matcher("x*", true, true).unwrap();
+        assert_eq!(find_matches(&matcher, "äxx"), vec![(1, 3)]);
+    }
+
+    #[test]
+    fn filter() {
+        let matcher = build_matcher("err", false, false).unwrap();
+        assert!(FilterMode::Off.is_visible(&matcher, "fine"));
+        assert!(FilterMode::ShowMatching.is_visible(&matcher, "ERROR"));
+        assert!(!FilterMode::ShowMatching.is_visible(&matcher, "fine"));
+        assert!(!FilterMode::HideMatching.is_visible(&matcher, "ERROR"));
+    }
+}
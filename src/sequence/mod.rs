@@ -0,0 +1,138 @@
+use std::io;
+use std::time::Duration;
+
+use crate::config;
+
+const SEQUENCES_FILE: &str = "sequences.txt";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Dtr(bool),
+    Rts(bool),
+    Wait(Duration),
+}
+
+#[derive(Debug, Clone)]
+pub struct Sequence {
+    pub name: String,
+    pub spec: String,
+}
+
+impl Sequence {
+    pub fn new(name: &str, spec: &str) -> Self {
+        Sequence { name: name.to_string(), spec: spec.to_string() }
+    }
+}
+
+pub fn predefined_sequences() -> Vec<Sequence> {
+    vec![
+        Sequence::new("ESP32/ESP8266: Reset into Bootloader", "D0|R1|W0.1|D1|R0|W0.05|D0"),
+        Sequence::new("ESP32/ESP8266: Hard Reset", "D0|R1|W0.1|R0"),
+        Sequence::new("STM32: Reset into Bootloader (BOOT0 on RTS)", "R1|D1|W0.1|D0|W0.1|R0"),
+        Sequence::new("STM32: Reset into Application", "R0|D1|W0.1|D0"),
+    ]
+}
+
+pub fn load_user_sequences() -> Vec<Sequence> {
+    config::load_records(SEQUENCES_FILE)
+        .into_iter()
+        .filter(|record| record.len() >= 2)
+        .map(|record| Sequence::new(&record[0], &record[1]))
+        .collect()
+}
+
+pub fn save_user_sequences(sequences: &[Sequence]) -> io::Result<()> {
+    let records: Vec<Vec<String>> = sequences.iter()
+        .map(|s| vec![s.name.clone(), s.spec.clone()])
+        .collect();
+    config::save_records(SEQUENCES_FILE, &records)
+}
+
+pub fn parse_sequence(spec: &str) -> Result<Vec<Step>, String> {
+    //
+    // spec format (same as esptool custom reset sequences):
+    //   D0/D1 set DTR, R0/R1 set RTS, U<dtr><rts> set both, W<seconds> wait
+    //   steps separated by `|`, e.g. D0|R1|W0.1|D1|R0|W0.05|D0
+    //
+    let mut steps = Vec::new();
+    for token in spec.split('|').map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        let mut chars = token.chars();
+        let command = chars.next().unwrap_or(' ').to_ascii_uppercase();
+        let arg = chars.as_str();
+        match command {
+            'D' => steps.push(Step::Dtr(parse_level(arg, token)?)),
+            'R' => steps.push(Step::Rts(parse_level(arg, token)?)),
+            'U' if arg.len() == 2 && arg.is_ascii() => {
+                steps.push(Step::Dtr(parse_level(&arg[..1], token)?));
+                steps.push(Step::Rts(parse_level(&arg[1..], token)?));
+            }
+            // negative, NaN or too large to be a Duration
+            'W' => match arg.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()) {
+                Some(duration) => steps.push(Step::Wait(duration)),
+                None => return Err(format!("Invalid wait time: {}", token)),
+            },
+            _ => return Err(format!("Unknown step: {}", token)),
+        }
+    }
+    if steps.is_empty() {
+        return Err(String::from("Empty sequence"));
+    }
+    Ok(steps)
+}
+
+fn parse_level(arg: &str, token: &str) -> Result<bool, String> {
+    match arg {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(format!("Invalid level: {}", token)),
+    }
+}
+
+// the DTR/RTS levels left behind by a sequence
+pub fn final_levels(steps: &[Step]) -> (Option<bool>, Option<bool>) {
+    let mut dtr = None;
+    let mut rts = None;
+    for step in steps {
+        match step {
+            Step::Dtr(level) => dtr = Some(*level),
+            Step::Rts(level) => rts = Some(*level),
+            Step::Wait(_) => {}
+        }
+    }
+    (dtr, rts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let steps = parse_sequence("D0|R1|W0.1| u10 |").unwrap();
+        assert_eq!(steps, vec![
+            Step::Dtr(false),
+            Step::Rts(true),
+            Step::Wait(Duration::from_millis(100)),
+            Step::Dtr(true),
+            Step::Rts(false),
+        ]);
+        assert_eq!(final_levels(&steps), (Some(true), Some(false)));
+    }
+
+    #[test]
+    fn predefined_sequences_parse() {
+        for sequence in predefined_sequences() {
+            assert!(parse_sequence(&sequence.spec).is_ok(), "{}", sequence.name);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse_sequence(""), Err(String::from("Empty sequence")));
+        assert_eq!(parse_sequence("D2"), Err(String::from("Invalid level: D2")));
+        assert_eq!(parse_sequence("X1"), Err(String::from("Unknown step: X1")));
+        for wait in ["W-1", "WNaN", "W1e300", "W"] {
+            assert_eq!(parse_sequence(wait), Err(format!("Invalid wait time: {}", wait)));
+        }
+    }
+}
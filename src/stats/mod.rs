@@ -0,0 +1,157 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+//
+// Traffic counters, kept by the port loop and sampled by the UI about once
+// a second for the throughput. Transfers and Modbus own the raw port while
+// they run, their bytes are not counted.
+//
+#[derive(Debug, Default)]
+pub struct PortStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_lines: u64,
+    pub rx_frames: u64,
+    pub tx_writes: u64,
+    pub utf8_errors: u64,
+    pub frame_errors: u64,
+    pub read_errors: u64,
+    pub dropped_bytes: u64,
+    // set while the port is open
+    pub opened: Option<Instant>,
+    pub baud_rate: u32,
+    // bytes per second
+    rx_rate: f64,
+    tx_rate: f64,
+    peak_rx_rate: f64,
+    peak_tx_rate: f64,
+    // (time, rx_bytes, tx_bytes) of the last sample
+    last_sample: Option<(Instant, u64, u64)>,
+}
+
+pub type SharedStats = Arc<Mutex<PortStats>>;
+
+impl PortStats {
+    pub fn opened(&mut self, baud_rate: u32) {
+        self.opened = Some(Instant::now());
+        self.baud_rate = baud_rate;
+    }
+
+    pub fn closed(&mut self) {
+        self.opened = None;
+        self.rx_rate = 0.0;
+        self.tx_rate = 0.0;
+        self.last_sample = None;
+    }
+
+    pub fn sent(&mut self, bytes: usize) {
+        self.tx_bytes += bytes as u64;
+        self.tx_writes += 1;
+    }
+
+    // updates the throughput from the bytes counted since the last sample
+    pub fn sample(&mut self) {
+        let now = Instant::now();
+        if let Some((time, rx_bytes, tx_bytes)) = self.last_sample {
+            let seconds = now.duration_since(time).as_secs_f64();
+            if seconds > 0.0 {
+                self.rx_rate = (self.rx_bytes - rx_bytes) as f64 / seconds;
+                self.tx_rate = (self.tx_bytes - tx_bytes) as f64 / seconds;
+                self.peak_rx_rate = self.peak_rx_rate.max(self.rx_rate);
+                self.peak_tx_rate = self.peak_tx_rate.max(self.tx_rate);
+            }
+        }
+        self.last_sample = Some((now, self.rx_bytes, self.tx_bytes));
+    }
+
+    // the port stays open, so does its uptime
+    pub fn reset(&mut self) {
+        *self = PortStats { opened: self.opened, baud_rate: self.baud_rate, ..Default::default() };
+    }
+
+    // throughput with the share of the line it takes, at 10 bits per byte (8N1)
+    fn format_rate(&self, rate: f64) -> String {
+        if self.baud_rate == 0 {
+            return format!("{}/s", format_bytes(rate as u64));
+        }
+        let capacity = self.baud_rate as f64 / 10.0;
+        format!("{}/s ({:.0}% of {} baud)", format_bytes(rate as u64), rate / capacity * 100.0, self.baud_rate)
+    }
+
+    pub fn summary(&self) -> Vec<(&'static str, String)> {
+        let uptime = match self.opened {
+            Some(opened) => format_duration(opened.elapsed()),
+            None => String::from("Port closed"),
+        };
+        vec![
+            ("Uptime", uptime),
+            ("Bytes Received", format_bytes(self.rx_bytes)),
+            ("Bytes Sent", format_bytes(self.tx_bytes)),
+            ("Lines Received", self.rx_lines.to_string()),
+            ("Frames Decoded", self.rx_frames.to_string()),
+            ("Writes Sent", self.tx_writes.to_string()),
+            ("RX Throughput", self.format_rate(self.rx_rate)),
+            ("TX Throughput", self.format_rate(self.tx_rate)),
+            ("Peak RX Throughput", self.format_rate(self.peak_rx_rate)),
+            ("Peak TX Throughput", self.format_rate(self.peak_tx_rate)),
+            ("Invalid UTF-8 Lines", self.utf8_errors.to_string()),
+            ("Frame Errors", self.frame_errors.to_string()),
+            ("Read Errors", self.read_errors.to_string()),
+            ("Dropped Bytes", self.dropped_bytes.to_string()),
+        ]
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1048575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
+
+// `1d 02:03:04`
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let time = format!("{:02}:{:02}:{:02}", seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+    match seconds / 86400 {
+        0 => time,
+        days => format!("{}d {}", days, time),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1048576), "3.0 MiB");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "01:02:03");
+        assert_eq!(format_duration(Duration::from_secs(2 * 86400 + 59)), "2d 00:00:59");
+    }
+
+    #[test]
+    fn rates() {
+        let mut stats = PortStats::default();
+        stats.opened(9600);
+        stats.sent(10);
+        stats.rx_bytes = 1920;
+        // a sample two seconds ago with nothing counted yet
+        stats.last_sample = Some((Instant::now() - Duration::from_secs(2), 0, 0));
+        stats.sample();
+        assert!((stats.rx_rate - 960.0).abs() < 5.0);
+        assert_eq!(stats.peak_rx_rate, stats.rx_rate);
+        // 960 B/s is the whole line at 9600 baud, 8N1
+        assert_eq!(stats.format_rate(960.0), "960 B/s (100% of 9600 baud)");
+
+        stats.reset();
+        assert_eq!((stats.tx_bytes, stats.tx_writes, stats.peak_rx_rate), (0, 0, 0.0));
+        assert!(stats.opened.is_some());
+        assert_eq!(stats.baud_rate, 9600);
+        stats.closed();
+        assert_eq!(stats.summary()[0], ("Uptime", String::from("Port closed")));
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::port::DataDirection;
+
+//
+// Output line format:
+//   12:34:56.789 -> data       timestamp only
+//   12:34:56.789 [TX] data     timestamp and direction marker (TX shown)
+//   [RX] data                  direction marker only
+//
+
+pub fn marker(direction: DataDirection) -> &'static str {
+    match direction {
+        DataDirection::Rx => "[RX]",
+        DataDirection::Tx => "[TX]",
+    }
+}
+
+pub fn format_line(timestamp: Option<&str>, direction: Option<DataDirection>, data: &str) -> String {
+    match (timestamp, direction) {
+        (Some(timestamp), Some(direction)) => format!("{} {} {}", timestamp, marker(direction), data),
+        (Some(timestamp), None) => format!("{} -> {}", timestamp, data),
+        (None, Some(direction)) => format!("{} {}", marker(direction), data),
+        (None, None) => data.to_string(),
+    }
+}
+
+// (timestamp, direction, data) of a line in the format above
+pub fn parse_line(line: &str) -> (Option<&str>, Option<DataDirection>, &str) {
+    let mut timestamp = None;
+    let mut rest = line;
+    if let Some((first, remaining)) = line.split_once(' ') {
+        if !first.is_empty() && first.chars().all(|c| c.is_ascii_digit() || c == ':' || c == '.') {
+            timestamp = Some(first);
+            rest = remaining;
+        }
+    }
+
+    for direction in [DataDirection::Rx, DataDirection::Tx] {
+        if let Some(data) = rest.strip_prefix(marker(direction)).and_then(|r| r.strip_prefix(' ')) {
+            return (timestamp, Some(direction), data);
+        }
+    }
+    match (timestamp, rest.strip_prefix("-> ")) {
+        (Some(_), Some(data)) => (timestamp, None, data),
+        _ => (None, None, line),
+    }
+}
+
+// the device echoes a command right away, anything later is a reply
+const ECHO_TIMEOUT: Duration = Duration::from_secs(1);
+
+//
+// Detects the remote echo of sent lines, so it can be hidden.
+// A received line is an echo if it equals the oldest unanswered sent line,
+// or does so once a prompt in front of the echoed command is removed.
+//
+#[derive(Debug, Default)]
+pub struct EchoFilter {
+    expected: VecDeque<(String, Instant)>,
+}
+
+impl EchoFilter {
+    pub fn sent(&mut self, line: &str) {
+        self.expected.push_back((trim_line_end(line).to_string(), Instant::now()));
+    }
+
+    pub fn is_echo(&mut self, line: &str) -> bool {
+        self.expected.retain(|(_, sent_at)| sent_at.elapsed() < ECHO_TIMEOUT);
+        let line = trim_line_end(line);
+        let is_echo = match self.expected.front() {
+            Some((sent, _)) => line == sent || is_prompted(line, sent),
+            None => return false,
+        };
+        if is_echo {
+            self.expected.pop_front();
+        } else {
+            // the device does not echo, or its output got in between
+            self.expected.clear();
+        }
+        is_echo
+    }
+
+    pub fn clear(&mut self) {
+        self.expected.clear();
+    }
+}
+
+fn trim_line_end(line: &str) -> &str {
+    line.trim_end_matches(['\r', '\n'])
+}
+
+// `line` is `sent` behind a shell style prompt: `> `, `user@host:~$ `, `# `, `login: `
+fn is_prompted(line: &str, sent: &str) -> bool {
+    ["> ", "$ ", "# ", ": "].iter().any(|prompt| {
+        line.strip_suffix(sent)
+            .and_then(|prefix| prefix.strip_suffix(prompt))
+            .is_some_and(|prefix| !prefix.contains(char::is_whitespace))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn echo() {
+        let mut filter = EchoFilter::default();
+        filter.sent("version\r\n");
+        filter.sent("reboot\r\n");
+        assert!(filter.is_echo("version\r\n"));
+        assert!(filter.is_echo("root@board:~# reboot\r\n"));
+        assert!(!filter.is_echo("version"));
+    }
+
+    #[test]
+    fn replies_are_not_echoes() {
+        let mut filter = EchoFilter::default();
+        filter.sent("on");
+        assert!(!filter.is_echo("LED is on"));
+        filter.sent("1");
+        assert!(!filter.is_echo("channel 1"));
+    }
+}
@@ -0,0 +1,140 @@
+use std::io;
+
+use regex::Regex;
+
+use crate::{config, escape};
+
+const TRIGGERS_FILE: &str = "triggers.txt";
+
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub enabled: bool,
+    pub regex: Regex,
+    // as typed by the user, with C-style escapes
+    pub response: String,
+    // `response` with the escapes resolved, ready to be written to the port
+    pub response_bytes: Vec<u8>,
+}
+
+impl Trigger {
+    pub fn new(enabled: bool, pattern: &str, response: &str) -> Result<Self, String> {
+        let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+        let response_bytes = escape::unescape(response)?;
+        Ok(Trigger { enabled, regex, response: response.to_string(), response_bytes })
+    }
+}
+
+pub fn load_triggers() -> Vec<Trigger> {
+    config::load_records(TRIGGERS_FILE)
+        .into_iter()
+        .filter(|record| record.len() >= 3)
+        .filter_map(|record| Trigger::new(record[0] == "1", &record[1], &record[2]).ok())
+        .collect()
+}
+
+pub fn save_triggers(triggers: &[Trigger]) -> io::Result<()> {
+    let records: Vec<Vec<String>> = triggers.iter()
+        .map(|t| vec![
+            String::from(if t.enabled { "1" } else { "0" }),
+            t.regex.as_str().to_string(),
+            t.response.clone(),
+        ])
+        .collect();
+    config::save_records(TRIGGERS_FILE, &records)
+}
+
+pub fn parse_triggers(text: &str) -> Result<Vec<Trigger>, String> {
+    //
+    // one trigger per line: [x] <regex> => <response>
+    //   `[ ]` instead of `[x]` disables the trigger, the marker may be omitted
+    //   e.g. [x] Hit any key to stop autoboot => \x20
+    //        [x] login:\s*$ => root\r
+    //
+    let mut triggers = Vec::new();
+    for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let (enabled, rest) = if let Some(rest) = line.strip_prefix("[x]") {
+            (true, rest)
+        } else if let Some(rest) = line.strip_prefix("[ ]") {
+            (false, rest)
+        } else {
+            (true, line)
+        };
+        let (pattern, response) = match config::split_arrow(rest) {
+            Some(split) => split,
+            None => return Err(format!("Missing `=>`: {}", line)),
+        };
+        let trigger = Trigger::new(enabled, pattern, response).map_err(|e| format!("{}\n\n{}", e, line))?;
+        triggers.push(trigger);
+    }
+    Ok(triggers)
+}
+
+pub fn format_triggers(triggers: &[Trigger]) -> String {
+    triggers.iter()
+        .map(|t| format!("[{}] {} => {}\n", if t.enabled { "x" } else { " " }, t.regex.as_str(), t.response))
+        .collect()
+}
+
+//
+// Triggers also match against a line that has not been terminated yet
+// (prompts such as `login: ` usually have no newline), so each trigger
+// fires at most once per line.
+//
+#[derive(Debug, Default)]
+pub struct TriggerMatcher {
+    fired: Vec<usize>,
+}
+
+impl TriggerMatcher {
+    // responses of the triggers matching `text` that have not fired on this line yet
+    pub fn check(&mut self, triggers: &[Trigger], text: &str) -> Vec<Vec<u8>> {
+        let mut responses = Vec::new();
+        for (i, trigger) in triggers.iter().enumerate() {
+            if trigger.enabled && !self.fired.contains(&i) && trigger.regex.is_match(text) {
+                self.fired.push(i);
+                responses.push(trigger.response_bytes.clone());
+            }
+        }
+        responses
+    }
+
+    pub fn end_line(&mut self) {
+        self.fired.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format() {
+        let text = "[x] Hit any key => \\x20\n[ ] login:\\s*$ => root\\r\n# comment\nPassword: =>secret\n";
+        let triggers = parse_triggers(text).unwrap();
+        assert_eq!(triggers.len(), 3);
+        assert!(triggers[0].enabled && !triggers[1].enabled && triggers[2].enabled);
+        assert_eq!(triggers[0].response_bytes, b" ");
+        assert_eq!(triggers[1].regex.as_str(), "login:\\s*$");
+        assert_eq!(triggers[1].response_bytes, b"root\r");
+        assert_eq!(triggers[2].regex.as_str(), "Password:");
+        assert_eq!(
+            format_triggers(&triggers),
+            "[x] Hit any key => \\x20\n[ ] login:\\s*$ => root\\r\n[x] Password: => secret\n"
+        );
+        assert!(parse_triggers("login: root").unwrap_err().starts_with("Missing `=>`"));
+        assert!(parse_triggers("login => \\q").is_err());
+    }
+
+    #[test]
+    fn fires_once_per_line() {
+        let triggers = parse_triggers("login: => root\\n\n[ ] disabled => x").unwrap();
+        let mut matcher = TriggerMatcher::default();
+        assert!(matcher.check(&triggers, "log").is_empty());
+        assert_eq!(matcher.check(&triggers, "login: "), vec![b"root\n".to_vec()]);
+        // the same unterminated line is checked again as more data arrives
+        assert!(matcher.check(&triggers, "login: ro").is_empty());
+        assert!(matcher.check(&triggers, "disabled").is_empty());
+        matcher.end_line();
+        assert_eq!(matcher.check(&triggers, "login: ").len(), 1);
+    }
+}
@@ -0,0 +1,601 @@
+use std::{fs, io};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout;
+
+use crate::checksum;
+use crate::zmodem;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+const CRC: u8 = b'C';
+
+const MAX_RETRIES: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Xmodem,
+    XmodemCrc,
+    Xmodem1k,
+    Ymodem,
+    Zmodem,
+}
+
+impl Protocol {
+    pub fn all() -> [Protocol; 5] {
+        [Protocol::Xmodem, Protocol::XmodemCrc, Protocol::Xmodem1k, Protocol::Ymodem, Protocol::Zmodem]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::Xmodem => "XMODEM",
+            Protocol::XmodemCrc => "XMODEM-CRC",
+            Protocol::Xmodem1k => "XMODEM-1K",
+            Protocol::Ymodem => "YMODEM",
+            Protocol::Zmodem => "ZMODEM",
+        }
+    }
+
+    // batch protocols carry file names, so they send several files
+    // and receive into a directory
+    pub fn is_batch(&self) -> bool {
+        matches!(self, Protocol::Ymodem | Protocol::Zmodem)
+    }
+
+    fn block_size(&self) -> usize {
+        match self {
+            Protocol::Xmodem | Protocol::XmodemCrc => 128,
+            Protocol::Xmodem1k | Protocol::Ymodem | Protocol::Zmodem => 1024,
+        }
+    }
+
+    fn use_crc(&self) -> bool {
+        *self != Protocol::Xmodem
+    }
+}
+
+#[derive(Debug)]
+pub enum Direction {
+    Send(Vec<PathBuf>),
+    // a file for XMODEM, a directory for batch protocols
+    Receive(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct TransferJob {
+    pub protocol: Protocol,
+    pub direction: Direction,
+    // ZMODEM only: continue partial files
+    pub resume: bool,
+    pub cancel_flag: Arc<Mutex<bool>>,
+}
+
+enum Packet {
+    Data(u8, Vec<u8>),
+    Eot,
+    Cancel,
+    Bad,
+}
+
+pub(crate) struct Session<'a, S> {
+    port: &'a mut S,
+    buf: BytesMut,
+    cancel_flag: Arc<Mutex<bool>>,
+    state_tx: &'a glib::Sender<String>,
+    retries: u32,
+}
+
+pub async fn run_transfer<S>(port: &mut S, pending: BytesMut, job: TransferJob, state_tx: &glib::Sender<String>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let value = match transfer(port, pending, job, state_tx).await {
+        Ok(()) => String::from("ok"),
+        Err(e) => {
+            eprintln!("transfer failed: {}", e);
+            e.to_string()
+        }
+    };
+    state_tx.send(format!("[transfer_done]({})", value)).expect("Could not send through channel");
+}
+
+async fn transfer<S>(port: &mut S, pending: BytesMut, job: TransferJob, state_tx: &glib::Sender<String>) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut session = Session {
+        port,
+        buf: pending,
+        cancel_flag: job.cancel_flag,
+        state_tx,
+        retries: 0,
+    };
+
+    match (job.protocol, job.direction) {
+        (Protocol::Zmodem, Direction::Send(files)) => zmodem::send_files(&mut session, &files, job.resume).await,
+        (Protocol::Zmodem, Direction::Receive(path)) => zmodem::receive_files(&mut session, &path, job.resume).await,
+        (protocol, Direction::Send(files)) => session.send_files(protocol, &files).await,
+        (protocol, Direction::Receive(path)) => session.receive_files(protocol, &path).await,
+    }
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> Session<'a, S> {
+    pub(crate) fn report(&self, event: &str, value: String) {
+        self.state_tx.send(format!("[{}]({})", event, value)).expect("Could not send through channel");
+    }
+
+    pub(crate) fn retry(&mut self) -> io::Result<()> {
+        self.retries += 1;
+        self.report("transfer_retry", self.retries.to_string());
+        if self.retries > MAX_RETRIES {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "Too many retries"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn reset_retries(&mut self) {
+        self.retries = 0;
+    }
+
+    pub(crate) async fn check_cancel(&mut self) -> io::Result<()> {
+        if *self.cancel_flag.lock().unwrap() {
+            self.port.write_all(&[CAN; 5]).await?;
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "Cancelled"));
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.port.write_all(data).await
+    }
+
+    pub(crate) async fn read_byte(&mut self, wait: Duration) -> io::Result<Option<u8>> {
+        if !self.buf.has_remaining() {
+            let mut chunk = [0u8; 1024];
+            let n = match timeout(wait, self.port.read(&mut chunk)).await {
+                Ok(result) => result?,
+                Err(_) => return Ok(None),
+            };
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Port closed"));
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+        Ok(Some(self.buf.get_u8()))
+    }
+
+    async fn purge(&mut self) -> io::Result<()> {
+        self.buf.clear();
+        while self.read_byte(Duration::from_secs(1)).await?.is_some() {}
+        Ok(())
+    }
+
+    async fn remote_cancelled(&mut self) -> io::Result<bool> {
+        Ok(self.read_byte(Duration::from_secs(1)).await? == Some(CAN))
+    }
+
+    //
+    // sender
+    //
+
+    async fn send_files(&mut self, protocol: Protocol, files: &[PathBuf]) -> io::Result<()> {
+        for path in files {
+            let data = fs::read(path)?;
+            let name = file_name_of(path);
+            self.report("transfer_file", name.clone());
+            self.retries = 0;
+
+            let mut crc = self.wait_for_receiver().await?;
+            if protocol == Protocol::Ymodem {
+                let header = format!("{}\0{}", name, data.len());
+                // a long name needs a 1K block, truncating it would lose the size
+                let header_size = match header.len() {
+                    0..=128 => 128,
+                    129..=1024 => 1024,
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("File name too long: {}", name))),
+                };
+                self.send_block(0, header.as_bytes(), header_size, crc, 0).await?;
+                crc = self.wait_for_receiver().await?;
+            }
+
+            // 1K blocks require CRC, fall back to 128 bytes for a checksum receiver
+            let block_size = if crc { protocol.block_size() } else { 128 };
+            let mut num: u8 = 1;
+            let mut sent = 0;
+            for chunk in data.chunks(block_size) {
+                self.send_block(num, chunk, block_size, crc, SUB).await?;
+                num = num.wrapping_add(1);
+                sent += chunk.len();
+                self.report("transfer_progress", format!("{}/{}", sent, data.len()));
+            }
+            self.send_eot().await?;
+
+            if protocol != Protocol::Ymodem {
+                break;
+            }
+        }
+
+        if protocol == Protocol::Ymodem {
+            let crc = self.wait_for_receiver().await?;
+            self.send_block(0, &[], 128, crc, 0).await?;
+        }
+        Ok(())
+    }
+
+    async fn wait_for_receiver(&mut self) -> io::Result<bool> {
+        for _ in 0..60 {
+            self.check_cancel().await?;
+            match self.read_byte(Duration::from_secs(1)).await? {
+                Some(CRC) => return Ok(true),
+                Some(NAK) => return Ok(false),
+                Some(CAN) if self.remote_cancelled().await? => {
+                    return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Cancelled by remote"));
+                }
+                _ => {}
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "Receiver not ready"))
+    }
+
+    async fn send_block(&mut self, num: u8, data: &[u8], block_size: usize, crc: bool, pad: u8) -> io::Result<()> {
+        let mut frame = Vec::with_capacity(block_size + 5);
+        frame.push(if block_size == 1024 { STX } else { SOH });
+        frame.push(num);
+        frame.push(!num);
+        frame.extend_from_slice(data);
+        frame.resize(3 + block_size, pad);
+        if crc {
+            let value = checksum::crc16_xmodem(&frame[3..]);
+            frame.extend_from_slice(&value.to_be_bytes());
+        } else {
+            frame.push(checksum::sum8(&frame[3..]));
+        }
+
+        loop {
+            self.check_cancel().await?;
+            self.port.write_all(&frame).await?;
+            if self.wait_for_ack().await? {
+                // the retry limit is per block, not per file
+                self.reset_retries();
+                return Ok(());
+            }
+            self.retry()?;
+        }
+    }
+
+    async fn send_eot(&mut self) -> io::Result<()> {
+        loop {
+            self.check_cancel().await?;
+            self.port.write_all(&[EOT]).await?;
+            if self.wait_for_ack().await? {
+                self.reset_retries();
+                return Ok(());
+            }
+            self.retry()?;
+        }
+    }
+
+    async fn wait_for_ack(&mut self) -> io::Result<bool> {
+        loop {
+            match self.read_byte(Duration::from_secs(10)).await? {
+                Some(ACK) => return Ok(true),
+                Some(NAK) | None => return Ok(false),
+                Some(CAN) if self.remote_cancelled().await? => {
+                    return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Cancelled by remote"));
+                }
+                // stale 'C' or line noise, keep waiting
+                _ => {}
+            }
+        }
+    }
+
+    //
+    // receiver
+    //
+
+    async fn receive_files(&mut self, protocol: Protocol, path: &Path) -> io::Result<()> {
+        let crc = protocol.use_crc();
+        if protocol != Protocol::Ymodem {
+            self.report("transfer_file", file_name_of(path));
+            let mut data = self.receive_data(crc).await?;
+            while data.last() == Some(&SUB) {
+                data.pop();
+            }
+            return fs::write(path, data);
+        }
+
+        loop {
+            self.retries = 0;
+            let header = match self.start_receive(crc).await? {
+                Packet::Data(0, header) => header,
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing YMODEM header")),
+            };
+            let (name, size) = parse_header(&header);
+            // an empty name ends the batch, one that is empty without its path can't be written
+            let is_end = header.first().copied().unwrap_or(0) == 0;
+            if name.is_empty() && !is_end {
+                self.port.write_all(&[CAN; 5]).await?;
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid file name in YMODEM header"));
+            }
+            self.port.write_all(&[ACK]).await?;
+            if is_end {
+                return Ok(());
+            }
+            self.report("transfer_file", name.clone());
+
+            let mut data = self.receive_data(crc).await?;
+            match size {
+                Some(size) => data.truncate(size),
+                None => {
+                    while data.last() == Some(&SUB) {
+                        data.pop();
+                    }
+                }
+            }
+            fs::write(path.join(name), data)?;
+        }
+    }
+
+    async fn start_receive(&mut self, crc: bool) -> io::Result<Packet> {
+        for _ in 0..20 {
+            self.check_cancel().await?;
+            self.port.write_all(&[if crc { CRC } else { NAK }]).await?;
+            if let Some(packet) = self.read_packet(crc, Duration::from_secs(3)).await? {
+                return Ok(packet);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "Sender did not start"))
+    }
+
+    async fn receive_data(&mut self, crc: bool) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut expected: u8 = 1;
+        let mut packet = self.start_receive(crc).await?;
+
+        loop {
+            match packet {
+                Packet::Data(num, block) if num == expected => {
+                    data.extend_from_slice(&block);
+                    self.port.write_all(&[ACK]).await?;
+                    expected = expected.wrapping_add(1);
+                    self.report("transfer_progress", format!("{}/0", data.len()));
+                }
+                Packet::Data(num, _) if num == expected.wrapping_sub(1) => {
+                    // the sender missed our ACK
+                    self.port.write_all(&[ACK]).await?;
+                }
+                Packet::Data(..) => {
+                    self.port.write_all(&[CAN; 5]).await?;
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Block sequence error"));
+                }
+                Packet::Bad => {
+                    self.retry()?;
+                    self.purge().await?;
+                    self.port.write_all(&[NAK]).await?;
+                }
+                Packet::Eot => {
+                    self.port.write_all(&[ACK]).await?;
+                    return Ok(data);
+                }
+                Packet::Cancel => {
+                    return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Cancelled by remote"));
+                }
+            }
+
+            self.check_cancel().await?;
+            packet = loop {
+                match self.read_packet(crc, Duration::from_secs(10)).await? {
+                    Some(p) => break p,
+                    None => {
+                        self.retry()?;
+                        self.port.write_all(&[NAK]).await?;
+                    }
+                }
+            };
+        }
+    }
+
+    async fn read_packet(&mut self, crc: bool, wait: Duration) -> io::Result<Option<Packet>> {
+        let size = match self.read_byte(wait).await? {
+            Some(SOH) => 128,
+            Some(STX) => 1024,
+            Some(EOT) => return Ok(Some(Packet::Eot)),
+            Some(CAN) => {
+                if self.remote_cancelled().await? {
+                    return Ok(Some(Packet::Cancel));
+                }
+                return Ok(Some(Packet::Bad));
+            }
+            Some(_) => return Ok(Some(Packet::Bad)),
+            None => return Ok(None),
+        };
+
+        let len = 2 + size + if crc { 2 } else { 1 };
+        let mut frame = Vec::with_capacity(len);
+        while frame.len() < len {
+            match self.read_byte(Duration::from_secs(1)).await? {
+                Some(b) => frame.push(b),
+                None => return Ok(Some(Packet::Bad)),
+            }
+        }
+
+        let num = frame[0];
+        if num != !frame[1] {
+            return Ok(Some(Packet::Bad));
+        }
+        let block = &frame[2..2 + size];
+        let valid = if crc {
+            checksum::crc16_xmodem(block).to_be_bytes() == frame[2 + size..]
+        } else {
+            checksum::sum8(block) == frame[2 + size]
+        };
+        if !valid {
+            return Ok(Some(Packet::Bad));
+        }
+        self.retries = 0;
+        Ok(Some(Packet::Data(num, block.to_vec())))
+    }
+}
+
+pub(crate) fn file_name_of(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => String::from(""),
+    }
+}
+
+pub(crate) fn parse_header(header: &[u8]) -> (String, Option<usize>) {
+    //
+    // header format: <file name>\0<size> [<mtime> ...]\0
+    //
+    let mut fields = header.split(|b| *b == 0);
+    let name = String::from_utf8_lossy(fields.next().unwrap_or(&[])).to_string();
+    // never write outside the chosen directory
+    let name = file_name_of(Path::new(&name));
+    let size = fields.next()
+        .and_then(|f| String::from_utf8_lossy(f).split(' ').next().map(|s| s.to_string()))
+        .and_then(|s| s.parse::<usize>().ok());
+    (name, size)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use tokio::io::duplex;
+
+    use super::*;
+
+    // an empty directory of its own for each test
+    pub(crate) fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("serial-tool-xmodem-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    pub(crate) fn job(protocol: Protocol, direction: Direction, resume: bool) -> TransferJob {
+        TransferJob { protocol, direction, resume, cancel_flag: Arc::new(Mutex::new(false)) }
+    }
+
+    // runs a sender and a receiver against each other
+    pub(crate) async fn send_and_receive(protocol: Protocol, files: Vec<PathBuf>, target: PathBuf, resume: bool) {
+        let (mut a, mut b) = duplex(4096);
+        // the progress reports are not looked at, the receivers only have to stay around
+        let (state_tx, _state_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let (sent, received) = tokio::join!(
+            transfer(&mut a, BytesMut::new(), job(protocol, Direction::Send(files), resume), &state_tx),
+            transfer(&mut b, BytesMut::new(), job(protocol, Direction::Receive(target), resume), &state_tx),
+        );
+        sent.unwrap();
+        received.unwrap();
+    }
+
+    pub(crate) fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn xmodem_round_trip() {
+        let dir = test_dir("xmodem");
+        let data = data(3000);
+        fs::write(dir.join("source.bin"), &data).unwrap();
+        for protocol in [Protocol::Xmodem, Protocol::XmodemCrc, Protocol::Xmodem1k] {
+            let target = dir.join(format!("{}.bin", protocol.name()));
+            send_and_receive(protocol, vec![dir.join("source.bin")], target.clone(), false).await;
+            assert_eq!(fs::read(target).unwrap(), data, "{}", protocol.name());
+        }
+    }
+
+    #[tokio::test]
+    async fn ymodem_batch() {
+        let dir = test_dir("ymodem");
+        let (source, target) = (dir.join("source"), dir.join("target"));
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        // the size in the header keeps trailing SUB bytes, a long name moves it to a 1K header
+        let long_name = format!("{}.bin", "n".repeat(150));
+        let files = [(String::from("fw(1).bin"), [data(1500), vec![SUB; 3]].concat()), (long_name, data(10))];
+        for (name, data) in files.iter() {
+            fs::write(source.join(name), data).unwrap();
+        }
+
+        let paths = files.iter().map(|(name, _)| source.join(name)).collect();
+        send_and_receive(Protocol::Ymodem, paths, target.clone(), false).await;
+        for (name, data) in files.iter() {
+            assert_eq!(fs::read(target.join(name)).unwrap(), *data, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_are_counted_per_block() {
+        let dir = test_dir("retries");
+        let data = data(128 * (MAX_RETRIES as usize + 2));
+        fs::write(dir.join("source.bin"), &data).unwrap();
+        let (mut port, mut remote) = duplex(4096);
+        let (state_tx, _state_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+        // a receiver that rejects the first copy of every block
+        let receiver = async move {
+            let mut received = Vec::new();
+            let mut rejected = false;
+            remote.write_all(&[CRC]).await.unwrap();
+            loop {
+                let mut frame = vec![0u8; 1];
+                // the sender gave up and closed the port
+                if remote.read_exact(&mut frame).await.is_err() {
+                    return received;
+                }
+                if frame[0] == EOT {
+                    remote.write_all(&[ACK]).await.unwrap();
+                    return received;
+                }
+                frame.resize(133, 0);
+                remote.read_exact(&mut frame[1..]).await.unwrap();
+                if rejected {
+                    received.extend_from_slice(&frame[3..131]);
+                    remote.write_all(&[ACK]).await.unwrap();
+                } else {
+                    remote.write_all(&[NAK]).await.unwrap();
+                }
+                rejected = !rejected;
+            }
+        };
+        let job = job(Protocol::XmodemCrc, Direction::Send(vec![dir.join("source.bin")]), false);
+        let sender = async move {
+            let sent = transfer(&mut port, BytesMut::new(), job, &state_tx).await;
+            drop(port);
+            sent
+        };
+        let (sent, received) = tokio::join!(sender, receiver);
+        sent.unwrap();
+        assert_eq!(received, data);
+    }
+
+    #[tokio::test]
+    async fn unusable_ymodem_name_is_rejected() {
+        let (mut port, mut remote) = duplex(4096);
+        let (state_tx, _state_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let mut block = b"..\x005".to_vec();
+        block.resize(128, 0);
+        let mut frame = vec![SOH, 0, 0xff];
+        frame.extend_from_slice(&block);
+        frame.extend_from_slice(&checksum::crc16_xmodem(&block).to_be_bytes());
+        remote.write_all(&frame).await.unwrap();
+
+        let job = job(Protocol::Ymodem, Direction::Receive(test_dir("bad-name")), false);
+        let error = transfer(&mut port, BytesMut::new(), job, &state_tx).await.unwrap_err();
+        assert_eq!(error.to_string(), "Invalid file name in YMODEM header");
+        // the sender is told to stop
+        let mut reply = [0u8; 6];
+        remote.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, [CRC, CAN, CAN, CAN, CAN, CAN]);
+    }
+}
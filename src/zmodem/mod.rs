@@ -0,0 +1,658 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::checksum::{crc16_xmodem, crc32};
+use crate::xmodem::{file_name_of, parse_header, Session};
+
+const ZPAD: u8 = b'*';
+const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+const CAN: u8 = 0x18;
+const BS: u8 = 0x08;
+
+// frame types
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZSKIP: u8 = 5;
+const ZNAK: u8 = 6;
+const ZABORT: u8 = 7;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZFERR: u8 = 12;
+const ZCRC: u8 = 13;
+const ZCHALLENGE: u8 = 14;
+const ZCAN: u8 = 16;
+
+// subpacket ends
+const ZCRCE: u8 = b'h';
+const ZCRCG: u8 = b'i';
+const ZCRCQ: u8 = b'j';
+const ZCRCW: u8 = b'k';
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+// ZRINIT capabilities
+const CANFDX: u8 = 0x01;
+const CANOVIO: u8 = 0x02;
+const CANFC32: u8 = 0x20;
+
+// ZFILE conversion option asking the receiver to resume a partial file
+const ZCRESUM: u8 = 3;
+
+const SUBPACKET_SIZE: usize = 1024;
+const MAX_SUBPACKET_SIZE: usize = 8192;
+// number of subpackets sent before waiting for a ZACK
+const WINDOW_PACKETS: usize = 8;
+
+// start of the hex ZRQINIT header sent by `sz`
+pub const ZRQINIT_PATTERN: &[u8] = b"*\x18B00";
+
+pub fn abort_sequence() -> Vec<u8> {
+    let mut seq = vec![CAN; 8];
+    seq.extend_from_slice(&[BS; 8]);
+    seq
+}
+
+struct Header {
+    frame_type: u8,
+    // ZP0..ZP3, or ZF3..ZF0 for flag headers
+    data: [u8; 4],
+}
+
+impl Header {
+    fn with_position(frame_type: u8, position: u64) -> Self {
+        Header { frame_type, data: (position as u32).to_le_bytes() }
+    }
+
+    fn with_flags(frame_type: u8, zf0: u8) -> Self {
+        Header { frame_type, data: [0, 0, 0, zf0] }
+    }
+
+    fn position(&self) -> u64 {
+        u32::from_le_bytes(self.data) as u64
+    }
+
+    fn zf0(&self) -> u8 {
+        self.data[3]
+    }
+}
+
+enum Zbyte {
+    Data(u8),
+    End(u8),
+}
+
+struct Zmodem<'s, 'a, S> {
+    session: &'s mut Session<'a, S>,
+    // set by the last binary header, selects the CRC of the subpackets that follow
+    crc32: bool,
+    // ask for (sending) or allow (receiving) ZCRESUM
+    resume: bool,
+}
+
+pub(crate) async fn send_files<S>(session: &mut Session<'_, S>, files: &[PathBuf], resume: bool) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut zmodem = Zmodem { session, crc32: false, resume };
+    zmodem.send(files).await
+}
+
+pub(crate) async fn receive_files<S>(session: &mut Session<'_, S>, dir: &Path, resume: bool) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut zmodem = Zmodem { session, crc32: false, resume };
+    zmodem.receive(dir).await
+}
+
+fn aborted() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "Cancelled by remote")
+}
+
+impl<'s, 'a, S: AsyncRead + AsyncWrite + Unpin> Zmodem<'s, 'a, S> {
+    //
+    // framing
+    //
+
+    async fn send_hex_header(&mut self, header: Header) -> io::Result<()> {
+        let mut raw = vec![header.frame_type];
+        raw.extend_from_slice(&header.data);
+        let crc = crc16_xmodem(&raw);
+        raw.extend_from_slice(&crc.to_be_bytes());
+
+        let mut frame = vec![ZPAD, ZPAD, ZDLE, ZHEX];
+        for b in raw {
+            frame.extend_from_slice(format!("{:02x}", b).as_bytes());
+        }
+        frame.extend_from_slice(&[b'\r', b'\n' | 0x80]);
+        if header.frame_type != ZACK && header.frame_type != ZFIN {
+            frame.push(XON);
+        }
+        self.session.write(&frame).await
+    }
+
+    async fn send_bin_header(&mut self, header: Header) -> io::Result<()> {
+        let mut raw = vec![header.frame_type];
+        raw.extend_from_slice(&header.data);
+        let crc = crc16_xmodem(&raw);
+        raw.extend_from_slice(&crc.to_be_bytes());
+
+        let mut frame = vec![ZPAD, ZDLE, ZBIN];
+        escape_into(&mut frame, &raw);
+        self.session.write(&frame).await
+    }
+
+    async fn send_subpacket(&mut self, data: &[u8], end: u8) -> io::Result<()> {
+        let mut raw = data.to_vec();
+        raw.push(end);
+        let crc = crc16_xmodem(&raw);
+
+        let mut frame = Vec::with_capacity(data.len() * 2 + 8);
+        escape_into(&mut frame, data);
+        frame.extend_from_slice(&[ZDLE, end]);
+        escape_into(&mut frame, &crc.to_be_bytes());
+        if end == ZCRCW {
+            frame.push(XON);
+        }
+        self.session.write(&frame).await
+    }
+
+    async fn read_escaped(&mut self, wait: Duration) -> io::Result<Option<Zbyte>> {
+        // ZDLE has the same value as CAN
+        let mut cans = 1;
+        let mut escaped = false;
+        loop {
+            let b = match self.session.read_byte(wait).await? {
+                Some(b) => b,
+                None => return Ok(None),
+            };
+            if b & 0x7f == XON || b & 0x7f == XOFF {
+                continue;
+            }
+            if !escaped {
+                if b == ZDLE {
+                    escaped = true;
+                    continue;
+                }
+                return Ok(Some(Zbyte::Data(b)));
+            }
+            match b {
+                ZDLE => {
+                    cans += 1;
+                    if cans >= 5 {
+                        return Err(aborted());
+                    }
+                }
+                ZCRCE | ZCRCG | ZCRCQ | ZCRCW => return Ok(Some(Zbyte::End(b))),
+                ZRUB0 => return Ok(Some(Zbyte::Data(0x7f))),
+                ZRUB1 => return Ok(Some(Zbyte::Data(0xff))),
+                _ => return Ok(Some(Zbyte::Data(b ^ 0x40))),
+            }
+        }
+    }
+
+    async fn read_escaped_bytes(&mut self, len: usize) -> io::Result<Option<Vec<u8>>> {
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            match self.read_escaped(Duration::from_secs(1)).await? {
+                Some(Zbyte::Data(b)) => bytes.push(b),
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(bytes))
+    }
+
+    async fn read_header(&mut self, wait: Duration) -> io::Result<Option<Header>> {
+        //
+        // hunt for: ZPAD [ZPAD] ZDLE <format>
+        //
+        let mut got_zpad = false;
+        let mut got_zdle = false;
+        let mut cans = 0;
+        let mut garbage = 0;
+        loop {
+            let b = match self.session.read_byte(wait).await? {
+                Some(b) => b,
+                None => return Ok(None),
+            };
+
+            if got_zdle {
+                match b {
+                    ZHEX => return self.read_hex_header().await,
+                    ZBIN => {
+                        self.crc32 = false;
+                        return self.read_bin_header().await;
+                    }
+                    ZBIN32 => {
+                        self.crc32 = true;
+                        return self.read_bin_header().await;
+                    }
+                    _ => {}
+                }
+            }
+
+            if b == CAN && !got_zpad {
+                cans += 1;
+                if cans >= 5 {
+                    return Err(aborted());
+                }
+            } else if b != CAN {
+                cans = 0;
+            }
+
+            got_zdle = got_zpad && b == ZDLE;
+            got_zpad = b == ZPAD;
+
+            garbage += 1;
+            if garbage > 4096 {
+                return Ok(None);
+            }
+        }
+    }
+
+    async fn read_hex_header(&mut self) -> io::Result<Option<Header>> {
+        let mut raw = Vec::with_capacity(7);
+        for _ in 0..7 {
+            let mut digits = [0u8; 2];
+            for digit in digits.iter_mut() {
+                *digit = match self.session.read_byte(Duration::from_secs(1)).await? {
+                    Some(b) => b,
+                    None => return Ok(None),
+                };
+            }
+            let value = std::str::from_utf8(&digits).ok().and_then(|s| u8::from_str_radix(s, 16).ok());
+            match value {
+                Some(v) => raw.push(v),
+                None => return Ok(None),
+            }
+        }
+        if crc16_xmodem(&raw[..5]).to_be_bytes() != raw[5..] {
+            return Ok(None);
+        }
+        Ok(Some(Header { frame_type: raw[0], data: [raw[1], raw[2], raw[3], raw[4]] }))
+    }
+
+    async fn read_bin_header(&mut self) -> io::Result<Option<Header>> {
+        let len = if self.crc32 { 9 } else { 7 };
+        let raw = match self.read_escaped_bytes(len).await? {
+            Some(raw) => raw,
+            None => return Ok(None),
+        };
+        let valid = if self.crc32 {
+            crc32(&raw[..5]).to_le_bytes() == raw[5..]
+        } else {
+            crc16_xmodem(&raw[..5]).to_be_bytes() == raw[5..]
+        };
+        if !valid {
+            return Ok(None);
+        }
+        Ok(Some(Header { frame_type: raw[0], data: [raw[1], raw[2], raw[3], raw[4]] }))
+    }
+
+    async fn read_subpacket(&mut self) -> io::Result<Option<(Vec<u8>, u8)>> {
+        let mut data = Vec::new();
+        let end = loop {
+            match self.read_escaped(Duration::from_secs(10)).await? {
+                Some(Zbyte::Data(b)) => {
+                    data.push(b);
+                    if data.len() > MAX_SUBPACKET_SIZE {
+                        return Ok(None);
+                    }
+                }
+                Some(Zbyte::End(end)) => break end,
+                None => return Ok(None),
+            }
+        };
+
+        let crc = match self.read_escaped_bytes(if self.crc32 { 4 } else { 2 }).await? {
+            Some(crc) => crc,
+            None => return Ok(None),
+        };
+        data.push(end);
+        let valid = if self.crc32 {
+            crc32(&data).to_le_bytes() == crc[..]
+        } else {
+            crc16_xmodem(&data).to_be_bytes() == crc[..]
+        };
+        data.pop();
+        if !valid {
+            return Ok(None);
+        }
+        Ok(Some((data, end)))
+    }
+
+    //
+    // receiver
+    //
+
+    async fn send_zrinit(&mut self) -> io::Result<()> {
+        self.send_hex_header(Header::with_flags(ZRINIT, CANFDX | CANOVIO | CANFC32)).await
+    }
+
+    async fn receive(&mut self, dir: &Path) -> io::Result<()> {
+        let mut file: Option<File> = None;
+        let mut offset: u64 = 0;
+        let mut size: u64 = 0;
+
+        self.send_zrinit().await?;
+        loop {
+            self.session.check_cancel().await?;
+            let header = match self.read_header(Duration::from_secs(10)).await? {
+                Some(header) => header,
+                None => {
+                    self.session.retry()?;
+                    if file.is_some() {
+                        self.send_hex_header(Header::with_position(ZRPOS, offset)).await?;
+                    } else {
+                        self.send_zrinit().await?;
+                    }
+                    continue;
+                }
+            };
+
+            match header.frame_type {
+                ZRQINIT => self.send_zrinit().await?,
+                ZSINIT => {
+                    let _ = self.read_subpacket().await?;
+                    self.send_hex_header(Header::with_position(ZACK, 0)).await?;
+                }
+                ZFILE => {
+                    let info = match self.read_subpacket().await? {
+                        Some((info, _)) => info,
+                        None => {
+                            self.send_hex_header(Header::with_position(ZNAK, 0)).await?;
+                            continue;
+                        }
+                    };
+                    let (name, file_size) = parse_header(&info);
+                    if name.is_empty() {
+                        self.send_hex_header(Header::with_position(ZSKIP, 0)).await?;
+                        continue;
+                    }
+
+                    let path = dir.join(&name);
+                    let existing = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                    size = file_size.unwrap_or(0) as u64;
+                    let resume = self.resume && header.zf0() == ZCRESUM && existing > 0;
+                    if resume && existing == size {
+                        // already complete from an earlier attempt
+                        self.send_hex_header(Header::with_position(ZSKIP, 0)).await?;
+                        continue;
+                    }
+
+                    offset = if resume && existing < size { existing } else { 0 };
+                    file = Some(if offset > 0 {
+                        OpenOptions::new().append(true).open(&path)?
+                    } else {
+                        File::create(&path)?
+                    });
+                    self.session.report("transfer_file", name);
+                    self.session.report("transfer_progress", format!("{}/{}", offset, size));
+                    self.send_hex_header(Header::with_position(ZRPOS, offset)).await?;
+                }
+                ZDATA => {
+                    if file.is_none() {
+                        self.send_zrinit().await?;
+                        continue;
+                    }
+                    if header.position() != offset {
+                        self.send_hex_header(Header::with_position(ZRPOS, offset)).await?;
+                        continue;
+                    }
+                    loop {
+                        let (data, end) = match self.read_subpacket().await? {
+                            Some(packet) => packet,
+                            None => {
+                                self.session.retry()?;
+                                self.send_hex_header(Header::with_position(ZRPOS, offset)).await?;
+                                break;
+                            }
+                        };
+                        if let Some(f) = file.as_mut() {
+                            f.write_all(&data)?;
+                        }
+                        offset += data.len() as u64;
+                        self.session.reset_retries();
+                        self.session.report("transfer_progress", format!("{}/{}", offset, size));
+
+                        match end {
+                            ZCRCW => {
+                                self.send_hex_header(Header::with_position(ZACK, offset)).await?;
+                                break;
+                            }
+                            ZCRCQ => self.send_hex_header(Header::with_position(ZACK, offset)).await?,
+                            ZCRCE => break,
+                            _ => {}
+                        }
+                    }
+                }
+                ZEOF => {
+                    if header.position() == offset {
+                        file = None;
+                        self.send_zrinit().await?;
+                    } else if file.is_some() {
+                        // data went missing, ask for it again
+                        self.send_hex_header(Header::with_position(ZRPOS, offset)).await?;
+                    }
+                }
+                ZFIN => {
+                    self.send_hex_header(Header::with_position(ZFIN, 0)).await?;
+                    // "OO" (over and out), sent by the other side if it cares
+                    let _ = self.session.read_byte(Duration::from_secs(1)).await?;
+                    let _ = self.session.read_byte(Duration::from_secs(1)).await?;
+                    return Ok(());
+                }
+                ZCAN | ZABORT | ZFERR => return Err(aborted()),
+                _ => {}
+            }
+        }
+    }
+
+    //
+    // sender
+    //
+
+    async fn send(&mut self, files: &[PathBuf]) -> io::Result<()> {
+        // wake up the remote `rz`, if any
+        self.session.write(b"rz\r").await?;
+        self.send_hex_header(Header::with_position(ZRQINIT, 0)).await?;
+        self.wait_for_zrinit().await?;
+
+        for path in files {
+            let mut file = File::open(path)?;
+            let size = file.metadata()?.len();
+            let name = file_name_of(path);
+            self.session.report("transfer_file", name.clone());
+            self.session.reset_retries();
+
+            let info = format!("{}\0{}\0", name, size);
+            let conversion = if self.resume { ZCRESUM } else { 0 };
+            let offset = loop {
+                self.session.check_cancel().await?;
+                self.send_bin_header(Header::with_flags(ZFILE, conversion)).await?;
+                self.send_subpacket(info.as_bytes(), ZCRCW).await?;
+                let mut reply = self.read_header(Duration::from_secs(10)).await?;
+                // the remote also answered our ZRQINIT, that ZRINIT may still be ahead of the reply
+                if matches!(&reply, Some(h) if h.frame_type == ZRINIT) {
+                    reply = self.read_header(Duration::from_secs(10)).await?;
+                }
+                match reply {
+                    Some(h) if h.frame_type == ZRPOS => break Some(h.position()),
+                    Some(h) if h.frame_type == ZSKIP => break None,
+                    Some(h) if h.frame_type == ZCRC => {
+                        let crc = crc32(&fs::read(path)?);
+                        self.send_hex_header(Header { frame_type: ZCRC, data: crc.to_le_bytes() }).await?;
+                    }
+                    Some(h) if h.frame_type == ZCAN || h.frame_type == ZABORT => return Err(aborted()),
+                    _ => self.session.retry()?,
+                }
+            };
+
+            if let Some(offset) = offset {
+                self.send_file_data(&mut file, offset, size).await?;
+            }
+        }
+
+        loop {
+            self.session.check_cancel().await?;
+            self.send_hex_header(Header::with_position(ZFIN, 0)).await?;
+            match self.read_header(Duration::from_secs(10)).await? {
+                Some(h) if h.frame_type == ZFIN => break,
+                _ => self.session.retry()?,
+            }
+        }
+        self.session.write(b"OO").await
+    }
+
+    async fn wait_for_zrinit(&mut self) -> io::Result<()> {
+        loop {
+            self.session.check_cancel().await?;
+            match self.read_header(Duration::from_secs(5)).await? {
+                Some(h) if h.frame_type == ZRINIT => return Ok(()),
+                Some(h) if h.frame_type == ZCHALLENGE => {
+                    self.send_hex_header(Header { frame_type: ZACK, data: h.data }).await?;
+                }
+                Some(h) if h.frame_type == ZCAN || h.frame_type == ZABORT => return Err(aborted()),
+                _ => {
+                    self.session.retry()?;
+                    self.send_hex_header(Header::with_position(ZRQINIT, 0)).await?;
+                }
+            }
+        }
+    }
+
+    async fn send_file_data(&mut self, file: &mut File, mut offset: u64, size: u64) -> io::Result<()> {
+        loop {
+            self.session.check_cancel().await?;
+            file.seek(SeekFrom::Start(offset))?;
+            self.send_bin_header(Header::with_position(ZDATA, offset)).await?;
+
+            // stream a window of subpackets, the last one asks for a ZACK
+            let mut at_eof = false;
+            for i in 0..WINDOW_PACKETS {
+                let mut chunk = Vec::with_capacity(SUBPACKET_SIZE);
+                (&mut *file).take(SUBPACKET_SIZE as u64).read_to_end(&mut chunk)?;
+                at_eof = chunk.len() < SUBPACKET_SIZE || offset + chunk.len() as u64 >= size;
+                let end = if at_eof || i == WINDOW_PACKETS - 1 { ZCRCW } else { ZCRCG };
+                self.send_subpacket(&chunk, end).await?;
+                offset += chunk.len() as u64;
+                self.session.report("transfer_progress", format!("{}/{}", offset, size));
+                if end == ZCRCW {
+                    break;
+                }
+            }
+
+            match self.read_header(Duration::from_secs(10)).await? {
+                Some(h) if h.frame_type == ZACK => self.session.reset_retries(),
+                Some(h) if h.frame_type == ZRPOS => {
+                    // the receiver lost data, go back to where it wants to resume
+                    self.session.retry()?;
+                    offset = h.position();
+                    continue;
+                }
+                Some(h) if h.frame_type == ZSKIP => return Ok(()),
+                Some(h) if h.frame_type == ZCAN || h.frame_type == ZABORT => return Err(aborted()),
+                _ => {
+                    self.session.retry()?;
+                    continue;
+                }
+            }
+
+            if !at_eof {
+                continue;
+            }
+
+            loop {
+                self.session.check_cancel().await?;
+                self.send_hex_header(Header::with_position(ZEOF, offset)).await?;
+                match self.read_header(Duration::from_secs(10)).await? {
+                    Some(h) if h.frame_type == ZRINIT => return Ok(()),
+                    Some(h) if h.frame_type == ZRPOS => {
+                        self.session.retry()?;
+                        offset = h.position();
+                        break;
+                    }
+                    Some(h) if h.frame_type == ZCAN || h.frame_type == ZABORT => return Err(aborted()),
+                    _ => self.session.retry()?,
+                }
+            }
+        }
+    }
+}
+
+fn escape_into(frame: &mut Vec<u8>, data: &[u8]) {
+    for b in data {
+        match *b {
+            ZDLE | 0x10 | 0x90 | XON | 0x91 | XOFF | 0x93 => {
+                frame.push(ZDLE);
+                frame.push(*b ^ 0x40);
+            }
+            _ => frame.push(*b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xmodem::tests::{data, send_and_receive, test_dir};
+    use crate::xmodem::Protocol;
+
+    // source and target directories with `files` in the source
+    fn batch(name: &str, files: &[(&str, &[u8])]) -> (Vec<PathBuf>, PathBuf) {
+        let dir = test_dir(name);
+        let (source, target) = (dir.join("source"), dir.join("target"));
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        let paths = files.iter()
+            .map(|(name, data)| {
+                fs::write(source.join(name), data).unwrap();
+                source.join(name)
+            })
+            .collect();
+        (paths, target)
+    }
+
+    #[tokio::test]
+    async fn batch_round_trip() {
+        // every byte value, so the escaped ones go through too
+        let escaped: Vec<u8> = (0..=255).cycle().take(3000).collect();
+        let large = data(SUBPACKET_SIZE * 5 + 100);
+        let (paths, target) = batch("zmodem", &[("fw(1).bin", &escaped), ("large.bin", &large)]);
+        // without resume an existing file is replaced
+        fs::write(target.join("large.bin"), [0xff; 100]).unwrap();
+
+        send_and_receive(Protocol::Zmodem, paths, target.clone(), false).await;
+        assert_eq!(fs::read(target.join("fw(1).bin")).unwrap(), escaped);
+        assert_eq!(fs::read(target.join("large.bin")).unwrap(), large);
+    }
+
+    #[tokio::test]
+    async fn resume_keeps_existing_data() {
+        let partial = data(SUBPACKET_SIZE * 3);
+        let complete = data(500);
+        let (paths, target) = batch("zmodem-resume", &[("partial.bin", &partial), ("complete.bin", &complete)]);
+        // bytes the sender never has, so it shows what was kept
+        fs::write(target.join("partial.bin"), [0xff; 1500]).unwrap();
+        fs::write(target.join("complete.bin"), [0xff; 500]).unwrap();
+
+        send_and_receive(Protocol::Zmodem, paths, target.clone(), true).await;
+        assert_eq!(fs::read(target.join("partial.bin")).unwrap(), [&[0xff; 1500][..], &partial[1500..]].concat());
+        assert_eq!(fs::read(target.join("complete.bin")).unwrap(), [0xff; 500]);
+    }
+}